@@ -0,0 +1,89 @@
+use std::fmt::Display;
+
+pub use aleo_pool_types::{AddressStats, PoolEvent, PoolStats, RejectionStats, StatsUpdate, SubmitLatency};
+use anyhow::Result;
+use futures_util::{stream, Stream, StreamExt};
+use reqwest::{header::ACCEPT, Client};
+
+/// Typed client for the pool server's HTTP API.
+#[derive(Clone)]
+pub struct PoolClient {
+    base_url: String,
+    client: Client,
+}
+
+impl PoolClient {
+    pub fn new(base_url: &str) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    pub async fn get_stats(&self) -> Result<PoolStats> {
+        self.get_json("stats").await
+    }
+
+    /// Accepts anything that displays as an Aleo address, such as `Address<Testnet3>`,
+    /// so this crate does not need to depend on snarkvm.
+    pub async fn get_address_stats(&self, address: impl Display) -> Result<AddressStats> {
+        self.get_json(&format!("stats/{}", address)).await
+    }
+
+    /// Prover connection events as they happen. Keep-alive and malformed events are skipped;
+    /// the stream ends when the server closes the connection.
+    pub async fn subscribe_events(&self) -> Result<impl Stream<Item = PoolEvent>> {
+        let response = self
+            .client
+            .get(format!("{}/events/connections", self.base_url))
+            .header(ACCEPT, "text/event-stream")
+            .send()
+            .await?
+            .error_for_status()?;
+        let body = Box::pin(response.bytes_stream());
+        Ok(stream::unfold(
+            (body, Vec::new()),
+            |(mut body, mut buffer)| async move {
+                loop {
+                    if let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                        let block = buffer.drain(..end + 2).collect::<Vec<u8>>();
+                        if let Some(event) = parse_event(&block) {
+                            return Some((event, (body, buffer)));
+                        }
+                        continue;
+                    }
+                    match body.next().await {
+                        Some(Ok(chunk)) => buffer.extend_from_slice(&chunk),
+                        _ => return None,
+                    }
+                }
+            },
+        ))
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        Ok(self
+            .client
+            .get(format!("{}/{}", self.base_url, path))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+/// Extracts the JSON payload of a single server-sent event.
+fn parse_event(block: &[u8]) -> Option<PoolEvent> {
+    let block = std::str::from_utf8(block).ok()?;
+    let data = block
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.trim_start())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if data.is_empty() {
+        return None;
+    }
+    serde_json::from_str(&data).ok()
+}
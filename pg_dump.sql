@@ -38,13 +38,21 @@ solution_id = solution[0]["id"]
 shares = plpy.execute(f"SELECT * FROM share WHERE solution_id = {solution_id}")
 if shares.nrows() == 0:
   plpy.fatal("No share data for solution")
+raw_reward = solution[0]["reward"]
+total_shares = sum(share["share"] for share in shares)
+# Shares of a tenant's provers pay the tenant's fee percent to its fee address, the others the pool fee.
 data = {}
+tenant_fees = {}
+pool_fee = 0
 for share in shares:
-  data[share["address"]] = share["share"]
-raw_reward = solution[0]["reward"]
-reward = int(raw_reward * 0.995)
-total_shares = sum(data.values())
-reward_per_share = reward // total_shares
+  earned = raw_reward * share["share"] // total_shares
+  if share["fee_percent"] is None:
+    amount = int(earned * 0.995)
+    pool_fee += earned - amount
+  else:
+    amount = int(earned * (100 - share["fee_percent"]) / 100)
+    tenant_fees[share["fee_address"]] = tenant_fees.get(share["fee_address"], 0) + earned - amount
+  data[share["address"]] = data.get(share["address"], 0) + amount
 def get_plan(name, stmt, types):
   if name in SD:
     return SD[name]
@@ -58,15 +66,19 @@ solution_plan = get_plan("block_plan", "UPDATE solution SET paid = true WHERE id
 try:
   with plpy.subtransaction():
     paid = 0
-    for miner, share in data.items():
-      amount = reward_per_share * share
+    for miner, amount in data.items():
       payout_plan.execute([solution_id, miner, amount])
       balance_plan.execute([miner, amount])
       solution_plan.execute([solution_id])
       paid += amount
+    for fee_address, amount in tenant_fees.items():
+      payout_plan.execute([solution_id, fee_address, amount])
+      balance_plan.execute([fee_address, amount])
+    tenant_fee = sum(tenant_fees.values())
     stats_plan.execute(["total_paid", paid])
-    stats_plan.execute(["total_fee", raw_reward - reward])
-    stats_plan.execute(["total_rounding", reward - paid])
+    stats_plan.execute(["total_fee", pool_fee])
+    stats_plan.execute(["total_tenant_fee", tenant_fee])
+    stats_plan.execute(["total_rounding", raw_reward - paid - pool_fee - tenant_fee])
 	
 except plpy.SPIError as e:
   plpy.fatal(f"Error while updating database: {e.args}")
@@ -122,7 +134,8 @@ CREATE TABLE pool.solution (
     paid boolean DEFAULT false NOT NULL,
     valid boolean DEFAULT false NOT NULL,
     commitment text NOT NULL,
-    checked integer DEFAULT 0 NOT NULL
+    checked integer DEFAULT 0 NOT NULL,
+    fee_address text
 );
 
 
@@ -187,7 +200,9 @@ CREATE TABLE pool.share (
     id integer NOT NULL,
     solution_id integer NOT NULL,
     address text NOT NULL,
-    share bigint NOT NULL
+    share bigint NOT NULL,
+    fee_address text,
+    fee_percent double precision
 );
 
 
@@ -211,6 +226,72 @@ CREATE SEQUENCE pool.share_id_seq
 ALTER SEQUENCE pool.share_id_seq OWNED BY pool.share.id;
 
 
+--
+-- Name: round_histogram; Type: TABLE; Schema: pool; Owner: -
+--
+
+CREATE TABLE pool.round_histogram (
+    id integer NOT NULL,
+    height bigint NOT NULL,
+    histogram text NOT NULL,
+    "time" timestamp without time zone DEFAULT now() NOT NULL
+);
+
+
+--
+-- Name: round_histogram_id_seq; Type: SEQUENCE; Schema: pool; Owner: -
+--
+
+CREATE SEQUENCE pool.round_histogram_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+
+--
+-- Name: round_histogram_id_seq; Type: SEQUENCE OWNED BY; Schema: pool; Owner: -
+--
+
+ALTER SEQUENCE pool.round_histogram_id_seq OWNED BY pool.round_histogram.id;
+
+
+--
+-- Name: share_extra_data; Type: TABLE; Schema: pool; Owner: -
+--
+
+CREATE TABLE pool.share_extra_data (
+    id integer NOT NULL,
+    commitment text NOT NULL,
+    address text NOT NULL,
+    value bigint NOT NULL,
+    extra_data bytea NOT NULL,
+    "time" timestamp without time zone DEFAULT now() NOT NULL
+);
+
+
+--
+-- Name: share_extra_data_id_seq; Type: SEQUENCE; Schema: pool; Owner: -
+--
+
+CREATE SEQUENCE pool.share_extra_data_id_seq
+    AS integer
+    START WITH 1
+    INCREMENT BY 1
+    NO MINVALUE
+    NO MAXVALUE
+    CACHE 1;
+
+
+--
+-- Name: share_extra_data_id_seq; Type: SEQUENCE OWNED BY; Schema: pool; Owner: -
+--
+
+ALTER SEQUENCE pool.share_extra_data_id_seq OWNED BY pool.share_extra_data.id;
+
+
 --
 -- Name: stats; Type: TABLE; Schema: pool; Owner: -
 --
@@ -242,6 +323,20 @@ ALTER TABLE ONLY pool.payout ALTER COLUMN id SET DEFAULT nextval('pool.payout_id
 ALTER TABLE ONLY pool.share ALTER COLUMN id SET DEFAULT nextval('pool.share_id_seq'::regclass);
 
 
+--
+-- Name: round_histogram id; Type: DEFAULT; Schema: pool; Owner: -
+--
+
+ALTER TABLE ONLY pool.round_histogram ALTER COLUMN id SET DEFAULT nextval('pool.round_histogram_id_seq'::regclass);
+
+
+--
+-- Name: share_extra_data id; Type: DEFAULT; Schema: pool; Owner: -
+--
+
+ALTER TABLE ONLY pool.share_extra_data ALTER COLUMN id SET DEFAULT nextval('pool.share_extra_data_id_seq'::regclass);
+
+
 --
 -- Name: solution id; Type: DEFAULT; Schema: pool; Owner: -
 --
@@ -273,6 +368,22 @@ ALTER TABLE ONLY pool.share
     ADD CONSTRAINT share_pk PRIMARY KEY (id);
 
 
+--
+-- Name: round_histogram round_histogram_pk; Type: CONSTRAINT; Schema: pool; Owner: -
+--
+
+ALTER TABLE ONLY pool.round_histogram
+    ADD CONSTRAINT round_histogram_pk PRIMARY KEY (id);
+
+
+--
+-- Name: share_extra_data share_extra_data_pk; Type: CONSTRAINT; Schema: pool; Owner: -
+--
+
+ALTER TABLE ONLY pool.share_extra_data
+    ADD CONSTRAINT share_extra_data_pk PRIMARY KEY (id);
+
+
 --
 -- Name: solution solution_pk; Type: CONSTRAINT; Schema: pool; Owner: -
 --
@@ -296,6 +407,13 @@ ALTER TABLE ONLY pool.stats
 CREATE UNIQUE INDEX balance_address_uindex ON pool.balance USING btree (address);
 
 
+--
+-- Name: share_extra_data_commitment_uindex; Type: INDEX; Schema: pool; Owner: -
+--
+
+CREATE UNIQUE INDEX share_extra_data_commitment_uindex ON pool.share_extra_data USING btree (commitment);
+
+
 --
 -- Name: payout_address_index; Type: INDEX; Schema: pool; Owner: -
 --
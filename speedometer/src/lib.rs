@@ -1,17 +1,26 @@
 use std::{
     collections::VecDeque,
+    sync::atomic::{AtomicU64, Ordering},
     time::{Duration, Instant},
 };
 
 use tokio::sync::RwLock;
 
+/// Marks a cache that has never been filled.
+const CACHE_EMPTY: u64 = u64::MAX;
+
 pub struct Speedometer {
     storage: RwLock<VecDeque<(Instant, u64)>>,
     interval: Duration,
+    /// Start of the recorded events after the window was made longer, until they cover the whole window
+    covered_since: Option<Instant>,
     cached: bool,
     cache_interval: Option<Duration>,
-    cache_instant: Option<Instant>,
-    cache_value: f64,
+    created: Instant,
+    /// Nanoseconds since `created` when the cache was last updated
+    cache_instant: AtomicU64,
+    /// Bits of the cached `f64` speed
+    cache_value: AtomicU64,
 }
 
 impl Speedometer {
@@ -19,10 +28,12 @@ impl Speedometer {
         Self {
             storage: RwLock::new(VecDeque::new()),
             interval,
+            covered_since: None,
             cached: false,
             cache_interval: None,
-            cache_instant: None,
-            cache_value: 0.0,
+            created: Instant::now(),
+            cache_instant: AtomicU64::new(CACHE_EMPTY),
+            cache_value: AtomicU64::new(0f64.to_bits()),
         }
     }
 
@@ -30,13 +41,25 @@ impl Speedometer {
         Self {
             storage: RwLock::new(VecDeque::new()),
             interval,
+            covered_since: None,
             cached: true,
             cache_interval: Some(cache_interval),
-            cache_instant: Some(Instant::now() - cache_interval),
-            cache_value: 0.0,
+            created: Instant::now(),
+            cache_instant: AtomicU64::new(CACHE_EMPTY),
+            cache_value: AtomicU64::new(0f64.to_bits()),
         }
     }
 
+    fn elapsed_nanos(&self) -> u64 {
+        self.created.elapsed().as_nanos() as u64
+    }
+
+    /// Part of the window the recorded events cover.
+    fn covered(&self) -> Duration {
+        self.covered_since
+            .map_or(self.interval, |since| since.elapsed().min(self.interval))
+    }
+
     pub async fn event(&self, value: u64) {
         let mut storage = self.storage.write().await;
         storage.push_back((Instant::now(), value));
@@ -45,9 +68,14 @@ impl Speedometer {
         }
     }
 
-    pub async fn speed(&mut self) -> f64 {
-        if self.cached && self.cache_instant.unwrap().elapsed() < self.cache_interval.unwrap() {
-            return self.cache_value;
+    pub async fn speed(&self) -> f64 {
+        if self.cached {
+            let cache_instant = self.cache_instant.load(Ordering::SeqCst);
+            if cache_instant != CACHE_EMPTY
+                && self.elapsed_nanos().saturating_sub(cache_instant) < self.cache_interval.unwrap().as_nanos() as u64
+            {
+                return f64::from_bits(self.cache_value.load(Ordering::SeqCst));
+            }
         }
         let mut storage = self.storage.write().await;
         while storage.front().map_or(false, |t| t.0.elapsed() > self.interval) {
@@ -55,14 +83,22 @@ impl Speedometer {
         }
         drop(storage);
         let events = self.storage.read().await.iter().fold(0, |acc, t| acc + t.1);
-        let speed = events as f64 / self.interval.as_secs_f64();
+        let speed = events as f64 / self.covered().as_secs_f64();
         if self.cached {
-            self.cache_instant = Some(Instant::now());
-            self.cache_value = speed;
+            self.cache_value.store(speed.to_bits(), Ordering::SeqCst);
+            self.cache_instant.store(self.elapsed_nanos(), Ordering::SeqCst);
         }
         speed
     }
 
+    /// Changes the window while keeping the recorded events. Events older than the previous window were
+    /// already dropped, so until a longer window fills up the speed is measured over the part the events cover.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.covered_since = Instant::now().checked_sub(self.covered());
+        self.interval = interval;
+        self.cache_instant.store(CACHE_EMPTY, Ordering::SeqCst);
+    }
+
     #[allow(dead_code)]
     pub async fn reset(&self) {
         self.storage.write().await.clear();
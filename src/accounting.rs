@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, VecDeque},
     fs::create_dir_all,
     path::PathBuf,
     sync::{atomic::AtomicBool, Arc},
@@ -17,18 +17,28 @@ use serde_json::{json, Value};
 use snarkvm::prelude::{PuzzleCommitment, Testnet3};
 use tokio::{
     sync::{
-        mpsc::{channel, Sender},
+        mpsc::{channel, error::SendError, Sender},
+        Mutex as TokioMutex,
         RwLock as TokioRwLock,
     },
     task,
-    time::sleep,
+    time::{sleep, timeout},
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 #[cfg(feature = "db")]
 use crate::db::DB;
 use crate::{
-    accounting::AccountingMessage::{NewShare, NewSolution},
+    accounting::AccountingMessage::{
+        CoinbaseTargetChanged,
+        FeeWallet,
+        NewShare,
+        NewShares,
+        NewSolution,
+        RoundHistogram,
+    },
+    config::TenantConfig,
+    server::ShareRatioBucket,
     AccountingMessage::{Exit, SetN},
 };
 
@@ -36,15 +46,21 @@ trait PayoutModel {
     fn add_share(&mut self, share: Share);
 }
 
+/// Version of the saved PPLNS state, raised whenever a field is added to it.
+static PPLNS_STATE_VERSION: u32 = 1;
+
 #[derive(Clone, Savefile)]
 struct Share {
     value: u64,
     owner: String,
+    /// Tenant the prover belonged to when the share was found
+    #[savefile_versions = "1.."]
+    tenant: Option<String>,
 }
 
 impl Share {
-    pub fn init(value: u64, owner: String) -> Self {
-        Share { value, owner }
+    pub fn init(value: u64, owner: String, tenant: Option<String>) -> Self {
+        Share { value, owner, tenant }
     }
 }
 
@@ -71,7 +87,7 @@ impl PPLNS {
                 n: Default::default(),
             };
         }
-        load_file::<PPLNS, PathBuf>(db_path, 0).unwrap()
+        load_file::<PPLNS, PathBuf>(db_path, PPLNS_STATE_VERSION).unwrap()
     }
 
     pub fn save(&self) -> std::result::Result<(), Error> {
@@ -80,7 +96,7 @@ impl PPLNS {
             panic!("No home directory found");
         }
         let db_path = home.unwrap().join(".aleo_pool_testnet3_2/state");
-        save_file(db_path, 0, self).map_err(|e| anyhow!("Failed to save PPLNS state: {}", e))
+        save_file(db_path, PPLNS_STATE_VERSION, self).map_err(|e| anyhow!("Failed to save PPLNS state: {}", e))
     }
 
     pub fn set_n(&mut self, n: u64) {
@@ -117,13 +133,159 @@ impl PayoutModel for PPLNS {
 #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Hash)]
 struct Null {}
 
+/// A share as `(address, value, extra_data, tenant)`, with the commitment of the share and the extra data the
+/// prover attached to it, and the tenant the prover belongs to
+pub type ShareRecord = (String, u64, Option<(String, Vec<u8>)>, Option<String>);
+
+/// Messages other than `Exit` carry a sequence number from the server so they can be processed in the order
+/// they were produced, even when sent from different tasks.
 pub enum AccountingMessage {
-    NewShare(String, u64),
-    SetN(u64),
-    NewSolution(PuzzleCommitment<Testnet3>),
+    /// Address, value, the commitment of the share with the extra data the prover attached to it, and the
+    /// prover's tenant
+    NewShare(u64, String, u64, Option<(String, Vec<u8>)>, Option<String>),
+    /// Shares buffered by the server to reduce channel traffic
+    NewShares(u64, Vec<ShareRecord>),
+    SetN(u64, u64),
+    /// Commitment of the solution, the configured pool fee address and the address of the first share of the round
+    NewSolution(u64, PuzzleCommitment<Testnet3>, Option<String>, Option<String>),
+    /// Previous and new coinbase target, and the block height where the change was seen
+    CoinbaseTargetChanged(u64, u64, u64, u32),
+    /// Wallet collecting the pool fee from now on
+    FeeWallet(u64, String),
+    /// Share ratio histogram of the round that found a solution, by the height of the block that started the round
+    RoundHistogram(u64, u32, Vec<ShareRatioBucket>),
     Exit,
 }
 
+impl AccountingMessage {
+    fn sequence(&self) -> Option<u64> {
+        match self {
+            NewShare(sequence, ..)
+            | NewShares(sequence, ..)
+            | SetN(sequence, ..)
+            | NewSolution(sequence, ..)
+            | CoinbaseTargetChanged(sequence, ..)
+            | FeeWallet(sequence, ..)
+            | RoundHistogram(sequence, ..) => Some(*sequence),
+            Exit => None,
+        }
+    }
+}
+
+/// Size of the channel feeding the accounting loop.
+pub static ACCOUNTING_CHANNEL_CAPACITY: usize = 1024;
+
+/// Out-of-order messages held back before giving up on a missing sequence number.
+static MAX_PENDING_MESSAGES: usize = 1024;
+
+/// Time to wait for a missing sequence number while later messages are held back.
+static SEQUENCE_GAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sends sequenced messages to the accounting loop. A sequence number is taken and its message sent under one lock,
+/// and only used up once the message is in the channel, so failed or cancelled sends leave no gap.
+#[derive(Debug)]
+pub struct AccountingSender {
+    sender: Sender<AccountingMessage>,
+    next_sequence: TokioMutex<u64>,
+}
+
+impl AccountingSender {
+    pub fn new(sender: Sender<AccountingMessage>) -> Self {
+        Self {
+            sender,
+            next_sequence: TokioMutex::new(0),
+        }
+    }
+
+    pub async fn send(
+        &self,
+        message: impl FnOnce(u64) -> AccountingMessage,
+    ) -> Result<(), SendError<AccountingMessage>> {
+        self.send_if(|sequence| Some(message(sequence))).await
+    }
+
+    /// Like `send`, for messages built under the lock that may turn out to have nothing to send.
+    pub async fn send_if(
+        &self,
+        message: impl FnOnce(u64) -> Option<AccountingMessage>,
+    ) -> Result<(), SendError<AccountingMessage>> {
+        let mut next_sequence = self.next_sequence.lock().await;
+        if let Some(message) = message(*next_sequence) {
+            self.sender.send(message).await?;
+            *next_sequence += 1;
+        }
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.sender.capacity()
+    }
+}
+
+/// Puts sequenced messages back in the order they were produced.
+struct SequenceReorder<T> {
+    next_sequence: u64,
+    pending: BTreeMap<u64, T>,
+}
+
+impl<T> SequenceReorder<T> {
+    fn new() -> Self {
+        Self {
+            next_sequence: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    fn is_waiting(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Messages that can be processed after receiving `message`, in order. Late messages are returned right away.
+    fn push(&mut self, sequence: u64, message: T) -> Vec<T> {
+        if sequence < self.next_sequence {
+            warn!(
+                "Processing late accounting message {} (expected {})",
+                sequence, self.next_sequence
+            );
+            return vec![message];
+        }
+        self.pending.insert(sequence, message);
+        if self.pending.len() > MAX_PENDING_MESSAGES {
+            return self.skip_gap();
+        }
+        self.ready()
+    }
+
+    /// Gives up on the missing messages before the first one held back.
+    fn skip_gap(&mut self) -> Vec<T> {
+        if let Some(first) = self.pending.keys().next().copied() {
+            if first > self.next_sequence {
+                warn!(
+                    "Accounting messages {} to {} are missing, skipping",
+                    self.next_sequence,
+                    first - 1
+                );
+                self.next_sequence = first;
+            }
+        }
+        self.ready()
+    }
+
+    /// All messages held back, in order, regardless of gaps.
+    fn drain(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.pending).into_values().collect()
+    }
+
+    fn ready(&mut self) -> Vec<T> {
+        let mut ready = vec![];
+        while let Some(message) = self.pending.remove(&self.next_sequence) {
+            self.next_sequence += 1;
+            ready.push(message);
+        }
+        ready
+    }
+}
+
 #[cfg(feature = "db")]
 static PAY_INTERVAL: Duration = Duration::from_secs(60);
 
@@ -134,17 +296,20 @@ pub struct Accounting {
     database: Arc<DB>,
     sender: Sender<AccountingMessage>,
     round_cache: TokioRwLock<Cache<Null, (u32, HashMap<String, u64>)>>,
+    fee_wallet: Arc<RwLock<Option<String>>>,
+    /// Mining groups sharing the pool, each taking its own fee from its provers' part of a solution
+    tenants: Arc<Vec<TenantConfig>>,
     exit_lock: Arc<AtomicBool>,
 }
 
 impl Accounting {
-    pub fn init() -> Arc<Accounting> {
+    pub fn init(tenants: Vec<TenantConfig>) -> Arc<Accounting> {
         #[cfg(feature = "db")]
         let database = Arc::new(DB::init());
 
         let pplns = Arc::new(TokioRwLock::new(PPLNS::load()));
 
-        let (sender, mut receiver) = channel(1024);
+        let (sender, mut receiver) = channel(ACCOUNTING_CHANNEL_CAPACITY);
 
         let accounting = Accounting {
             pplns,
@@ -152,40 +317,75 @@ impl Accounting {
             database,
             sender,
             round_cache: TokioRwLock::new(Cache::new(Duration::from_secs(10))),
+            fee_wallet: Default::default(),
+            tenants: Arc::new(tenants),
             exit_lock: Arc::new(AtomicBool::new(false)),
         };
 
         let pplns = accounting.pplns.clone();
         #[cfg(feature = "db")]
         let database = accounting.database.clone();
+        let fee_wallet = accounting.fee_wallet.clone();
+        let tenants = accounting.tenants.clone();
         let exit_lock = accounting.exit_lock.clone();
         task::spawn(async move {
-            while let Some(request) = receiver.recv().await {
-                match request {
-                    NewShare(address, value) => {
-                        pplns.write().await.add_share(Share::init(value, address.clone()));
-                        debug!("Recorded share from {} with value {}", address, value);
-                    }
-                    SetN(n) => {
-                        pplns.write().await.set_n(n);
-                        debug!("Set N to {}", n);
-                    }
-                    NewSolution(commitment) => {
-                        let pplns = pplns.read().await.clone();
-                        let (_, address_shares) = Accounting::pplns_to_provers_shares(&pplns);
-
-                        #[cfg(feature = "db")]
-                        if let Err(e) = database.save_solution(commitment, address_shares).await {
-                            error!("Failed to save block reward : {}", e);
-                        } else {
-                            info!("Recorded solution {}", commitment);
+            let mut reorder = SequenceReorder::new();
+            loop {
+                let request = if reorder.is_waiting() {
+                    match timeout(SEQUENCE_GAP_TIMEOUT, receiver.recv()).await {
+                        Ok(request) => request,
+                        Err(_) => {
+                            for request in reorder.skip_gap() {
+                                Accounting::process_message(
+                                    &pplns,
+                                    #[cfg(feature = "db")]
+                                    &database,
+                                    &fee_wallet,
+                                    &tenants,
+                                    request,
+                                )
+                                .await;
+                            }
+                            continue;
                         }
                     }
-                    Exit => {
+                } else {
+                    receiver.recv().await
+                };
+                let request = match request {
+                    Some(request) => request,
+                    None => break,
+                };
+                let sequence = match request.sequence() {
+                    Some(sequence) => sequence,
+                    None => {
+                        for request in reorder.drain() {
+                            Accounting::process_message(
+                                &pplns,
+                                #[cfg(feature = "db")]
+                                &database,
+                                &fee_wallet,
+                                &tenants,
+                                request,
+                            )
+                            .await;
+                        }
                         receiver.close();
                         let _ = pplns.read().await.save();
                         exit_lock.store(true, std::sync::atomic::Ordering::SeqCst);
+                        continue;
                     }
+                };
+                for request in reorder.push(sequence, request) {
+                    Accounting::process_message(
+                        &pplns,
+                        #[cfg(feature = "db")]
+                        &database,
+                        &fee_wallet,
+                        &tenants,
+                        request,
+                    )
+                    .await;
                 }
             }
         });
@@ -210,6 +410,131 @@ impl Accounting {
         res
     }
 
+    /// Adds the shares to PPLNS and saves the extra data attached to any of them in one query.
+    async fn record_shares(pplns: &TokioRwLock<PPLNS>, #[cfg(feature = "db")] database: &DB, shares: Vec<ShareRecord>) {
+        #[cfg(feature = "db")]
+        let extra_data_shares = shares
+            .iter()
+            .filter_map(|(address, value, extra_data, _)| {
+                extra_data
+                    .clone()
+                    .map(|(commitment, extra_data)| (commitment, address.clone(), *value, extra_data))
+            })
+            .collect::<Vec<_>>();
+        {
+            let mut pplns = pplns.write().await;
+            for (address, value, _, tenant) in shares {
+                pplns.add_share(Share::init(value, address, tenant));
+            }
+        }
+        #[cfg(feature = "db")]
+        if !extra_data_shares.is_empty() {
+            if let Err(e) = database.save_share_extra_data(extra_data_shares).await {
+                error!("Failed to save share extra data: {}", e);
+            }
+        }
+    }
+
+    async fn process_message(
+        pplns: &TokioRwLock<PPLNS>,
+        #[cfg(feature = "db")] database: &DB,
+        fee_wallet: &RwLock<Option<String>>,
+        tenants: &[TenantConfig],
+        request: AccountingMessage,
+    ) {
+        match request {
+            NewShare(_, address, value, extra_data, tenant) => {
+                debug!("Recording share from {} with value {}", address, value);
+                Accounting::record_shares(
+                    pplns,
+                    #[cfg(feature = "db")]
+                    database,
+                    vec![(address, value, extra_data, tenant)],
+                )
+                .await;
+            }
+            NewShares(_, shares) => {
+                debug!("Recording {} shares", shares.len());
+                Accounting::record_shares(
+                    pplns,
+                    #[cfg(feature = "db")]
+                    database,
+                    shares,
+                )
+                .await;
+            }
+            SetN(_, n) => {
+                pplns.write().await.set_n(n);
+                debug!("Set N to {}", n);
+            }
+            NewSolution(_, commitment, pool_fee_address, round_first_share) => {
+                let pplns = pplns.read().await.clone();
+                let (_, address_shares) = Accounting::pplns_to_provers_shares(&pplns);
+                // Fall back to the active fee wallet, then to whoever found the first share of the round.
+                let fee_address = pool_fee_address
+                    .or_else(|| fee_wallet.read().clone())
+                    .or(round_first_share);
+                debug!("Pool fee of solution {} goes to {:?}", commitment, fee_address);
+                // Whoever found the solution, each tenant takes its fee from the part of the reward its provers
+                // earned with their shares of the window.
+                #[cfg(feature = "db")]
+                let tenant_fees = Accounting::tenant_fees(tenants, &pplns);
+                let total_shares = address_shares.values().sum::<u64>().max(1);
+                for (name, shares) in Accounting::tenant_shares(&pplns) {
+                    let fee_percent = tenants
+                        .iter()
+                        .find(|tenant| tenant.name == name)
+                        .map(|tenant| tenant.fee_percent);
+                    debug!(
+                        "Tenant {} holds {:.2}% of the window of solution {} (fee percent {:?})",
+                        name,
+                        shares as f64 * 100.0 / total_shares as f64,
+                        commitment,
+                        fee_percent
+                    );
+                }
+
+                #[cfg(feature = "db")]
+                if let Err(e) = database
+                    .save_solution(commitment, address_shares, fee_address, tenant_fees)
+                    .await
+                {
+                    error!("Failed to save block reward : {}", e);
+                } else {
+                    info!("Recorded solution {}", commitment);
+                }
+            }
+            CoinbaseTargetChanged(_, old_target, new_target, height) => {
+                info!(
+                    "Coinbase target changed from {} to {} at height {}, block rewards will change accordingly",
+                    old_target, new_target, height
+                );
+            }
+            FeeWallet(_, address) => {
+                info!("Pool fees now go to {}", address);
+                *fee_wallet.write() = Some(address);
+            }
+            RoundHistogram(_, height, histogram) => {
+                let histogram = match serde_json::to_value(&histogram) {
+                    Ok(histogram) => histogram,
+                    Err(e) => {
+                        error!("Failed to serialize share ratio histogram: {}", e);
+                        return;
+                    }
+                };
+                info!(
+                    "Share ratio histogram of the round from height {}: {}",
+                    height, histogram
+                );
+                #[cfg(feature = "db")]
+                if let Err(e) = database.save_round_histogram(height, &histogram.to_string()).await {
+                    error!("Failed to save share ratio histogram: {}", e);
+                }
+            }
+            Exit => unreachable!("Exit is handled by the accounting loop"),
+        }
+    }
+
     pub fn sender(&self) -> Sender<AccountingMessage> {
         self.sender.clone()
     }
@@ -236,6 +561,42 @@ impl Accounting {
         (address_shares.len() as u32, address_shares)
     }
 
+    /// Fee address and percent of the tenant recorded with the latest share of each address in the window, for
+    /// the addresses whose tenant is still configured.
+    #[cfg(feature = "db")]
+    fn tenant_fees(tenants: &[TenantConfig], pplns: &PPLNS) -> HashMap<String, (String, f64)> {
+        let mut tenant_fees = HashMap::new();
+        for share in &pplns.queue {
+            match share
+                .tenant
+                .as_ref()
+                .and_then(|name| tenants.iter().find(|tenant| &tenant.name == name))
+            {
+                Some(tenant) => {
+                    tenant_fees.insert(
+                        share.owner.clone(),
+                        (tenant.fee_address.to_string(), tenant.fee_percent),
+                    );
+                }
+                None => {
+                    tenant_fees.remove(&share.owner);
+                }
+            }
+        }
+        tenant_fees
+    }
+
+    /// Share value in the window by the tenant recorded with each share.
+    fn tenant_shares(pplns: &PPLNS) -> HashMap<String, u64> {
+        let mut tenant_shares = HashMap::new();
+        for share in &pplns.queue {
+            if let Some(tenant) = &share.tenant {
+                *tenant_shares.entry(tenant.clone()).or_default() += share.value;
+            }
+        }
+        tenant_shares
+    }
+
     pub async fn current_round(&self) -> Value {
         let pplns = self.pplns.clone().read().await.clone();
         let cache = self.round_cache.read().await.get(Null {});
@@ -252,6 +613,8 @@ impl Accounting {
             "current_n": pplns.current_n,
             "provers": provers,
             "shares": shares,
+            "fee_wallet": self.fee_wallet.read().clone(),
+            "tenant_shares": Accounting::tenant_shares(&pplns),
         })
     }
 
@@ -317,3 +680,38 @@ impl Accounting {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reorder_skips_a_dropped_sequence_number() {
+        let mut reorder = SequenceReorder::new();
+        assert_eq!(reorder.push(0, 0), vec![0]);
+        // 1 never arrives
+        assert!(reorder.push(2, 2).is_empty());
+        assert!(reorder.push(3, 3).is_empty());
+        assert!(reorder.is_waiting());
+        assert_eq!(reorder.skip_gap(), vec![2, 3]);
+        assert_eq!(reorder.push(4, 4), vec![4]);
+        assert_eq!(reorder.push(1, 1), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_send_does_not_use_up_a_sequence_number() {
+        let (sender, mut receiver) = channel(1);
+        let accounting_sender = AccountingSender::new(sender);
+        accounting_sender.send(|sequence| SetN(sequence, 1)).await.unwrap();
+        // The channel is full, so this send is cancelled before its message gets in.
+        let cancelled = timeout(
+            Duration::from_millis(10),
+            accounting_sender.send(|sequence| SetN(sequence, 2)),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        assert_eq!(receiver.recv().await.and_then(|message| message.sequence()), Some(0));
+        accounting_sender.send(|sequence| SetN(sequence, 3)).await.unwrap();
+        assert_eq!(receiver.recv().await.and_then(|message| message.sequence()), Some(1));
+    }
+}
@@ -1,59 +1,273 @@
-use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
 
-use serde_json::json;
+use aleo_pool_types::{AddressStats, PoolStats, StatsUpdate};
+use futures_util::SinkExt;
+use ipnet::IpNet;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use signal_hook::{consts::SIGTERM, low_level::raise};
 use snarkvm::{console::account::address::Address, prelude::Testnet3};
-use tokio::task;
-use tracing::info;
+use tokio::{task, time::sleep};
+use tokio_stream::{wrappers::BroadcastStream, StreamExt};
+use tracing::{error, info};
 use warp::{
     addr::remote,
+    body,
     get,
     head,
+    header,
+    http::header::CONTENT_TYPE,
     path,
+    post,
     reply,
-    reply::{json, Json},
+    reply::{json, Response},
     serve,
+    sse,
+    ws::{Message, WebSocket, Ws},
     Filter,
+    Rejection,
     Reply,
 };
 
-use crate::{Accounting, Server};
+use crate::{display::format_hashrate, Accounting, Server};
+
+/// Supported API versions with the function converting a response to that version's format.
+/// Responses are built in the latest format; older versions should map them back.
+static API_VERSIONS: &[(u32, fn(Value) -> Value)] = &[(1, api_v1)];
+static DEFAULT_API_VERSION: u32 = 1;
+
+fn api_v1(value: Value) -> Value {
+    value
+}
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    field: Option<String>,
+    interval: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SessionsQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SubnetsQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct ContributionsQuery {
+    block_height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct RestartRequest {
+    reconnect_host: String,
+    reconnect_port: u16,
+    wait_before_reconnect_seconds: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct KickRequest {
+    peer_addr: SocketAddr,
+    reason: Option<String>,
+}
 
 pub fn start(port: u16, accounting: Arc<Accounting>, server: Arc<Server>) {
     task::spawn(async move {
         let current_round = path("current_round")
+            .and(use_api_version())
             .and(use_accounting(accounting.clone()))
             .then(current_round)
             .boxed();
 
-        let pool_stats = path("stats").and(use_server(server.clone())).then(pool_stats).boxed();
+        let pool_stats = path("stats")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(pool_stats)
+            .boxed();
+
+        let pool_history = path!("stats" / "history")
+            .and(warp::query::<HistoryQuery>())
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(pool_history)
+            .boxed();
+
+        let pool_capacity = path!("stats" / "capacity")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(pool_capacity)
+            .boxed();
+
+        let difficulty_histogram = path!("stats" / "difficulty-histogram")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(difficulty_histogram)
+            .boxed();
+
+        let difficulty_history = path!("stats" / "difficulty-history")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(difficulty_history)
+            .boxed();
+
+        let pool_subnets = path!("stats" / "subnets")
+            .and(warp::query::<SubnetsQuery>())
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(pool_subnets)
+            .boxed();
+
+        let pool_contributions = path!("stats" / "contributions")
+            .and(warp::query::<ContributionsQuery>())
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(pool_contributions)
+            .boxed();
 
         let address_stats = path!("stats" / String)
+            .and(use_api_version())
             .and(use_server(server.clone()))
             .then(address_stats)
             .boxed();
 
+        let address_forecast = path!("address" / String / "forecast")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(address_forecast)
+            .boxed();
+
+        let address_workers = path!("address" / String / "workers")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(address_workers)
+            .boxed();
+
+        let address_sessions = path!("address" / String / "sessions")
+            .and(warp::query::<SessionsQuery>())
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(address_sessions)
+            .boxed();
+
+        let farm_stats = path!("farm" / String / u8 / "stats")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(farm_stats)
+            .boxed();
+
         let admin_current_round = path!("admin" / "current_round")
             .and(remote())
+            .and(header::optional::<String>("authorization"))
+            .and(use_api_version())
             .and(use_accounting(accounting.clone()))
+            .and(use_server(server.clone()))
             .then(admin_current_round)
             .boxed();
 
+        let admin_security_audit = path!("admin" / "security" / "audit")
+            .and(remote())
+            .and(header::optional::<String>("authorization"))
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(admin_security_audit)
+            .boxed();
+
+        let health = path("health")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(health)
+            .boxed();
+
+        let connection_events = path!("events" / "connections")
+            .and(use_server(server.clone()))
+            .then(connection_events)
+            .boxed();
+
+        let stats_socket = path!("ws" / "stats")
+            .and(warp::ws())
+            .and(use_server(server.clone()))
+            .then(stats_socket)
+            .boxed();
+
+        let metrics = path("metrics").and(use_server(server.clone())).then(metrics).boxed();
+
         let endpoints = current_round
+            .or(pool_history)
+            .or(pool_contributions)
+            .or(pool_capacity)
+            .or(difficulty_histogram)
+            .or(difficulty_history)
+            .or(pool_subnets)
             .or(address_stats)
             .or(pool_stats)
+            .or(address_forecast)
+            .or(address_workers)
+            .or(address_sessions)
+            .or(farm_stats)
             .or(admin_current_round)
+            .or(admin_security_audit)
+            .or(health)
+            .or(metrics)
+            .or(connection_events)
+            .or(stats_socket)
+            .boxed();
+
+        let admin_kick_prover = path!("admin" / "prover" / "kick")
+            .and(remote())
+            .and(header::optional::<String>("authorization"))
+            .and(use_api_version())
+            .and(body::json())
+            .and(use_server(server.clone()))
+            .then(admin_kick_prover)
+            .boxed();
+
+        let admin_restart = path!("admin" / "restart")
+            .and(remote())
+            .and(header::optional::<String>("authorization"))
+            .and(use_api_version())
+            .and(body::json())
+            .and(use_server(server.clone()))
+            .then(admin_restart)
+            .boxed();
+
+        let admin_rotate_token = path!("admin" / "rotate-token")
+            .and(remote())
+            .and(header::optional::<String>("authorization"))
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(admin_rotate_token)
             .boxed();
 
         let routes = get()
             .or(head())
             .unify()
             .and(endpoints)
+            .or(post().and(admin_kick_prover.or(admin_restart).or(admin_rotate_token)))
             .with(warp::log("aleo_pool_server::api"));
         info!("Starting API server on port {}", port);
         serve(routes).run(([0, 0, 0, 0], port)).await;
     });
 }
 
+/// Serves internal diagnostics on the loopback interface only, separately from the public API.
+pub fn start_debug(port: u16, server: Arc<Server>) {
+    task::spawn(async move {
+        let pipeline = path!("debug" / "pipeline")
+            .and(use_api_version())
+            .and(use_server(server.clone()))
+            .then(debug_pipeline)
+            .boxed();
+
+        let routes = get().and(pipeline).with(warp::log("aleo_pool_server::api"));
+        info!("Starting debug API server on 127.0.0.1:{}", port);
+        serve(routes).run(([127, 0, 0, 1], port)).await;
+    });
+}
+
 fn use_accounting(
     accounting: Arc<Accounting>,
 ) -> impl Filter<Extract = (Arc<Accounting>,), Error = Infallible> + Clone {
@@ -63,54 +277,492 @@ fn use_server(server: Arc<Server>) -> impl Filter<Extract = (Arc<Server>,), Erro
     warp::any().map(move || server.clone())
 }
 
-async fn pool_stats(server: Arc<Server>) -> Json {
-    json(&json!({
-        "online_addresses": server.online_addresses().await,
-        "online_provers": server.online_provers().await,
-        "speed": server.pool_speed().await,
-    }))
+/// Extracts the API version requested with `Accept: application/json; version=N`.
+fn use_api_version() -> impl Filter<Extract = (u32,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept").map(|accept: Option<String>| {
+        accept
+            .and_then(|accept| {
+                accept.split(',').find_map(|media_range| {
+                    let mut params = media_range.split(';').map(str::trim);
+                    if params.next()? != "application/json" {
+                        return None;
+                    }
+                    params.find_map(|param| param.strip_prefix("version=")?.parse::<u32>().ok())
+                })
+            })
+            .unwrap_or(DEFAULT_API_VERSION)
+    })
 }
 
-async fn address_stats(address: String, server: Arc<Server>) -> impl Reply {
-    if let Ok(address) = address.parse::<Address<Testnet3>>() {
-        let speed = server.address_speed(address).await;
-        let prover_count = server.address_prover_count(address).await;
-        Ok(reply::with_status(
+fn versioned_json(version: u32, value: Value, status: warp::http::StatusCode) -> Response {
+    match API_VERSIONS.iter().find(|(v, _)| *v == version) {
+        Some((version, serialize)) => reply::with_header(
+            reply::with_status(json(&serialize(value)), status),
+            CONTENT_TYPE,
+            format!("application/json; version={}", version),
+        )
+        .into_response(),
+        None => reply::with_status(
             json(&json!({
-                "online_provers": prover_count,
-                "speed": speed,
+                "error": "unsupported api version",
+                "supported_versions": API_VERSIONS.iter().map(|(v, _)| *v).collect::<Vec<u32>>(),
             })),
+            warp::http::StatusCode::NOT_ACCEPTABLE,
+        )
+        .into_response(),
+    }
+}
+
+async fn pool_stats(version: u32, server: Arc<Server>) -> Response {
+    let speed = server.pool_speed().await;
+    let (connection_rate_1m, connection_rate_5m) = server.connection_rate().await;
+    versioned_json(
+        version,
+        json!(PoolStats {
+            online_addresses: server.online_addresses().await,
+            online_provers: server.online_provers().await,
+            connected_provers: server.connected_provers().await,
+            max_provers: server.max_provers(),
+            speed_display: speed.iter().copied().map(format_hashrate).collect(),
+            speed,
+            stale_rate_percent: server.pool_stale_rate_percent().await,
+            submit_latency_us: server.pool_submit_latency().await,
+            rejections: server.pool_rejections().await,
+            performance_score: server.pool_performance_score().await,
+            difficulty_trend_24h: server.difficulty_trend_24h().await,
+            connection_rate_1m,
+            connection_rate_5m,
+            backpressured_provers: server.backpressured_provers().await,
+            nonce_exhaustion_estimate_days: server.nonce_exhaustion_estimate_days(),
+        }),
+        warp::http::StatusCode::OK,
+    )
+}
+
+async fn pool_history(query: HistoryQuery, version: u32, server: Arc<Server>) -> Response {
+    let samples = server
+        .history(query.interval.unwrap_or(60), query.limit.unwrap_or(1440))
+        .await;
+    let value = match query.field.as_deref() {
+        None => json!(samples),
+        Some("prover_count") => json!(
+            samples
+                .iter()
+                .map(|sample| (sample.timestamp, sample.prover_count))
+                .collect::<Vec<_>>()
+        ),
+        Some("address_count") => json!(
+            samples
+                .iter()
+                .map(|sample| (sample.timestamp, sample.address_count))
+                .collect::<Vec<_>>()
+        ),
+        Some(_) => {
+            return versioned_json(
+                version,
+                json!({
+                    "error": "unknown field"
+                }),
+                warp::http::StatusCode::BAD_REQUEST,
+            );
+        }
+    };
+    versioned_json(version, value, warp::http::StatusCode::OK)
+}
+
+async fn difficulty_histogram(version: u32, server: Arc<Server>) -> Response {
+    versioned_json(
+        version,
+        json!({
+            "buckets": server.difficulty_histogram().await,
+        }),
+        warp::http::StatusCode::OK,
+    )
+}
+
+async fn difficulty_history(version: u32, server: Arc<Server>) -> Response {
+    versioned_json(
+        version,
+        json!({
+            "blocks": server.difficulty_history().await,
+        }),
+        warp::http::StatusCode::OK,
+    )
+}
+
+async fn pool_subnets(query: SubnetsQuery, version: u32, server: Arc<Server>) -> Response {
+    let subnets = server
+        .top_subnets(query.limit.unwrap_or(20))
+        .into_iter()
+        .map(|(subnet, connections)| json!({ "subnet": subnet.to_string(), "connections": connections }))
+        .collect::<Vec<_>>();
+    versioned_json(
+        version,
+        json!({
+            "subnets": subnets,
+        }),
+        warp::http::StatusCode::OK,
+    )
+}
+
+async fn pool_capacity(version: u32, server: Arc<Server>) -> Response {
+    versioned_json(
+        version,
+        json!(server.capacity_estimate().await),
+        warp::http::StatusCode::OK,
+    )
+}
+
+async fn pool_contributions(query: ContributionsQuery, version: u32, server: Arc<Server>) -> Response {
+    match server.contributions(query.block_height) {
+        Some(contributions) => versioned_json(version, json!(contributions), warp::http::StatusCode::OK),
+        None => versioned_json(
+            version,
+            json!({
+                "error": "unknown block height"
+            }),
+            warp::http::StatusCode::NOT_FOUND,
+        ),
+    }
+}
+
+async fn address_stats(address: String, version: u32, server: Arc<Server>) -> Response {
+    if let Ok(address) = address.parse::<Address<Testnet3>>() {
+        let speed = server.address_speed(address).await;
+        versioned_json(
+            version,
+            json!(AddressStats {
+                label: server.address_label(&address),
+                online_provers: server.address_prover_count(address).await,
+                speed_display: speed.iter().copied().map(format_hashrate).collect(),
+                speed,
+                stale_rate_percent: server.address_stale_rate_percent(address).await,
+                share_quality: server.address_share_quality(address).await,
+                rejections: server.address_rejections(address).await,
+            }),
             warp::http::StatusCode::OK,
-        ))
+        )
     } else {
-        Ok(reply::with_status(
-            json(&json!({
+        versioned_json(
+            version,
+            json!({
                 "error": "invalid address"
-            })),
+            }),
             warp::http::StatusCode::BAD_REQUEST,
-        ))
+        )
     }
 }
 
-async fn current_round(accounting: Arc<Accounting>) -> Json {
-    let data = accounting.current_round().await;
+async fn address_forecast(address: String, version: u32, server: Arc<Server>) -> Response {
+    if let Ok(address) = address.parse::<Address<Testnet3>>() {
+        versioned_json(
+            version,
+            json!(server.address_earnings_forecast(address).await),
+            warp::http::StatusCode::OK,
+        )
+    } else {
+        versioned_json(
+            version,
+            json!({
+                "error": "invalid address"
+            }),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+    }
+}
 
-    json(&json! ({
-        "n": data["n"],
-        "current_n": data["current_n"],
-        "provers": data["provers"],
-    }))
+async fn address_workers(address: String, version: u32, server: Arc<Server>) -> Response {
+    if let Ok(address) = address.parse::<Address<Testnet3>>() {
+        versioned_json(
+            version,
+            json!(server.address_workers(address).await),
+            warp::http::StatusCode::OK,
+        )
+    } else {
+        versioned_json(
+            version,
+            json!({
+                "error": "invalid address"
+            }),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+    }
 }
 
-async fn admin_current_round(addr: Option<SocketAddr>, accounting: Arc<Accounting>) -> impl Reply {
-    let addr = addr.unwrap();
-    if addr.ip().is_loopback() {
-        let pplns = accounting.current_round().await;
-        Ok(reply::with_status(json(&pplns), warp::http::StatusCode::OK))
+async fn address_sessions(address: String, query: SessionsQuery, version: u32, server: Arc<Server>) -> Response {
+    if let Ok(address) = address.parse::<Address<Testnet3>>() {
+        versioned_json(
+            version,
+            json!(server.address_sessions(address, query.limit.unwrap_or(50)).await),
+            warp::http::StatusCode::OK,
+        )
     } else {
-        Ok(reply::with_status(
-            json(&"Method Not Allowed"),
+        versioned_json(
+            version,
+            json!({
+                "error": "invalid address"
+            }),
+            warp::http::StatusCode::BAD_REQUEST,
+        )
+    }
+}
+
+async fn farm_stats(ip: String, prefix_len: u8, version: u32, server: Arc<Server>) -> Response {
+    match format!("{}/{}", ip, prefix_len).parse::<IpNet>() {
+        Ok(subnet) => versioned_json(
+            version,
+            json!(server.farm_stats(subnet.trunc()).await),
+            warp::http::StatusCode::OK,
+        ),
+        Err(_) => versioned_json(
+            version,
+            json!({
+                "error": "invalid subnet"
+            }),
+            warp::http::StatusCode::BAD_REQUEST,
+        ),
+    }
+}
+
+async fn health(version: u32, server: Arc<Server>) -> Response {
+    let healthy = server.is_healthy().await;
+    versioned_json(
+        version,
+        json!({
+            "healthy": healthy,
+            "lifecycle": server.lifecycle().name(),
+            "block_template_age_seconds": server.template_age().await.map(|age| age.as_secs()),
+        }),
+        if healthy {
+            warp::http::StatusCode::OK
+        } else {
+            warp::http::StatusCode::SERVICE_UNAVAILABLE
+        },
+    )
+}
+
+async fn connection_events(server: Arc<Server>) -> impl Reply {
+    // Subscribers that fall too far behind skip the events they missed.
+    let events = BroadcastStream::new(server.subscribe_connection_events()).filter_map(|event| {
+        let event = event.ok()?;
+        sse::Event::default()
+            .event(&event.event)
+            .json_data(&event)
+            .ok()
+            .map(Ok::<_, Infallible>)
+    });
+    sse::reply(sse::keep_alive().stream(events))
+}
+
+/// Requests a stats WebSocket client may send.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StatsSubscription {
+    /// Receive share updates of these addresses only, replacing any earlier subscription
+    AddressStats { addresses: Vec<String> },
+}
+
+async fn stats_socket(ws: Ws, server: Arc<Server>) -> impl Reply {
+    ws.on_upgrade(move |socket| handle_stats_socket(socket, server))
+}
+
+async fn handle_stats_socket(socket: WebSocket, server: Arc<Server>) {
+    let (mut ws_sender, mut ws_receiver) = futures_util::StreamExt::split(socket);
+    let (subscriber, mut updates) = server.stats_subscriber();
+    loop {
+        let reply = tokio::select! {
+            message = ws_receiver.next() => match message {
+                Some(Ok(message)) if message.is_text() => {
+                    match serde_json::from_str::<StatsSubscription>(message.to_str().unwrap_or_default()) {
+                        Ok(StatsSubscription::AddressStats { addresses }) => {
+                            let addresses = addresses
+                                .iter()
+                                .filter_map(|address| address.parse::<Address<Testnet3>>().ok())
+                                .collect::<Vec<_>>();
+                            server.subscribe_address_stats(&subscriber, &addresses).await;
+                            StatsUpdate::Subscribed {
+                                addresses: addresses.iter().map(|address| address.to_string()).collect(),
+                            }
+                        }
+                        Err(e) => StatsUpdate::Error { error: e.to_string() },
+                    }
+                }
+                Some(Ok(message)) if !message.is_close() => continue,
+                _ => break,
+            },
+            Some(update) = updates.recv() => update,
+        };
+        let reply = match serde_json::to_string(&reply) {
+            Ok(reply) => reply,
+            Err(e) => {
+                error!("Failed to serialize stats update: {}", e);
+                continue;
+            }
+        };
+        if ws_sender.send(Message::text(reply)).await.is_err() {
+            break;
+        }
+    }
+    server.unsubscribe_address_stats(&subscriber).await;
+}
+
+async fn metrics(server: Arc<Server>) -> impl Reply {
+    match server.metrics().encode() {
+        Ok(metrics) => reply::with_status(metrics, warp::http::StatusCode::OK),
+        Err(e) => reply::with_status(e.to_string(), warp::http::StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn debug_pipeline(version: u32, server: Arc<Server>) -> Response {
+    versioned_json(
+        version,
+        json!(server.pipeline_stats().await),
+        warp::http::StatusCode::OK,
+    )
+}
+
+async fn current_round(version: u32, accounting: Arc<Accounting>) -> Response {
+    let data = accounting.current_round().await;
+
+    versioned_json(
+        version,
+        json! ({
+            "n": data["n"],
+            "current_n": data["current_n"],
+            "provers": data["provers"],
+        }),
+        warp::http::StatusCode::OK,
+    )
+}
+
+/// Admin endpoints only answer local requests, which also have to carry the admin token if one is configured.
+fn admin_rejection(
+    addr: Option<SocketAddr>,
+    authorization: Option<String>,
+    version: u32,
+    server: &Server,
+) -> Option<Response> {
+    if !addr.unwrap().ip().is_loopback() {
+        return Some(versioned_json(
+            version,
+            json!("Method Not Allowed"),
             warp::http::StatusCode::METHOD_NOT_ALLOWED,
-        ))
+        ));
     }
+    let token = authorization
+        .as_deref()
+        .map(|value| value.strip_prefix("Bearer ").unwrap_or(value));
+    if !server.admin_token_valid(token) {
+        return Some(versioned_json(
+            version,
+            json!({
+                "error": "invalid admin token"
+            }),
+            warp::http::StatusCode::UNAUTHORIZED,
+        ));
+    }
+    None
+}
+
+async fn admin_current_round(
+    addr: Option<SocketAddr>,
+    authorization: Option<String>,
+    version: u32,
+    accounting: Arc<Accounting>,
+    server: Arc<Server>,
+) -> Response {
+    if let Some(rejection) = admin_rejection(addr, authorization, version, &server) {
+        return rejection;
+    }
+    let pplns = accounting.current_round().await;
+    versioned_json(version, pplns, warp::http::StatusCode::OK)
+}
+
+async fn admin_rotate_token(
+    addr: Option<SocketAddr>,
+    authorization: Option<String>,
+    version: u32,
+    server: Arc<Server>,
+) -> Response {
+    if let Some(rejection) = admin_rejection(addr, authorization, version, &server) {
+        return rejection;
+    }
+    match server.rotate_admin_token() {
+        Some(token) => versioned_json(version, json!({ "token": token }), warp::http::StatusCode::OK),
+        None => versioned_json(
+            version,
+            json!({
+                "error": "no admin token configured"
+            }),
+            warp::http::StatusCode::CONFLICT,
+        ),
+    }
+}
+
+async fn admin_security_audit(
+    addr: Option<SocketAddr>,
+    authorization: Option<String>,
+    version: u32,
+    server: Arc<Server>,
+) -> Response {
+    if let Some(rejection) = admin_rejection(addr, authorization, version, &server) {
+        return rejection;
+    }
+    versioned_json(
+        version,
+        json!(server.security_audit().await),
+        warp::http::StatusCode::OK,
+    )
+}
+
+async fn admin_kick_prover(
+    addr: Option<SocketAddr>,
+    authorization: Option<String>,
+    version: u32,
+    request: KickRequest,
+    server: Arc<Server>,
+) -> Response {
+    if let Some(rejection) = admin_rejection(addr, authorization, version, &server) {
+        return rejection;
+    }
+    let reason = request.reason.unwrap_or_else(|| "Kicked by pool operator".to_string());
+    if server.kick_prover(request.peer_addr, reason).await {
+        versioned_json(version, json!({ "kicked": true }), warp::http::StatusCode::OK)
+    } else {
+        versioned_json(
+            version,
+            json!({
+                "error": "prover not found"
+            }),
+            warp::http::StatusCode::NOT_FOUND,
+        )
+    }
+}
+
+async fn admin_restart(
+    addr: Option<SocketAddr>,
+    authorization: Option<String>,
+    version: u32,
+    request: RestartRequest,
+    server: Arc<Server>,
+) -> Response {
+    if let Some(rejection) = admin_rejection(addr, authorization, version, &server) {
+        return rejection;
+    }
+    server
+        .announce_restart(
+            request.reconnect_host,
+            request.reconnect_port,
+            request.wait_before_reconnect_seconds.unwrap_or(0),
+        )
+        .await;
+    // Shut down through the signal handler so states are saved the same way as on SIGTERM.
+    task::spawn(async {
+        // Give connections a moment to flush the notification.
+        sleep(Duration::from_secs(1)).await;
+        if let Err(e) = raise(SIGTERM) {
+            error!("Unable to trigger shutdown: {}", e);
+        }
+    });
+    versioned_json(version, json!({ "restarting": true }), warp::http::StatusCode::OK)
 }
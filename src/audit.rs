@@ -0,0 +1,80 @@
+use std::{
+    io::Write,
+    net::SocketAddr,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tracing::error;
+use tracing_appender::rolling::{self, RollingFileAppender};
+
+#[derive(Serialize)]
+struct AuditEvent<'a> {
+    timestamp: u64,
+    event: &'a str,
+    peer_addr: SocketAddr,
+    aleo_address: Option<&'a str>,
+    miner_ua: &'a str,
+    reason_if_failure: Option<&'a str>,
+}
+
+/// Append-only record of prover authentication attempts, one JSON object per line.
+/// A new file is started every day, named after the configured path with the date appended.
+pub struct AuditLog {
+    writer: Mutex<RollingFileAppender>,
+}
+
+impl AuditLog {
+    pub fn init(path: &Path) -> Self {
+        let directory = path.parent().unwrap_or_else(|| Path::new("."));
+        let file_name = path.file_name().expect("Audit log path has no file name");
+        Self {
+            writer: Mutex::new(rolling::daily(directory, file_name)),
+        }
+    }
+
+    pub fn auth_success(&self, peer_addr: SocketAddr, aleo_address: &str, miner_ua: &str) {
+        self.write(AuditEvent {
+            timestamp: now(),
+            event: "auth_success",
+            peer_addr,
+            aleo_address: Some(aleo_address),
+            miner_ua,
+            reason_if_failure: None,
+        });
+    }
+
+    pub fn auth_failure(&self, peer_addr: SocketAddr, aleo_address: Option<&str>, miner_ua: &str, reason: &str) {
+        self.write(AuditEvent {
+            timestamp: now(),
+            event: "auth_failure",
+            peer_addr,
+            aleo_address,
+            miner_ua,
+            reason_if_failure: Some(reason),
+        });
+    }
+
+    fn write(&self, event: AuditEvent) {
+        let mut line = match serde_json::to_vec(&event) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize audit event: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        if let Err(e) = self.writer.lock().write_all(&line) {
+            error!("Failed to write audit log: {}", e);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use regex::Regex;
+use serde::Deserialize;
+use snarkvm::{console::account::address::Address, prelude::Testnet3};
+
+pub struct ServerConfig {
+    /// Initial capacity of the seen nonce set
+    pub nonce_cache_capacity: usize,
+    /// Number of proof verification results kept to skip verifying resubmitted proofs
+    pub proof_cache_capacity: usize,
+    /// Respond to accepted shares with difficulty and hashrate details instead of a bare `true`
+    pub extended_share_response: bool,
+    /// Estimated network block time used for earnings forecasts and difficulty logging
+    pub estimated_block_time_secs: u64,
+    /// Expected reward per block used for earnings forecasts
+    pub expected_block_reward: Option<u64>,
+    /// Lowest share difficulty that will ever be assigned to a prover
+    pub min_prover_difficulty: u64,
+    /// Lowest proof target, `u64::MAX / difficulty`, a prover may be asked for; higher difficulties are capped
+    pub min_valid_target: u64,
+    /// Age after which the latest epoch challenge is considered stale
+    pub stale_template_warn_threshold_secs: u64,
+    /// PPLNS window size in multiples of the network proof target
+    pub shares_per_block_target: u64,
+    /// Time without any message from a prover before its connection is considered dead and closed
+    pub dead_connection_timeout_secs: u64,
+    /// Time to a new prover's first accepted share above which its initial difficulty is reported as too high
+    pub ttfs_warn_threshold_secs: u64,
+    /// Time without an accepted share before a prover is considered idle
+    pub idle_timeout_secs: u64,
+    /// Disconnect idle provers instead of only logging a warning
+    pub disconnect_idle_provers: bool,
+    /// Epoch challenges with a lower proof target are ignored as coming from a broken or malicious validator
+    pub min_network_proof_target: Option<u64>,
+    /// Number of shares buffered before they are sent to accounting; 1 sends every share on its own
+    pub accounting_batch_size: usize,
+    /// Longest time a share stays buffered before it is sent to accounting
+    pub accounting_batch_interval_ms: u64,
+    /// Number of previous epochs whose solutions are still accepted; 0 rejects every stale solution
+    pub stale_accept_tolerance_epochs: u32,
+    /// Multiplier applied to the value credited for solutions accepted from a previous epoch
+    pub stale_penalty_factor: f64,
+    /// Number of one minute pool size samples kept for the history API
+    pub history_retention_minutes: usize,
+    /// Longest time spent notifying provers of a restart before shutting down
+    pub restart_notify_timeout_secs: u64,
+    /// Undecodable solutions tolerated from one connection before it is closed
+    pub max_deserialization_errors_per_session: u32,
+    /// Errors of a single kind (parse, protocol or logic) tolerated from one connection before it is closed
+    pub max_protocol_errors_before_disconnect: u32,
+    /// Alarm when the 5 minute pool speed falls below this percentage of the 1 hour speed; 0 disables it
+    pub hashrate_drop_alarm_threshold_percent: f64,
+    /// URL receiving a JSON POST when the hashrate drop alarm goes off
+    pub alarm_webhook_url: Option<String>,
+    /// Append prover authentication attempts to this file, rotated daily
+    pub audit_log_path: Option<PathBuf>,
+    /// Append a CSV row per active prover to this file every export interval, rotated daily
+    pub prover_stats_export_path: Option<PathBuf>,
+    pub prover_stats_export_interval_secs: u64,
+    /// Verify proofs on a dedicated thread pool pinned to these CPU cores
+    pub compute_cores: Option<Vec<usize>>,
+    /// Time a proof verification may take on the compute pool before it is retried on a blocking thread
+    pub verification_timeout_ms: u64,
+    /// Share of verifications falling back off the compute pool above which a warning is logged
+    pub verification_fallback_warn_ratio: f64,
+    /// Time without any submission before a prover's target is lowered; 0 disables the fallback
+    pub difficulty_fallback_idle_secs: u64,
+    /// Factor applied to the target of a prover that stopped submitting
+    pub difficulty_fallback_factor: f64,
+    /// Consecutive target reductions allowed before the prover submits an accepted share again
+    pub difficulty_fallback_max_reductions: u32,
+    /// Growth of the 1 minute share rate between checks that applies the global difficulty modifier immediately
+    pub spike_detection_threshold: f64,
+    /// Longest wait for a prover's connection to take a message before the prover is dropped
+    pub response_send_timeout_secs: u64,
+    /// Longest a prover's message channel may stay full before the prover is disconnected
+    pub backpressure_timeout_secs: u64,
+    /// Looser thresholds and quieter stale / duplicate logging for test networks
+    pub testnet_mode: bool,
+    /// Most solutions accepted in one `mining.submit_batch` request
+    pub max_batch_size: usize,
+    /// Solutions buffered while waiting to be sent to the validator
+    pub max_queued_solutions: usize,
+    /// Solution round trips to the validator slower than this are logged as a warning
+    pub max_solution_submit_latency_ms: u64,
+    /// Connections refused with a "Pool full" error once this many provers are connected
+    pub max_provers: Option<usize>,
+    /// Accepted shares after which a prover is asked to reconnect with a fresh session
+    pub max_shares_per_session: Option<u64>,
+    /// Connections per second from one monitored subnet over the last minute above which the subnet's new
+    /// connections are refused; 0 disables it
+    pub connection_flood_threshold: f64,
+    /// Ascending bucket boundaries of the accepted share difficulty histogram
+    pub difficulty_histogram_bounds: Vec<u64>,
+    /// Finished sessions kept for each address, oldest dropped first
+    pub max_sessions_per_address: usize,
+    /// Close the older connection when a prover authenticates again from the same IP with the same worker name
+    pub close_duplicate_connections: bool,
+    /// Prefix length IPv4 connections are grouped by for subnet monitoring
+    pub subnet_monitoring_prefix_len: u8,
+    /// Prefix length IPv6 connections are grouped by for subnet monitoring
+    pub subnet_monitoring_prefix_len_v6: u8,
+    /// Only accept provers whose password is a valid token for `authentication_secret`
+    pub require_authentication: bool,
+    /// Secret shared with the provers of a private pool
+    pub authentication_secret: Option<String>,
+    /// Token admin API requests have to send as `Authorization: Bearer <token>`, besides coming from localhost
+    pub admin_token: Option<String>,
+    /// How long a token replaced through `POST /admin/rotate-token` stays valid
+    pub token_rotation_grace_secs: u64,
+    /// Speedometer windows of provers without an address specific override
+    pub speedometer: SpeedometerConfig,
+    /// Overrides from the `[address.<aleo_address>]` sections of the config file
+    pub address_configs: HashMap<Address<Testnet3>, AddressConfig>,
+    /// Wallets the pool fee rotates between, from the `[[fee_wallet]]` entries of the config file
+    pub fee_wallets: Vec<FeeWallet>,
+    /// Address credited with the pool fee of found solutions. Without it the active fee wallet is used, or else
+    /// the address of the first share accepted in the round
+    pub pool_fee_address: Option<Address<Testnet3>>,
+    /// Mining groups sharing the pool, from the `[[tenant]]` entries of the config file, in matching order
+    pub tenants: Vec<TenantConfig>,
+    /// Submission error messages by error code from the `[error_messages]` section of the config file,
+    /// replacing the built-in messages
+    pub error_messages: HashMap<u32, String>,
+    /// Protocol versions accepted in `mining.subscribe`, such as `2.0.0`
+    pub supported_stratum_versions: Vec<String>,
+    pub misbehavior: MisbehaviorConfig,
+    pub performance_score: PerformanceScoreConfig,
+    pub tcp_options: TcpOptions,
+}
+
+impl ServerConfig {
+    /// Speedometer windows for the provers of `address`.
+    pub fn speedometer_config(&self, address: &Address<Testnet3>) -> SpeedometerConfig {
+        self.address_configs
+            .get(address)
+            .and_then(|config| config.prover_speed_config)
+            .unwrap_or(self.speedometer)
+    }
+
+    /// Label configured for `address`, if any.
+    pub fn address_label(&self, address: &Address<Testnet3>) -> Option<String> {
+        self.address_configs
+            .get(address)
+            .and_then(|config| config.label.clone())
+    }
+}
+
+/// Score added for each kind of bad submission, and the score thresholds of each response.
+/// Every accepted share lowers the score by one.
+#[derive(Clone, Copy)]
+pub struct MisbehaviorConfig {
+    pub stale_penalty: u32,
+    pub low_difficulty_penalty: u32,
+    pub invalid_proof_penalty: u32,
+    pub duplicate_nonce_penalty: u32,
+    pub warn_threshold: u32,
+    pub throttle_threshold: u32,
+    pub disconnect_threshold: u32,
+    pub ban_threshold: u32,
+    /// How long an IP stays banned after reaching `ban_threshold`
+    pub ban_duration: Duration,
+}
+
+/// Weights of the pool performance score, `luck * luck_weight + efficiency * efficiency_weight +
+/// min(speed_5m / target_hashrate, 1) * hashrate_weight`, the hashrate term being 1 without a target.
+#[derive(Clone, Copy)]
+pub struct PerformanceScoreConfig {
+    pub luck_weight: f64,
+    pub efficiency_weight: f64,
+    pub hashrate_weight: f64,
+    pub target_hashrate: Option<f64>,
+}
+
+/// Socket options applied to every accepted prover connection.
+#[derive(Clone, Copy)]
+pub struct TcpOptions {
+    pub nodelay: bool,
+    pub keepalive_idle: Duration,
+    pub keepalive_interval: Duration,
+    pub keepalive_retries: u32,
+}
+
+/// Windows of the speedometers kept for each prover.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct SpeedometerConfig {
+    /// Window of the speed a prover's share difficulty follows
+    pub target_window_secs: u64,
+    /// How long the 5 minute to 1 hour speeds are cached between recalculations
+    pub cache_interval_secs: u64,
+}
+
+impl SpeedometerConfig {
+    /// Windows suited to a pool of `prover_count` provers. The share rate of a small pool is too noisy over
+    /// a short window, while a large pool submits enough shares for a 2 minute window.
+    pub fn auto_select(prover_count: usize) -> SpeedometerConfig {
+        let target_window_secs = match prover_count {
+            0..=9 => 300,
+            10..=1000 => 180,
+            _ => 120,
+        };
+        SpeedometerConfig {
+            target_window_secs,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SpeedometerConfig {
+    fn default() -> Self {
+        Self {
+            target_window_secs: 120,
+            cache_interval_secs: 30,
+        }
+    }
+}
+
+/// Settings of a single address, read from an `[address.<aleo_address>]` section of the config file.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct AddressConfig {
+    /// Name shown next to the address in logs and the API, such as `Farm-A Rack 3`
+    pub label: Option<String>,
+    pub prover_speed_config: Option<SpeedometerConfig>,
+}
+
+/// A wallet collecting the pool fee. A scheduled wallet becomes active each time its schedule fires and stays
+/// active until another one fires; wallets without a schedule are used while no scheduled wallet has fired yet.
+#[derive(Clone)]
+pub struct FeeWallet {
+    pub address: Address<Testnet3>,
+    pub schedule: Option<Schedule>,
+}
+
+impl FeeWallet {
+    /// The wallet active at `now`.
+    pub fn active(wallets: &[FeeWallet], now: DateTime<Utc>) -> Option<Address<Testnet3>> {
+        wallets
+            .iter()
+            .filter_map(|wallet| Some((wallet.schedule.as_ref()?.after(&now).next_back()?, wallet.address)))
+            .max_by_key(|(activated_at, _)| *activated_at)
+            .map(|(_, address)| address)
+            .or_else(|| {
+                wallets
+                    .iter()
+                    .find(|wallet| wallet.schedule.is_none())
+                    .map(|wallet| wallet.address)
+            })
+    }
+
+    /// The next time any wallet's schedule fires after `now`.
+    pub fn next_rotation(wallets: &[FeeWallet], now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        wallets
+            .iter()
+            .filter_map(|wallet| wallet.schedule.as_ref()?.after(&now).next())
+            .min()
+    }
+}
+
+/// A mining group sharing the pool with its own pool fee.
+#[derive(Clone)]
+pub struct TenantConfig {
+    pub name: String,
+    /// Addresses matching this pattern belong to the tenant; a tenant without one takes any address
+    pub address_prefix_filter: Option<Regex>,
+    /// Address credited with the fee taken from the reward the tenant's provers earn
+    pub fee_address: Address<Testnet3>,
+    pub fee_percent: f64,
+}
+
+impl TenantConfig {
+    /// The first tenant whose filter matches `address`.
+    pub fn find<'a>(tenants: &'a [TenantConfig], address: &str) -> Option<&'a TenantConfig> {
+        tenants.iter().find(|tenant| {
+            tenant
+                .address_prefix_filter
+                .as_ref()
+                .map_or(true, |filter| filter.is_match(address))
+        })
+    }
+}
+
+/// Settings read from the TOML config file.
+#[derive(Default)]
+pub struct FileConfig {
+    pub address_configs: HashMap<Address<Testnet3>, AddressConfig>,
+    pub fee_wallets: Vec<FeeWallet>,
+    pub tenants: Vec<TenantConfig>,
+    pub error_messages: HashMap<u32, String>,
+}
+
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    address: HashMap<String, AddressConfig>,
+    #[serde(default)]
+    fee_wallet: Vec<FeeWalletEntry>,
+    #[serde(default)]
+    tenant: Vec<TenantEntry>,
+    /// Submission error messages keyed by error code, such as `21 = "Stale solution"`
+    #[serde(default)]
+    error_messages: HashMap<String, String>,
+}
+
+/// A `[[fee_wallet]]` entry, with the schedule as a cron expression such as `0 0 0 * * *`.
+#[derive(Deserialize)]
+struct FeeWalletEntry {
+    address: String,
+    schedule: Option<String>,
+}
+
+impl FeeWalletEntry {
+    fn parse(self) -> Result<FeeWallet> {
+        let address = parse_address(&self.address)?;
+        let schedule = self
+            .schedule
+            .map(|schedule| {
+                Schedule::from_str(&schedule)
+                    .map_err(|e| anyhow!("Invalid schedule {} for fee wallet {}: {}", schedule, address, e))
+            })
+            .transpose()?;
+        Ok(FeeWallet { address, schedule })
+    }
+}
+
+/// A `[[tenant]]` entry, with the address filter as a regular expression such as `^aleo1abc`.
+#[derive(Deserialize)]
+struct TenantEntry {
+    name: String,
+    address_prefix_filter: Option<String>,
+    fee_address: String,
+    fee_percent: f64,
+}
+
+impl TenantEntry {
+    fn parse(self) -> Result<TenantConfig> {
+        let address_prefix_filter = self
+            .address_prefix_filter
+            .map(|filter| {
+                Regex::new(&filter)
+                    .map_err(|e| anyhow!("Invalid address filter {} for tenant {}: {}", filter, self.name, e))
+            })
+            .transpose()?;
+        if !(0.0..=100.0).contains(&self.fee_percent) {
+            return Err(anyhow!(
+                "Invalid fee percent {} for tenant {}",
+                self.fee_percent,
+                self.name
+            ));
+        }
+        Ok(TenantConfig {
+            fee_address: parse_address(&self.fee_address)?,
+            name: self.name,
+            address_prefix_filter,
+            fee_percent: self.fee_percent,
+        })
+    }
+}
+
+fn parse_address(address: &str) -> Result<Address<Testnet3>> {
+    Address::<Testnet3>::from_str(address).map_err(|e| anyhow!("Invalid address {} in config file: {}", address, e))
+}
+
+/// Reads the per-address sections, fee wallets, tenants and error messages of a TOML config file.
+pub fn load_config_file(path: &Path) -> Result<FileConfig> {
+    let file: ConfigFile = toml::from_str(&std::fs::read_to_string(path)?)?;
+    let address_configs = file
+        .address
+        .into_iter()
+        .map(|(address, config)| Ok((parse_address(&address)?, config)))
+        .collect::<Result<_>>()?;
+    let fee_wallets = file
+        .fee_wallet
+        .into_iter()
+        .map(FeeWalletEntry::parse)
+        .collect::<Result<_>>()?;
+    let tenants: Vec<TenantConfig> = file.tenant.into_iter().map(TenantEntry::parse).collect::<Result<_>>()?;
+    let mut tenant_names = HashSet::new();
+    if let Some(tenant) = tenants.iter().find(|tenant| !tenant_names.insert(tenant.name.as_str())) {
+        return Err(anyhow!("Duplicate tenant {}", tenant.name));
+    }
+    let error_messages = file
+        .error_messages
+        .into_iter()
+        .map(|(code, message)| {
+            let code = code
+                .parse::<u32>()
+                .map_err(|e| anyhow!("Invalid error code {} in config file: {}", code, e))?;
+            Ok((code, message))
+        })
+        .collect::<Result<_>>()?;
+    Ok(FileConfig {
+        address_configs,
+        fee_wallets,
+        tenants,
+        error_messages,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use snarkvm::prelude::PrivateKey;
+
+    use super::*;
+
+    fn tenant(name: &str, address_prefix_filter: Option<&str>) -> TenantConfig {
+        let private_key = PrivateKey::<Testnet3>::new(&mut rand::thread_rng()).unwrap();
+        TenantConfig {
+            name: name.to_string(),
+            address_prefix_filter: address_prefix_filter.map(|filter| Regex::new(filter).unwrap()),
+            fee_address: Address::try_from(&private_key).unwrap(),
+            fee_percent: 1.0,
+        }
+    }
+
+    fn find<'a>(tenants: &'a [TenantConfig], address: &str) -> Option<&'a str> {
+        TenantConfig::find(tenants, address).map(|tenant| tenant.name.as_str())
+    }
+
+    #[test]
+    fn first_matching_tenant_wins() {
+        let tenants = vec![tenant("narrow", Some("^aleo1abc")), tenant("wide", Some("^aleo1a"))];
+        assert_eq!(find(&tenants, "aleo1abcdef"), Some("narrow"));
+        assert_eq!(find(&tenants, "aleo1axyz"), Some("wide"));
+        assert_eq!(find(&tenants, "aleo1xyz"), None);
+        let tenants = vec![tenant("wide", Some("^aleo1a")), tenant("narrow", Some("^aleo1abc"))];
+        assert_eq!(find(&tenants, "aleo1abcdef"), Some("wide"));
+    }
+
+    #[test]
+    fn tenant_without_filter_matches_every_address() {
+        let tenants = vec![tenant("narrow", Some("^aleo1abc")), tenant("everyone", None)];
+        assert_eq!(find(&tenants, "aleo1abcdef"), Some("narrow"));
+        assert_eq!(find(&tenants, "aleo1xyz"), Some("everyone"));
+        assert_eq!(find(&tenants, ""), Some("everyone"));
+        let tenants = vec![tenant("everyone", None), tenant("narrow", Some("^aleo1abc"))];
+        assert_eq!(find(&tenants, "aleo1abcdef"), Some("everyone"));
+        assert_eq!(find(&[], "aleo1abcdef"), None);
+    }
+}
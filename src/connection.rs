@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     net::SocketAddr,
     str::FromStr,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
@@ -10,6 +12,7 @@ use aleo_stratum::{
 };
 use anyhow::{anyhow, Result};
 use futures_util::SinkExt;
+use json_rpc_types::{Error, ErrorCode};
 use semver::Version;
 use snarkvm::{
     console::account::address::Address,
@@ -18,7 +21,10 @@ use snarkvm::{
 use snarkvm_algorithms::polycommit::kzg10::{KZGCommitment, KZGProof};
 use tokio::{
     net::TcpStream,
-    sync::mpsc::{channel, Sender},
+    sync::{
+        mpsc::{channel, Sender},
+        Notify,
+    },
     task,
     time::timeout,
 };
@@ -26,51 +32,239 @@ use tokio_stream::StreamExt;
 use tokio_util::codec::Framed;
 use tracing::{error, info, trace, warn};
 
-use crate::server::ServerMessage;
+use crate::{
+    audit::AuditLog,
+    metrics::Metrics,
+    server::{ProverAuthenticator, SecurityEvents, ServerMessage, SubmittedSolution},
+};
+
+/// Settings and shared handles every connection gets from the server.
+#[derive(Clone)]
+pub struct ConnectionContext {
+    pub pool_address: Address<Testnet3>,
+    pub dead_connection_timeout: Duration,
+    pub max_deserialization_errors: u32,
+    pub max_protocol_errors: u32,
+    pub max_batch_size: usize,
+    pub metrics: Arc<Metrics>,
+    pub audit_log: Option<Arc<AuditLog>>,
+    pub security_events: Arc<SecurityEvents>,
+    /// Checks prover passwords before their authorization is accepted, if authentication is required
+    pub authenticator: Option<Arc<ProverAuthenticator>>,
+    pub supported_stratum_versions: Arc<Vec<Version>>,
+}
 
 pub struct Connection {
     user_agent: String,
     address: Option<Address<Testnet3>>,
     version: Version,
     last_received: Option<Instant>,
+    deserialization_errors: u32,
+    protocol_errors: HashMap<ProtocolErrorKind, u32>,
+}
+
+/// Kinds of malformed messages counted against a prover.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProtocolErrorKind {
+    /// The message is not valid JSON or not a stratum message.
+    Parse,
+    /// The message is not expected at this point of the session.
+    Protocol,
+    /// A field of the message holds an invalid value.
+    Logic,
+}
+
+impl ProtocolErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProtocolErrorKind::Parse => "parse",
+            ProtocolErrorKind::Protocol => "protocol",
+            ProtocolErrorKind::Logic => "logic",
+        }
+    }
+}
+
+/// Why a commitment or proof submitted by a prover could not be decoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeserializeErrorKind {
+    /// The value ended before a full commitment or proof was read.
+    TruncatedData,
+    /// The value is not hex or does not describe a valid curve point.
+    InvalidEncoding,
+    /// The proof is a hiding proof, which coinbase puzzle solutions never are.
+    WrongProofType,
+    /// Bytes are left over after decoding, as with a prover using another serialization format.
+    VersionMismatch,
+}
+
+impl DeserializeErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeserializeErrorKind::TruncatedData => "truncated_data",
+            DeserializeErrorKind::InvalidEncoding => "invalid_encoding",
+            DeserializeErrorKind::WrongProofType => "wrong_proof_type",
+            DeserializeErrorKind::VersionMismatch => "version_mismatch",
+        }
+    }
+}
+
+/// Splits an `address.worker` username into the address and the worker name.
+fn parse_username(username: &str) -> (&str, &str) {
+    username.split_once('.').unwrap_or((username, DEFAULT_WORKER_NAME))
+}
+
+/// Job ids are the little-endian `u32` epoch number.
+fn decode_job_id(job_id: &str) -> Option<u32> {
+    let bytes: [u8; 4] = hex::decode(job_id).ok()?.try_into().ok()?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+/// Extra data is opaque hex-encoded bytes, limited to `MAX_EXTRA_DATA_LENGTH` of them.
+fn decode_extra_data(extra_data: &str) -> Option<Vec<u8>> {
+    let bytes = hex::decode(extra_data).ok()?;
+    if bytes.len() > MAX_EXTRA_DATA_LENGTH {
+        return None;
+    }
+    Some(bytes)
+}
+
+/// Nonces are little-endian `u64`s, so anything other than exactly 8 hex-encoded bytes is malformed.
+fn validate_nonce(nonce: &str) -> Option<u64> {
+    let bytes: [u8; 8] = hex::decode(nonce).ok()?.try_into().ok()?;
+    Some(u64::from_le_bytes(bytes))
+}
+
+fn decode_hex_le<T: FromBytes>(value: &str) -> Result<T, DeserializeErrorKind> {
+    let bytes = hex::decode(value).map_err(|_| DeserializeErrorKind::InvalidEncoding)?;
+    let mut reader = &bytes[..];
+    let decoded = T::read_le(&mut reader).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => DeserializeErrorKind::TruncatedData,
+        _ => DeserializeErrorKind::InvalidEncoding,
+    })?;
+    if !reader.is_empty() {
+        return Err(DeserializeErrorKind::VersionMismatch);
+    }
+    Ok(decoded)
+}
+
+fn decode_solution(
+    commitment: &str,
+    proof: &str,
+) -> Result<
+    (
+        KZGCommitment<<Testnet3 as Environment>::PairingCurve>,
+        KZGProof<<Testnet3 as Environment>::PairingCurve>,
+    ),
+    DeserializeErrorKind,
+> {
+    let commitment = decode_hex_le::<KZGCommitment<<Testnet3 as Environment>::PairingCurve>>(commitment)?;
+    let proof = decode_hex_le::<KZGProof<<Testnet3 as Environment>::PairingCurve>>(proof)?;
+    if proof.is_hiding() {
+        return Err(DeserializeErrorKind::WrongProofType);
+    }
+    Ok((commitment, proof))
 }
 
 static PEER_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
-static PEER_COMM_TIMEOUT: Duration = Duration::from_secs(180);
 
-static MIN_SUPPORTED_VERSION: Version = Version::new(2, 0, 0);
-static MAX_SUPPORTED_VERSION: Version = Version::new(2, 0, 0);
+/// Length limit of a single stratum message, as used by the default codec.
+static MAX_MESSAGE_LENGTH: usize = 4096;
+
+/// Worker name used when the username is a bare address.
+pub static DEFAULT_WORKER_NAME: &str = "default";
+
+/// Longest extra data a prover may attach to a share submission.
+static MAX_EXTRA_DATA_LENGTH: usize = 32;
+
+/// Messages queued for a prover before sends to it start waiting.
+pub static PROVER_CHANNEL_CAPACITY: usize = 1024;
+
+/// First protocol version with `mining.round_reset`; older miners fail to decode unknown methods.
+static ROUND_RESET_VERSION: Version = Version::new(2, 1, 0);
 
 impl Connection {
     pub async fn init(
         stream: TcpStream,
         peer_addr: SocketAddr,
         server_sender: Sender<ServerMessage>,
-        pool_address: Address<Testnet3>,
+        context: ConnectionContext,
     ) {
-        task::spawn(Connection::run(stream, peer_addr, server_sender, pool_address));
+        task::spawn(Connection::run(stream, peer_addr, server_sender, context));
+    }
+
+    /// Tells a peer the connection is refused and closes it without starting a session.
+    pub async fn reject(stream: TcpStream, peer_addr: SocketAddr, reason: String) {
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        match timeout(PEER_HANDSHAKE_TIMEOUT, framed.send(StratumMessage::Disconnect(reason))).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Failed to send disconnect to peer {:?}: {:?}", peer_addr, e),
+            Err(_) => warn!("Timed out sending disconnect to peer {:?}", peer_addr),
+        }
+    }
+
+    /// Answers the peer's first request with an error and closes the connection without starting a session,
+    /// for miners that only report errors they get in a response.
+    pub async fn reject_request(stream: TcpStream, peer_addr: SocketAddr, reason: &str) {
+        let mut framed = Framed::new(stream, StratumCodec::default());
+        let id = match timeout(PEER_HANDSHAKE_TIMEOUT, framed.next()).await {
+            Ok(Some(Ok(StratumMessage::Subscribe(id, ..)))) => id,
+            Ok(Some(Ok(message))) => {
+                warn!("Peer {:?} sent {} as its first message", peer_addr, message.name());
+                return;
+            }
+            _ => {
+                warn!("Peer {:?} sent no request before being rejected", peer_addr);
+                return;
+            }
+        };
+        let error = Error::with_custom_msg(ErrorCode::from_code(20), reason);
+        let response = StratumMessage::Response(id, None, Some(error));
+        match timeout(PEER_HANDSHAKE_TIMEOUT, framed.send(response)).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => warn!("Failed to send rejection to peer {:?}: {:?}", peer_addr, e),
+            Err(_) => warn!("Timed out sending rejection to peer {:?}", peer_addr),
+        }
     }
 
     pub async fn run(
         stream: TcpStream,
         peer_addr: SocketAddr,
         server_sender: Sender<ServerMessage>,
-        pool_address: Address<Testnet3>,
+        context: ConnectionContext,
     ) {
-        let mut framed = Framed::new(stream, StratumCodec::default());
+        let ConnectionContext {
+            pool_address,
+            dead_connection_timeout,
+            max_deserialization_errors,
+            max_protocol_errors,
+            max_batch_size,
+            metrics,
+            audit_log,
+            security_events,
+            authenticator,
+            supported_stratum_versions,
+        } = context;
+        // Every submission fits in a default sized message, so a full batch fits in that many of them.
+        let mut framed = Framed::new(stream, StratumCodec::new(MAX_MESSAGE_LENGTH * max_batch_size.max(1)));
 
-        let (sender, mut receiver) = channel(1024);
+        let (sender, mut receiver) = channel(PROVER_CHANNEL_CAPACITY);
+        // The server closes connections through this, even while a send to the peer is blocked.
+        let close = Arc::new(Notify::new());
 
         let mut conn = Connection {
             user_agent: "Unknown".to_string(),
             address: None,
             version: Version::new(0, 0, 0),
             last_received: None,
+            deserialization_errors: 0,
+            protocol_errors: HashMap::new(),
         };
 
         // Handshake
 
-        if let Ok((user_agent, version)) = Connection::handshake(&mut framed, pool_address.to_string()).await {
+        if let Ok((user_agent, version)) =
+            Connection::handshake(&mut framed, pool_address.to_string(), &supported_stratum_versions).await
+        {
             conn.user_agent = user_agent;
             conn.version = version;
         } else {
@@ -80,13 +274,25 @@ impl Connection {
             return;
         }
 
-        if let Ok(address) = Connection::authorize(&mut framed).await {
+        let authorized = Connection::authorize(&mut framed, authenticator.as_deref()).await;
+        if let Some(audit_log) = &audit_log {
+            match &authorized {
+                Ok((address, ..)) => audit_log.auth_success(peer_addr, &address.to_string(), &conn.user_agent),
+                Err(e) => audit_log.auth_failure(peer_addr, None, &conn.user_agent, &e.to_string()),
+            }
+        }
+        if authorized.is_err() {
+            security_events.auth_failure().await;
+        }
+        if let Ok((address, worker_name)) = authorized {
             conn.address = Some(address);
             if let Err(e) = server_sender
                 .send(ServerMessage::ProverAuthenticated(
                     peer_addr,
                     conn.address.unwrap(),
-                    sender,
+                    worker_name,
+                    sender.clone(),
+                    close.clone(),
                 ))
                 .await
             {
@@ -103,86 +309,175 @@ impl Connection {
 
         info!("Peer {:?} authenticated as {}", peer_addr, conn.address.unwrap());
 
-        loop {
-            tokio::select! {
-                Some(msg) = receiver.recv() => {
-                    if let Some(instant) = conn.last_received {
-                        if instant.elapsed() > PEER_COMM_TIMEOUT {
-                            warn!("Peer {:?} timed out", peer_addr);
+        let session = async {
+            loop {
+                tokio::select! {
+                    Some(msg) = receiver.recv() => {
+                        if let Some(instant) = conn.last_received {
+                            if instant.elapsed() > dead_connection_timeout {
+                                warn!("Peer {:?} timed out", peer_addr);
+                                break;
+                            }
+                        }
+                        if matches!(msg, StratumMessage::RoundReset(..)) && conn.version < ROUND_RESET_VERSION {
+                            continue;
+                        }
+                        trace!("Sending message {} to peer {:?}", msg.name(), peer_addr);
+                        let disconnect = matches!(msg, StratumMessage::Disconnect(..));
+                        if let Err(e) = framed.send(msg).await {
+                            error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                        }
+                        if disconnect {
+                            info!("Disconnecting peer {:?}", peer_addr);
                             break;
                         }
-                    }
-                    trace!("Sending message {} to peer {:?}", msg.name(), peer_addr);
-                    if let Err(e) = framed.send(msg).await {
-                        error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
-                    }
-                },
-                result = framed.next() => match result {
-                    Some(Ok(msg)) => {
-                        trace!("Received message {} from peer {:?}", msg.name(), peer_addr);
-                        conn.last_received = Some(Instant::now());
-                        match msg {
-                            StratumMessage::Submit(id, _worker_name, job_id, nonce, commitment, proof) => {
-                                let job_bytes = hex::decode(job_id.clone());
-                                if job_bytes.is_err() {
-                                    warn!("Failed to decode job_id {} from peer {:?}", job_id, peer_addr);
-                                    break;
-                                }
-                                if job_bytes.clone().unwrap().len() != 4 {
-                                    warn!("Invalid job_id {} from peer {:?}", job_id, peer_addr);
-                                    break;
-                                }
-                                let epoch_number = u32::from_le_bytes(job_bytes.unwrap().try_into().unwrap());
-                                let nonce_bytes = hex::decode(nonce.clone());
-                                if nonce_bytes.is_err() {
-                                    warn!("Failed to decode nonce {} from peer {:?}", nonce, peer_addr);
-                                    break;
-                                }
-                                let nonce = u64::from_le_bytes(nonce_bytes.unwrap().try_into().unwrap());
-                                let commitment_bytes = hex::decode(commitment.clone());
-                                if commitment_bytes.is_err() {
-                                    warn!("Failed to decode commitment {} from peer {:?}", commitment, peer_addr);
-                                    break;
+                    },
+                    result = framed.next() => match result {
+                        Some(Ok(msg)) => {
+                            trace!("Received message {} from peer {:?}", msg.name(), peer_addr);
+                            conn.last_received = Some(Instant::now());
+                            match msg {
+                                StratumMessage::Submit(id, _worker_name, job_id, nonce, commitment, proof, extra_data) => {
+                                    let extra_data = match extra_data.as_deref().map(decode_extra_data) {
+                                        None => None,
+                                        Some(Some(extra_data)) => Some(extra_data),
+                                        Some(None) => {
+                                            warn!("Invalid extra data from peer {:?}", peer_addr);
+                                            if conn.protocol_error(ProtocolErrorKind::Logic, peer_addr, &metrics, max_protocol_errors) {
+                                                break;
+                                            }
+                                            if let Err(e) = framed.send(StratumMessage::Response(id, None, Some(Error::with_custom_msg(ErrorCode::from_code(20), "Invalid extra data")))).await {
+                                                error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                                            }
+                                            continue;
+                                        }
+                                    };
+                                    let solution = match conn.decode_submission(peer_addr, &metrics, max_deserialization_errors, max_protocol_errors, (job_id, nonce, commitment, proof)) {
+                                        Ok(solution) => solution,
+                                        Err(Some(error)) => {
+                                            if let Err(e) = framed.send(StratumMessage::Response(id, None, Some(error))).await {
+                                                error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                                            }
+                                            continue;
+                                        }
+                                        Err(None) => break,
+                                    };
+                                    if let Err(e) = server_sender.send(ServerMessage::ProverSubmit(id, peer_addr, vec![solution], false, extra_data, Instant::now())).await {
+                                        error!("Failed to send ProverSubmit message to server: {}", e);
+                                    }
                                 }
-                                let commitment = KZGCommitment::<<Testnet3 as Environment>::PairingCurve>::from_bytes_le(&commitment_bytes.unwrap()[..]);
-                                if commitment.is_err() {
-                                    warn!("Invalid commitment from peer {:?}", peer_addr);
-                                    break;
+                                StratumMessage::SubmitBatch(id, _worker_name, submissions) => {
+                                    if submissions.is_empty() || submissions.len() > max_batch_size {
+                                        warn!("Invalid batch size {} from peer {:?}", submissions.len(), peer_addr);
+                                        if conn.protocol_error(ProtocolErrorKind::Logic, peer_addr, &metrics, max_protocol_errors) {
+                                            break;
+                                        }
+                                        if let Err(e) = framed.send(StratumMessage::Response(id, None, Some(Error::with_custom_msg(ErrorCode::from_code(20), "Invalid batch size")))).await {
+                                            error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                                        }
+                                        continue;
+                                    }
+                                    // A batch is one request, so a single malformed solution rejects all of it.
+                                    let mut solutions = Vec::with_capacity(submissions.len());
+                                    let mut rejected = None;
+                                    for submission in submissions {
+                                        match conn.decode_submission(peer_addr, &metrics, max_deserialization_errors, max_protocol_errors, submission) {
+                                            Ok(solution) => solutions.push(solution),
+                                            Err(error) => {
+                                                rejected = Some(error);
+                                                break;
+                                            }
+                                        }
+                                    }
+                                    match rejected {
+                                        None => {
+                                            if let Err(e) = server_sender.send(ServerMessage::ProverSubmit(id, peer_addr, solutions, true, None, Instant::now())).await {
+                                                error!("Failed to send ProverSubmit message to server: {}", e);
+                                            }
+                                        }
+                                        Some(Some(error)) => {
+                                            if let Err(e) = framed.send(StratumMessage::Response(id, None, Some(error))).await {
+                                                error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                                            }
+                                        }
+                                        Some(None) => break,
+                                    }
                                 }
-                                let proof_bytes = hex::decode(proof.clone());
-                                if proof_bytes.is_err() {
-                                warn!("Failed to decode proof {} from peer {:?}", proof, peer_addr);
-                                    break;
+                                StratumMessage::Authorize(id, username, password) => {
+                                    let (address, worker_name) = parse_username(&username);
+                                    let address = match Address::<Testnet3>::from_str(address) {
+                                        Ok(address) => address,
+                                        Err(e) => {
+                                            warn!("Invalid address {} from peer {:?}: {:?}", address, peer_addr, e);
+                                            if let Some(audit_log) = &audit_log {
+                                                audit_log.auth_failure(peer_addr, Some(address), &conn.user_agent, "Invalid address");
+                                            }
+                                            if conn.protocol_error(ProtocolErrorKind::Logic, peer_addr, &metrics, max_protocol_errors) {
+                                                break;
+                                            }
+                                            if let Err(e) = framed.send(StratumMessage::Response(id, None, Some(Error::with_custom_msg(ErrorCode::from_code(20), "Invalid address")))).await {
+                                                error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                                            }
+                                            continue;
+                                        }
+                                    };
+                                    if authenticator.as_ref().map_or(false, |authenticator| !authenticator.token_valid(&address, &password)) {
+                                        warn!("Peer {:?} sent an invalid authentication token for {}", peer_addr, address);
+                                        if let Some(audit_log) = &audit_log {
+                                            audit_log.auth_failure(peer_addr, Some(&address.to_string()), &conn.user_agent, "Invalid authentication token");
+                                        }
+                                        security_events.auth_failure().await;
+                                        if let Err(e) = framed.send(StratumMessage::Response(id, None, Some(Error::with_custom_msg(ErrorCode::from_code(24), "Invalid authentication token")))).await {
+                                            error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                                        }
+                                        break;
+                                    }
+                                    if let Some(audit_log) = &audit_log {
+                                        audit_log.auth_success(peer_addr, &address.to_string(), &conn.user_agent);
+                                    }
+                                    if let Err(e) = framed.send(StratumMessage::Response(id, Some(ResponseParams::Bool(true)), None)).await {
+                                        error!("Failed to send message to peer {:?}: {:?}", peer_addr, e);
+                                    }
+                                    conn.address = Some(address);
+                                    if let Err(e) = server_sender.send(ServerMessage::ProverAuthenticated(peer_addr, address, worker_name.to_string(), sender.clone(), close.clone())).await {
+                                        error!("Failed to send ProverAuthenticated message to server: {}", e);
+                                    }
                                 }
-                                let proof = KZGProof::<<Testnet3 as Environment>::PairingCurve>::from_bytes_le(&proof_bytes.unwrap());
-                                if proof.is_err() {
-                                    warn!("Invalid proof from peer {:?}", peer_addr);
-                                    break;
+                                StratumMessage::Malformed(e) => {
+                                    warn!("Failed to parse message from peer {:?}: {}", peer_addr, e);
+                                    if conn.protocol_error(ProtocolErrorKind::Parse, peer_addr, &metrics, max_protocol_errors) {
+                                        break;
+                                    }
                                 }
-                                if let Err(e) = server_sender.send(ServerMessage::ProverSubmit(id, peer_addr, epoch_number, nonce, commitment.unwrap(), proof.unwrap())).await {
-                                    error!("Failed to send ProverSubmit message to server: {}", e);
+                                _ => {
+                                    warn!("Received unexpected message from peer {:?}: {:?}", peer_addr, msg.name());
+                                    if conn.protocol_error(ProtocolErrorKind::Protocol, peer_addr, &metrics, max_protocol_errors) {
+                                        break;
+                                    }
                                 }
                             }
-                            _ => {
-                                warn!("Received unexpected message from peer {:?}: {:?}", peer_addr, msg.name());
-                                break;
-                            }
                         }
-                    }
-                    Some(Err(e)) => {
-                        warn!("Failed to read message from peer: {:?}", e);
-                        break;
-                    }
-                    None => {
-                        info!("Peer {:?} disconnected", peer_addr);
+                        Some(Err(e)) => {
+                            warn!("Failed to read message from peer: {:?}", e);
+                            // Framing errors such as an overlong line end the stream, so this is always the last message.
+                            conn.protocol_error(ProtocolErrorKind::Parse, peer_addr, &metrics, max_protocol_errors);
+                            break;
+                        }
+                        None => {
+                            info!("Peer {:?} disconnected", peer_addr);
+                            break;
+                        }
+                    },
+                    _ = tokio::time::sleep(dead_connection_timeout) => {
+                        info!("Peer {:?} timed out", peer_addr);
                         break;
-                    }
-                },
-                _ = tokio::time::sleep(PEER_COMM_TIMEOUT) => {
-                    info!("Peer {:?} timed out", peer_addr);
-                    break;
-                },
+                    },
+                }
             }
+        };
+        tokio::select! {
+            _ = session => {}
+            _ = close.notified() => info!("Closing connection to peer {:?}", peer_addr),
         }
         if let Err(e) = server_sender.send(ServerMessage::ProverDisconnected(peer_addr)).await {
             error!("Failed to send ProverDisconnected message to server: {}", e);
@@ -192,6 +487,7 @@ impl Connection {
     pub async fn handshake(
         framed: &mut Framed<TcpStream, StratumCodec>,
         pool_address: String,
+        supported_versions: &[Version],
     ) -> Result<(String, Version)> {
         let peer_addr = framed.get_ref().peer_addr()?;
         match timeout(PEER_HANDSHAKE_TIMEOUT, framed.next()).await {
@@ -218,8 +514,24 @@ impl Connection {
                             );
                             e
                         })?;
-                        if version < MIN_SUPPORTED_VERSION || version > MAX_SUPPORTED_VERSION {
-                            warn!("Unsupported protocol version {} from peer {:?}", version, peer_addr);
+                        if !supported_versions.contains(&version) {
+                            warn!(
+                                "Unsupported protocol version {} from peer {:?} ({})",
+                                protocol_version, peer_addr, user_agent
+                            );
+                            let supported = supported_versions
+                                .iter()
+                                .map(|version| format!("AleoStratum/{}", version))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+                            let error = Error::with_custom_msg(
+                                ErrorCode::from_code(20),
+                                &format!(
+                                    "Unsupported protocol version {}, supported: {}",
+                                    protocol_version, supported
+                                ),
+                            );
+                            framed.send(StratumMessage::Response(id, None, Some(error))).await?;
                             return Err(anyhow!("Unsupported protocol version"));
                         }
                         let response_params: Vec<Box<dyn BoxedType>> = vec![
@@ -257,21 +569,39 @@ impl Connection {
         }
     }
 
-    pub async fn authorize(framed: &mut Framed<TcpStream, StratumCodec>) -> Result<Address<Testnet3>> {
+    /// Returns the address and worker name of the peer's authorization, which is only answered with `true` once
+    /// the password passed `authenticator`.
+    pub async fn authorize(
+        framed: &mut Framed<TcpStream, StratumCodec>,
+        authenticator: Option<&ProverAuthenticator>,
+    ) -> Result<(Address<Testnet3>, String)> {
         let peer_addr = framed.get_ref().peer_addr()?;
         match timeout(PEER_HANDSHAKE_TIMEOUT, framed.next()).await {
             Ok(Some(Ok(message))) => {
                 trace!("Received message {} from peer {:?}", message.name(), peer_addr);
                 match message {
-                    StratumMessage::Authorize(id, address, _) => {
-                        let address = Address::<Testnet3>::from_str(address.as_str()).map_err(|e| {
+                    StratumMessage::Authorize(id, username, password) => {
+                        let (address, worker_name) = parse_username(&username);
+                        let address = Address::<Testnet3>::from_str(address).map_err(|e| {
                             warn!("Invalid address {} from peer {:?}: {:?}", address, peer_addr, e);
                             e
                         })?;
+                        let token_valid =
+                            authenticator.map_or(true, |authenticator| authenticator.token_valid(&address, &password));
+                        if !token_valid {
+                            warn!(
+                                "Peer {:?} sent an invalid authentication token for {}",
+                                peer_addr, address
+                            );
+                            let error =
+                                Error::with_custom_msg(ErrorCode::from_code(24), "Invalid authentication token");
+                            framed.send(StratumMessage::Response(id, None, Some(error))).await?;
+                            return Err(anyhow!("Invalid authentication token"));
+                        }
                         framed
                             .send(StratumMessage::Response(id, Some(ResponseParams::Bool(true)), None))
                             .await?;
-                        Ok(address)
+                        Ok((address, worker_name.to_string()))
                     }
                     _ => {
                         warn!("Peer {:?} sent {} before authorizing", peer_addr, message.name());
@@ -293,4 +623,82 @@ impl Connection {
             }
         }
     }
+
+    /// Decodes a `(job_id, nonce, commitment, proof)` submission. `Err(Some(..))` is the error to answer the
+    /// prover with, `Err(None)` means the connection should be closed.
+    fn decode_submission(
+        &mut self,
+        peer_addr: SocketAddr,
+        metrics: &Metrics,
+        max_deserialization_errors: u32,
+        max_protocol_errors: u32,
+        (job_id, nonce, commitment, proof): (String, String, String, String),
+    ) -> Result<SubmittedSolution, Option<Error<()>>> {
+        let epoch_number = match decode_job_id(&job_id) {
+            Some(epoch_number) => epoch_number,
+            None => {
+                warn!("Invalid job_id {} from peer {:?}", job_id, peer_addr);
+                if self.protocol_error(ProtocolErrorKind::Logic, peer_addr, metrics, max_protocol_errors) {
+                    return Err(None);
+                }
+                return Err(Some(Error::with_custom_msg(ErrorCode::from_code(20), "Invalid job id")));
+            }
+        };
+        let nonce = match validate_nonce(&nonce) {
+            Some(nonce) => nonce,
+            None => {
+                warn!("Invalid nonce {} from peer {:?}", nonce, peer_addr);
+                if self.protocol_error(ProtocolErrorKind::Logic, peer_addr, metrics, max_protocol_errors) {
+                    return Err(None);
+                }
+                return Err(Some(Error::with_custom_msg(
+                    ErrorCode::from_code(22),
+                    "Invalid nonce format",
+                )));
+            }
+        };
+        let (commitment, proof) = match decode_solution(&commitment, &proof) {
+            Ok(solution) => solution,
+            Err(kind) => {
+                self.deserialization_errors += 1;
+                metrics.proof_deserialization_error(kind.as_str());
+                warn!("Failed to deserialize solution from peer {:?}: {:?}", peer_addr, kind);
+                if self.deserialization_errors > max_deserialization_errors {
+                    warn!(
+                        "Peer {:?} sent {} undecodable solutions, disconnecting",
+                        peer_addr, self.deserialization_errors
+                    );
+                    return Err(None);
+                }
+                return Err(Some(Error::with_custom_msg(ErrorCode::from_code(20), "Invalid proof")));
+            }
+        };
+        Ok((epoch_number, nonce, commitment, proof))
+    }
+
+    /// Counts a malformed message and returns whether the connection has to be closed for it.
+    fn protocol_error(
+        &mut self,
+        kind: ProtocolErrorKind,
+        peer_addr: SocketAddr,
+        metrics: &Metrics,
+        max_protocol_errors: u32,
+    ) -> bool {
+        metrics.protocol_error(kind.as_str());
+        let count = self.protocol_errors.entry(kind).or_default();
+        *count += 1;
+        if *count < max_protocol_errors {
+            return false;
+        }
+        let count_of = |kind| self.protocol_errors.get(&kind).copied().unwrap_or(0);
+        warn!(
+            "Peer {:?} sent too many {} errors, disconnecting (parse: {}, protocol: {}, logic: {})",
+            peer_addr,
+            kind.as_str(),
+            count_of(ProtocolErrorKind::Parse),
+            count_of(ProtocolErrorKind::Protocol),
+            count_of(ProtocolErrorKind::Logic)
+        );
+        true
+    }
 }
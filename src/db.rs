@@ -69,24 +69,40 @@ impl DB {
         &self,
         commitment: PuzzleCommitment<Testnet3>,
         shares: HashMap<String, u64>,
+        fee_address: Option<String>,
+        tenant_fees: HashMap<String, (String, f64)>,
     ) -> Result<()> {
         let mut conn = self.connection_pool.get().await?;
         let transaction = conn.transaction().await?;
 
         let solution_id: i32 = transaction
             .query_one(
-                "INSERT INTO solution (commitment) VALUES ($1) RETURNING id",
-                &[&commitment.to_string()],
+                "INSERT INTO solution (commitment, fee_address) VALUES ($1, $2) RETURNING id",
+                &[&commitment.to_string(), &fee_address],
             )
             .await?
             .try_get("id")?;
 
         let stmt = transaction
-            .prepare_cached("INSERT INTO share (solution_id, address, share) VALUES ($1, $2, $3)")
+            .prepare_cached(
+                "INSERT INTO share (solution_id, address, share, fee_address, fee_percent) VALUES ($1, $2, $3, $4, $5)",
+            )
             .await?;
         for (address, share) in shares {
+            let tenant_fee = tenant_fees.get(&address);
+            let tenant_fee_address = tenant_fee.map(|(fee_address, _)| fee_address);
+            let tenant_fee_percent = tenant_fee.map(|(_, fee_percent)| *fee_percent);
             transaction
-                .query(&stmt, &[&solution_id, &address, &(share as i64)])
+                .query(
+                    &stmt,
+                    &[
+                        &solution_id,
+                        &address,
+                        &(share as i64),
+                        &tenant_fee_address,
+                        &tenant_fee_percent,
+                    ],
+                )
                 .await?;
         }
 
@@ -94,6 +110,40 @@ impl DB {
         Ok(())
     }
 
+    /// Records the extra data provers attached to shares, for later analysis, as
+    /// `(commitment, address, value, extra_data)` keyed by the commitment of each share.
+    pub async fn save_share_extra_data(&self, shares: Vec<(String, String, u64, Vec<u8>)>) -> Result<()> {
+        let mut commitments = Vec::with_capacity(shares.len());
+        let mut addresses = Vec::with_capacity(shares.len());
+        let mut values = Vec::with_capacity(shares.len());
+        let mut extra_data = Vec::with_capacity(shares.len());
+        for (commitment, address, value, data) in shares {
+            commitments.push(commitment);
+            addresses.push(address);
+            values.push(value as i64);
+            extra_data.push(data);
+        }
+        let conn = self.connection_pool.get().await?;
+        let stmt = conn
+            .prepare_cached(
+                "INSERT INTO share_extra_data (commitment, address, value, extra_data) SELECT * FROM \
+                 UNNEST($1::text[], $2::text[], $3::bigint[], $4::bytea[]) ON CONFLICT (commitment) DO NOTHING",
+            )
+            .await?;
+        conn.query(&stmt, &[&commitments, &addresses, &values, &extra_data])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn save_round_histogram(&self, height: u32, histogram: &str) -> Result<()> {
+        let conn = self.connection_pool.get().await?;
+        let stmt = conn
+            .prepare_cached("INSERT INTO round_histogram (height, histogram) VALUES ($1, $2)")
+            .await?;
+        conn.query(&stmt, &[&(height as i64), &histogram]).await?;
+        Ok(())
+    }
+
     pub async fn set_solution_valid(
         &self,
         commitment: &String,
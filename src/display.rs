@@ -0,0 +1,34 @@
+const HASHRATE_UNITS: [&str; 6] = ["H/s", "KH/s", "MH/s", "GH/s", "TH/s", "PH/s"];
+
+/// Formats a speed with 3 significant figures in the largest unit that keeps it at least 1, e.g. `12.3 MH/s`.
+pub fn format_hashrate(hashes_per_sec: f64) -> String {
+    let mut value = hashes_per_sec;
+    let mut unit = 0;
+    // 999.5 would round up to 1000, so it is shown as 1.00 of the next unit instead.
+    while value >= 999.5 && unit < HASHRATE_UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    let decimals = if value >= 99.95 {
+        0
+    } else if value >= 9.995 {
+        1
+    } else {
+        2
+    };
+    format!("{:.*} {}", decimals, value, HASHRATE_UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scales_to_three_significant_figures() {
+        assert_eq!(format_hashrate(0.0), "0.00 H/s");
+        assert_eq!(format_hashrate(123.4), "123 H/s");
+        assert_eq!(format_hashrate(12_345_678.3), "12.3 MH/s");
+        assert_eq!(format_hashrate(999_700.0), "1.00 MH/s");
+        assert_eq!(format_hashrate(2.5e18), "2500 PH/s");
+    }
+}
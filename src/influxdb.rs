@@ -0,0 +1,87 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use reqwest::Client;
+use tokio::{task, time::sleep};
+use tracing::{debug, info, warn};
+
+use crate::Server;
+
+static PUSH_INTERVAL: Duration = Duration::from_secs(60);
+static PUSH_ATTEMPTS: u32 = 3;
+static RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Periodically pushes pool and address measurements to InfluxDB using the line protocol.
+pub fn start(url: String, token: Option<String>, server: Arc<Server>) {
+    task::spawn(async move {
+        info!("Pushing statistics to InfluxDB at {}", url);
+        let client = Client::new();
+        let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string());
+        let mut ticker = tokio::time::interval(PUSH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            let body = measurements(&server, &host).await;
+            for attempt in 1..=PUSH_ATTEMPTS {
+                match push(&client, &url, token.as_deref(), body.clone()).await {
+                    Ok(()) => {
+                        debug!("Pushed statistics to InfluxDB");
+                        break;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to push statistics to InfluxDB (attempt {}/{}): {}",
+                            attempt, PUSH_ATTEMPTS, e
+                        );
+                        if attempt < PUSH_ATTEMPTS {
+                            sleep(RETRY_DELAY).await;
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+async fn measurements(server: &Server, host: &str) -> String {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let mut lines = vec![format!(
+        "pool_stats,host={} provers={}i,addresses={}i,hashrate_5m={},blocks_found={}i {}",
+        escape_tag(host),
+        server.online_provers().await,
+        server.online_addresses().await,
+        server.pool_speed().await[0],
+        server.pool_solutions_found().await,
+        timestamp
+    )];
+    for address in server.online_address_list().await {
+        lines.push(format!(
+            "address_stats,host={},address={} provers={}i,hashrate_5m={} {}",
+            escape_tag(host),
+            address,
+            server.address_prover_count(address).await,
+            server.address_speed(address).await[0],
+            timestamp
+        ));
+    }
+    lines.join("\n")
+}
+
+async fn push(client: &Client, url: &str, token: Option<&str>, body: String) -> Result<()> {
+    let mut request = client.post(url).body(body);
+    if let Some(token) = token {
+        request = request.header("Authorization", format!("Token {}", token));
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Escapes the characters that are special in line protocol tag values.
+fn escape_tag(value: &str) -> String {
+    value.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
@@ -1,13 +1,19 @@
 mod accounting;
 mod api;
+mod audit;
+mod config;
 mod connection;
+mod display;
+mod influxdb;
+mod metrics;
 mod server;
+mod stats_export;
 mod validator_peer;
 
 #[cfg(feature = "db")]
 mod db;
 
-use std::sync::Arc;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use clap::Parser;
 use futures::stream::StreamExt;
@@ -15,7 +21,7 @@ use rand::seq::SliceRandom;
 use signal_hook::consts::{SIGABRT, SIGHUP, SIGINT, SIGQUIT, SIGTERM, SIGTSTP, SIGUSR1};
 use signal_hook_tokio::Signals;
 use snarkvm::{console::account::address::Address, prelude::Testnet3};
-use tokio::sync::mpsc::Sender;
+use tokio::sync::{mpsc::Sender, oneshot};
 use tracing::{debug, error, info, warn};
 use tracing_log::{log, LogTracer};
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter};
@@ -23,10 +29,24 @@ use tracing_subscriber::{layer::SubscriberExt, EnvFilter};
 use crate::validator_peer::Node;
 use crate::{
     accounting::{Accounting, AccountingMessage},
+    config::{
+        load_config_file,
+        MisbehaviorConfig,
+        PerformanceScoreConfig,
+        ServerConfig,
+        SpeedometerConfig,
+        TcpOptions,
+    },
     //    operator_peer::Node,
     server::{Server, ServerMessage},
 };
 
+/// Least number of previous epochs whose solutions are accepted in testnet mode.
+static TESTNET_STALE_ACCEPT_TOLERANCE_EPOCHS: u32 = 5;
+
+/// Grafana dashboard for the metrics served at `/metrics`.
+static GRAFANA_DASHBOARD: &str = include_str!("../dashboards/pool.json");
+
 #[derive(Debug, Parser)]
 #[clap(name = "pool_server", about = "Aleo mining pool server")]
 struct Opt {
@@ -38,9 +58,17 @@ struct Opt {
     #[clap(short, long)]
     address: Address<Testnet3>,
 
-    /// Port to listen for incoming provers
-    #[clap(short, long)]
-    port: u16,
+    /// Address credited with the pool fee of found solutions, overriding any rotating fee wallet
+    #[clap(long = "pool-fee-address")]
+    pool_fee_address: Option<Address<Testnet3>>,
+
+    /// Port to listen for incoming provers on all interfaces
+    #[clap(short, long, required_unless_present = "listen")]
+    port: Option<u16>,
+
+    /// Address to listen for incoming provers on, may be repeated; replaces --port
+    #[clap(long = "listen")]
+    listen: Vec<SocketAddr>,
 
     /// API port
     #[clap(short, long = "api-port")]
@@ -57,11 +85,332 @@ struct Opt {
     /// Output log to file
     #[clap(long)]
     log: Option<String>,
+
+    /// Initial capacity of the seen nonce set
+    #[clap(long = "nonce-cache-capacity", default_value_t = 10 << 20)]
+    nonce_cache_capacity: usize,
+
+    /// Number of proof verification results remembered so resubmitted proofs are not verified again
+    #[clap(long = "proof-cache-capacity", default_value_t = 4096)]
+    proof_cache_capacity: usize,
+
+    /// Include difficulty and hashrate details in accepted share responses
+    #[clap(long = "extended-share-response")]
+    extended_share_response: bool,
+
+    /// Estimated network block time in seconds, used for earnings forecasts and difficulty logging
+    #[clap(long = "estimated-block-time", default_value_t = 25)]
+    estimated_block_time: u64,
+
+    /// Expected reward per block, used for earnings forecasts
+    #[clap(long = "expected-block-reward")]
+    expected_block_reward: Option<u64>,
+
+    /// Lowest share difficulty that will ever be assigned to a prover
+    #[clap(long = "min-prover-difficulty", default_value_t = 1000)]
+    min_prover_difficulty: u64,
+
+    /// Lowest proof target real hardware can reach, which caps the share difficulty at u64::MAX / this
+    #[clap(long = "min-valid-target", default_value_t = 1024)]
+    min_valid_target: u64,
+
+    /// Seconds without a new epoch challenge before the pool is reported unhealthy
+    #[clap(long = "stale-template-warn-threshold", default_value_t = 120)]
+    stale_template_warn_threshold: u64,
+
+    /// PPLNS window size in multiples of the network proof target
+    #[clap(long = "shares-per-block-target", default_value_t = 5)]
+    shares_per_block_target: u64,
+
+    /// Seconds without any message from a prover before its connection is closed
+    #[clap(long = "dead-connection-timeout", default_value_t = 300)]
+    dead_connection_timeout: u64,
+
+    /// Seconds to a new prover's first accepted share above which its initial difficulty is reported as too high
+    #[clap(long = "ttfs-warn-threshold", default_value_t = 60)]
+    ttfs_warn_threshold: u64,
+
+    /// Seconds without an accepted share before a prover is reported idle
+    #[clap(long = "idle-timeout", default_value_t = 1800)]
+    idle_timeout: u64,
+
+    /// Disconnect idle provers instead of only logging a warning
+    #[clap(long = "disconnect-idle-provers")]
+    disconnect_idle_provers: bool,
+
+    /// Ignore epoch challenges with a proof target below this value
+    #[clap(long = "min-network-proof-target")]
+    min_network_proof_target: Option<u64>,
+
+    /// Number of shares buffered before they are sent to accounting
+    #[clap(long = "accounting-batch-size", default_value_t = 100)]
+    accounting_batch_size: usize,
+
+    /// Milliseconds after which buffered shares are sent to accounting regardless of batch size
+    #[clap(long = "accounting-batch-interval", default_value_t = 1000)]
+    accounting_batch_interval_ms: u64,
+
+    /// Number of previous epochs whose solutions are still accepted
+    #[clap(long = "stale-accept-tolerance-epochs", default_value_t = 0)]
+    stale_accept_tolerance_epochs: u32,
+
+    /// Multiplier applied to the credit of solutions accepted from a previous epoch
+    #[clap(long = "stale-penalty-factor", default_value_t = 0.5)]
+    stale_penalty_factor: f64,
+
+    /// Minutes of pool size history kept for the history API
+    #[clap(long = "history-retention-minutes", default_value_t = 1440)]
+    history_retention_minutes: usize,
+
+    /// Seconds spent notifying provers of a planned restart before shutting down
+    #[clap(long = "restart-notify-timeout", default_value_t = 5)]
+    restart_notify_timeout: u64,
+
+    /// Undecodable solutions accepted from a single connection before disconnecting it
+    #[clap(long = "max-deserialization-errors", default_value_t = 10)]
+    max_deserialization_errors: u32,
+
+    /// Malformed messages of a single kind accepted from a single connection before disconnecting it
+    #[clap(long = "max-protocol-errors", default_value_t = 10)]
+    max_protocol_errors: u32,
+
+    /// Alarm when the 5 minute pool speed drops below this percentage of the 1 hour speed, 0 to disable
+    #[clap(long = "hashrate-drop-alarm-threshold", default_value_t = 50.0)]
+    hashrate_drop_alarm_threshold: f64,
+
+    /// Webhook URL to POST to when the hashrate drop alarm goes off
+    #[clap(long = "alarm-webhook-url")]
+    alarm_webhook_url: Option<String>,
+
+    /// File to append prover authentication events to, rotated daily
+    #[clap(long = "audit-log")]
+    audit_log: Option<PathBuf>,
+
+    /// CSV file to append the stats of active provers to, rotated daily
+    #[clap(long = "prover-stats-export")]
+    prover_stats_export: Option<PathBuf>,
+
+    /// Seconds between prover stats exports
+    #[clap(long = "prover-stats-export-interval", default_value_t = 300)]
+    prover_stats_export_interval: u64,
+
+    /// Comma separated CPU cores to pin proof verification threads to
+    #[clap(long = "compute-cores", value_delimiter = ',')]
+    compute_cores: Vec<usize>,
+
+    /// Milliseconds a proof verification may take on the compute cores before it is retried on another thread
+    #[clap(long = "verification-timeout", default_value_t = 1000)]
+    verification_timeout: u64,
+
+    /// Share of verifications retried off the compute cores above which a warning is logged
+    #[clap(long = "verification-fallback-warn-ratio", default_value_t = 0.05)]
+    verification_fallback_warn_ratio: f64,
+
+    /// Seconds without any submission before a prover's target is lowered, 0 to disable
+    #[clap(long = "difficulty-fallback-idle", default_value_t = 300)]
+    difficulty_fallback_idle: u64,
+
+    /// Factor applied to the target of a prover that stopped submitting
+    #[clap(long = "difficulty-fallback-factor", default_value_t = 0.5)]
+    difficulty_fallback_factor: f64,
+
+    /// Consecutive target reductions for a prover that stopped submitting
+    #[clap(long = "difficulty-fallback-max-reductions", default_value_t = 3)]
+    difficulty_fallback_max_reductions: u32,
+
+    /// Growth of the 1 minute share rate between two checks 30 seconds apart that raises the global difficulty
+    /// modifier right away instead of at the next epoch
+    #[clap(long = "spike-detection-threshold", default_value_t = 2.0)]
+    spike_detection_threshold: f64,
+
+    /// Seconds to wait for a prover connection to accept a message before dropping the prover
+    #[clap(long = "response-send-timeout", default_value_t = 10)]
+    response_send_timeout: u64,
+
+    /// Seconds a prover's message channel may stay full before the prover is disconnected
+    #[clap(long = "backpressure-timeout", default_value_t = 60)]
+    backpressure_timeout: u64,
+
+    /// Seconds without a message from the validator, e.g. on a half-open connection or a congested send, before
+    /// it is reconnected. The validator answers pings every 5 seconds, and at least 15 seconds are allowed
+    #[clap(long = "validator-timeout", default_value_t = 60)]
+    validator_timeout: u64,
+
+    /// Double every timeout, accept solutions up to 5 epochs old and log stale or duplicate solutions at debug level
+    #[clap(long = "testnet-mode")]
+    testnet_mode: bool,
+
+    /// Most solutions a prover may submit in a single batch
+    #[clap(long = "max-batch-size", default_value_t = 10)]
+    max_batch_size: usize,
+
+    /// Solutions buffered for the validator before new ones are dropped
+    #[clap(long = "max-queued-solutions", default_value_t = 16)]
+    max_queued_solutions: usize,
+
+    /// Warn when the validator takes longer than this many milliseconds to acknowledge a solution
+    #[clap(long = "max-solution-submit-latency", default_value_t = 500)]
+    max_solution_submit_latency: u64,
+
+    /// Most provers connected at once; further connections are refused with a "Pool full" error
+    #[clap(long = "max-provers")]
+    max_provers: Option<usize>,
+
+    /// Disconnect a prover after this many accepted shares so it reconnects with a fresh session
+    #[clap(long = "max-shares-per-session")]
+    max_shares_per_session: Option<u64>,
+
+    /// Connections per second from one monitored subnet over the last minute above which its new connections are
+    /// refused, 0 to disable
+    #[clap(long = "connection-flood-threshold", default_value_t = 50.0)]
+    connection_flood_threshold: f64,
+
+    /// Comma separated bucket boundaries of the share difficulty histogram, powers of 10 by default
+    #[clap(long = "difficulty-histogram-buckets", value_delimiter = ',')]
+    difficulty_histogram_buckets: Vec<u64>,
+
+    /// Finished prover sessions remembered for each address
+    #[clap(long = "max-sessions-per-address", default_value_t = 1000)]
+    max_sessions_per_address: usize,
+
+    /// Close older connections of a prover that reconnects from the same IP with the same worker name.
+    /// Provers without a worker name are never treated as duplicates, as several rigs may share the default one
+    #[clap(long = "close-duplicate-connections")]
+    close_duplicate_connections: bool,
+
+    /// Prefix length IPv4 prover connections are grouped by in the subnet stats
+    #[clap(long = "subnet-monitoring-prefix-len", default_value_t = 24)]
+    subnet_monitoring_prefix_len: u8,
+
+    /// Prefix length IPv6 prover connections are grouped by in the subnet stats
+    #[clap(long = "subnet-monitoring-prefix-len-v6", default_value_t = 48)]
+    subnet_monitoring_prefix_len_v6: u8,
+
+    /// Require provers to send an HMAC-SHA256 token of "{address}{block_height}{secret}" as their password
+    #[clap(long = "require-authentication", requires = "authentication_secret")]
+    require_authentication: bool,
+
+    /// Secret shared with the provers of a private pool, used to check their authentication tokens
+    #[clap(long = "authentication-secret")]
+    authentication_secret: Option<String>,
+
+    /// Token admin API requests have to send as "Authorization: Bearer <token>"
+    #[clap(long = "admin-token")]
+    admin_token: Option<String>,
+
+    /// Seconds an admin token replaced by a rotation stays valid
+    #[clap(long = "token-rotation-grace", default_value_t = 300)]
+    token_rotation_grace: u64,
+
+    /// Comma separated AleoStratum protocol versions provers may subscribe with
+    #[clap(long = "supported-stratum-versions", value_delimiter = ',', default_value = "2.0.0,2.1.0")]
+    supported_stratum_versions: Vec<String>,
+
+    /// Window in seconds of the speed each prover's share difficulty follows
+    #[clap(long = "speed-target-window", default_value_t = 120)]
+    speed_target_window: u64,
+
+    /// Seconds the 5 minute to 1 hour prover speeds are cached between recalculations
+    #[clap(long = "speed-cache-interval", default_value_t = 30)]
+    speed_cache_interval: u64,
+
+    /// TOML file with [address.<aleo_address>] sections, [[fee_wallet]] and [[tenant]] entries and [error_messages]
+    #[clap(long = "config-file")]
+    config_file: Option<PathBuf>,
+
+    /// Misbehavior score added for a stale share
+    #[clap(long = "misbehavior-stale-penalty", default_value_t = 1)]
+    misbehavior_stale_penalty: u32,
+
+    /// Misbehavior score added for a share below the assigned difficulty
+    #[clap(long = "misbehavior-low-difficulty-penalty", default_value_t = 5)]
+    misbehavior_low_difficulty_penalty: u32,
+
+    /// Misbehavior score added for an invalid proof
+    #[clap(long = "misbehavior-invalid-proof-penalty", default_value_t = 10)]
+    misbehavior_invalid_proof_penalty: u32,
+
+    /// Misbehavior score added for a duplicate nonce
+    #[clap(long = "misbehavior-duplicate-nonce-penalty", default_value_t = 20)]
+    misbehavior_duplicate_nonce_penalty: u32,
+
+    /// Misbehavior score at which a warning is logged
+    #[clap(long = "misbehavior-warn-threshold", default_value_t = 50)]
+    misbehavior_warn_threshold: u32,
+
+    /// Misbehavior score at which the prover's submission rate is throttled
+    #[clap(long = "misbehavior-throttle-threshold", default_value_t = 100)]
+    misbehavior_throttle_threshold: u32,
+
+    /// Misbehavior score at which the prover is disconnected
+    #[clap(long = "misbehavior-disconnect-threshold", default_value_t = 200)]
+    misbehavior_disconnect_threshold: u32,
+
+    /// Misbehavior score at which the prover's IP is banned
+    #[clap(long = "misbehavior-ban-threshold", default_value_t = 500)]
+    misbehavior_ban_threshold: u32,
+
+    /// Seconds an IP stays banned after reaching the ban threshold
+    #[clap(long = "misbehavior-ban-duration", default_value_t = 86400)]
+    misbehavior_ban_duration: u64,
+
+    /// Weight of pool luck in the performance score
+    #[clap(long = "performance-luck-weight", default_value_t = 0.4)]
+    performance_luck_weight: f64,
+
+    /// Weight of the accepted share fraction in the performance score
+    #[clap(long = "performance-efficiency-weight", default_value_t = 0.4)]
+    performance_efficiency_weight: f64,
+
+    /// Weight of the 5 minute pool speed against the target speed in the performance score
+    #[clap(long = "performance-hashrate-weight", default_value_t = 0.2)]
+    performance_hashrate_weight: f64,
+
+    /// Pool speed counting fully towards the performance score, always counted fully when not set
+    #[clap(long = "performance-target-hashrate")]
+    performance_target_hashrate: Option<f64>,
+
+    /// Disable TCP_NODELAY on prover connections
+    #[clap(long = "no-tcp-nodelay")]
+    no_tcp_nodelay: bool,
+
+    /// Seconds a prover connection is idle before keepalive probes are sent
+    #[clap(long = "tcp-keepalive-idle", default_value_t = 30)]
+    tcp_keepalive_idle: u64,
+
+    /// Seconds between keepalive probes
+    #[clap(long = "tcp-keepalive-interval", default_value_t = 10)]
+    tcp_keepalive_interval: u64,
+
+    /// Unanswered keepalive probes before a prover connection is dropped
+    #[clap(long = "tcp-keepalive-retries", default_value_t = 3)]
+    tcp_keepalive_retries: u32,
+
+    /// InfluxDB write URL to push statistics to, including the bucket or database parameters
+    #[clap(long = "influxdb-push-url")]
+    influxdb_push_url: Option<String>,
+
+    /// Token used to authenticate to InfluxDB
+    #[clap(long = "influxdb-token")]
+    influxdb_token: Option<String>,
+
+    /// Serve the debug API on the loopback interface
+    #[clap(long = "enable-debug-api")]
+    enable_debug_api: bool,
+
+    /// Port for the debug API, only used with --enable-debug-api
+    #[clap(long = "debug-api-port", default_value_t = 9000)]
+    debug_api_port: u16,
 }
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
+    // Checked before parsing so the dashboard can be printed without the otherwise required options.
+    if std::env::args().any(|arg| arg == "--generate-grafana-dashboard") {
+        print!("{}", GRAFANA_DASHBOARD);
+        return;
+    }
     let opt = Opt::parse();
     let tracing_level = if opt.trace {
         tracing::Level::TRACE
@@ -119,20 +468,164 @@ async fn main() {
             bootstrap.choose(&mut rand::thread_rng()).unwrap().to_string()
         }
     };
-    let port = opt.port;
+    let listen_addrs = if opt.listen.is_empty() {
+        let port = opt.port.expect("--port is required without --listen");
+        vec![SocketAddr::from(([0, 0, 0, 0], port))]
+    } else {
+        opt.listen
+    };
 
     let address = opt.address;
 
-    let accounting = Accounting::init();
+    // Test networks produce blocks irregularly, so give provers and shares more slack there.
+    let timeout_scale = if opt.testnet_mode { 2 } else { 1 };
+
+    let node = Node::init(validator, opt.validator_timeout.max(1) * timeout_scale);
+    let stale_accept_tolerance_epochs = if opt.testnet_mode {
+        opt.stale_accept_tolerance_epochs
+            .max(TESTNET_STALE_ACCEPT_TOLERANCE_EPOCHS)
+    } else {
+        opt.stale_accept_tolerance_epochs
+    };
+
+    let mut difficulty_histogram_bounds = opt.difficulty_histogram_buckets;
+    if difficulty_histogram_bounds.is_empty() {
+        difficulty_histogram_bounds = (1..=19).map(|exponent| 10u64.pow(exponent)).collect();
+    }
+    difficulty_histogram_bounds.sort_unstable();
+    difficulty_histogram_bounds.dedup();
 
-    let node = Node::init(validator);
+    if let Some(version) = opt
+        .supported_stratum_versions
+        .iter()
+        .find(|version| semver::Version::parse(version).is_err())
+    {
+        error!("Invalid supported stratum version {}", version);
+        std::process::exit(1);
+    }
 
-    let server = Server::init(port, address, node.sender(), accounting.sender()).await;
+    if let Some(target_hashrate) = opt.performance_target_hashrate {
+        if !target_hashrate.is_finite() || target_hashrate <= 0.0 {
+            error!("Invalid performance target hashrate {}", target_hashrate);
+            std::process::exit(1);
+        }
+    }
+
+    let file_config = match &opt.config_file {
+        Some(path) => match load_config_file(path) {
+            Ok(file_config) => file_config,
+            Err(e) => {
+                error!("Unable to load config file {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        None => Default::default(),
+    };
+
+    let accounting = Accounting::init(file_config.tenants.clone());
+
+    let config = ServerConfig {
+        nonce_cache_capacity: opt.nonce_cache_capacity,
+        proof_cache_capacity: opt.proof_cache_capacity,
+        extended_share_response: opt.extended_share_response,
+        estimated_block_time_secs: opt.estimated_block_time.max(1),
+        expected_block_reward: opt.expected_block_reward,
+        min_prover_difficulty: opt.min_prover_difficulty.max(1),
+        min_valid_target: opt.min_valid_target.max(1),
+        stale_template_warn_threshold_secs: opt.stale_template_warn_threshold * timeout_scale,
+        shares_per_block_target: opt.shares_per_block_target.max(1),
+        dead_connection_timeout_secs: opt.dead_connection_timeout * timeout_scale,
+        ttfs_warn_threshold_secs: opt.ttfs_warn_threshold * timeout_scale,
+        idle_timeout_secs: opt.idle_timeout * timeout_scale,
+        disconnect_idle_provers: opt.disconnect_idle_provers,
+        min_network_proof_target: opt.min_network_proof_target,
+        accounting_batch_size: opt.accounting_batch_size.max(1),
+        accounting_batch_interval_ms: opt.accounting_batch_interval_ms.max(1),
+        stale_accept_tolerance_epochs,
+        stale_penalty_factor: opt.stale_penalty_factor.clamp(0.0, 1.0),
+        history_retention_minutes: opt.history_retention_minutes,
+        restart_notify_timeout_secs: opt.restart_notify_timeout * timeout_scale,
+        max_deserialization_errors_per_session: opt.max_deserialization_errors,
+        max_protocol_errors_before_disconnect: opt.max_protocol_errors.max(1),
+        hashrate_drop_alarm_threshold_percent: opt.hashrate_drop_alarm_threshold,
+        alarm_webhook_url: opt.alarm_webhook_url,
+        audit_log_path: opt.audit_log,
+        prover_stats_export_path: opt.prover_stats_export,
+        prover_stats_export_interval_secs: opt.prover_stats_export_interval.max(1),
+        compute_cores: Some(opt.compute_cores).filter(|cores| !cores.is_empty()),
+        verification_timeout_ms: opt.verification_timeout.max(1) * timeout_scale,
+        verification_fallback_warn_ratio: opt.verification_fallback_warn_ratio,
+        difficulty_fallback_idle_secs: opt.difficulty_fallback_idle * timeout_scale,
+        difficulty_fallback_factor: opt.difficulty_fallback_factor.clamp(0.0, 1.0),
+        difficulty_fallback_max_reductions: opt.difficulty_fallback_max_reductions,
+        spike_detection_threshold: opt.spike_detection_threshold.max(1.0),
+        response_send_timeout_secs: opt.response_send_timeout * timeout_scale,
+        backpressure_timeout_secs: opt.backpressure_timeout * timeout_scale,
+        testnet_mode: opt.testnet_mode,
+        max_batch_size: opt.max_batch_size.max(1),
+        max_queued_solutions: opt.max_queued_solutions.max(1),
+        max_solution_submit_latency_ms: opt.max_solution_submit_latency,
+        max_provers: opt.max_provers,
+        max_shares_per_session: opt.max_shares_per_session.map(|max| max.max(1)),
+        connection_flood_threshold: opt.connection_flood_threshold.max(0.0),
+        difficulty_histogram_bounds,
+        max_sessions_per_address: opt.max_sessions_per_address.max(1),
+        close_duplicate_connections: opt.close_duplicate_connections,
+        subnet_monitoring_prefix_len: opt.subnet_monitoring_prefix_len.min(32),
+        subnet_monitoring_prefix_len_v6: opt.subnet_monitoring_prefix_len_v6.min(128),
+        require_authentication: opt.require_authentication,
+        authentication_secret: opt.authentication_secret,
+        admin_token: opt.admin_token,
+        token_rotation_grace_secs: opt.token_rotation_grace,
+        speedometer: SpeedometerConfig {
+            target_window_secs: opt.speed_target_window.max(1),
+            cache_interval_secs: opt.speed_cache_interval,
+        },
+        address_configs: file_config.address_configs,
+        fee_wallets: file_config.fee_wallets,
+        tenants: file_config.tenants,
+        pool_fee_address: opt.pool_fee_address,
+        error_messages: file_config.error_messages,
+        supported_stratum_versions: opt.supported_stratum_versions,
+        misbehavior: MisbehaviorConfig {
+            stale_penalty: opt.misbehavior_stale_penalty,
+            low_difficulty_penalty: opt.misbehavior_low_difficulty_penalty,
+            invalid_proof_penalty: opt.misbehavior_invalid_proof_penalty,
+            duplicate_nonce_penalty: opt.misbehavior_duplicate_nonce_penalty,
+            warn_threshold: opt.misbehavior_warn_threshold,
+            throttle_threshold: opt.misbehavior_throttle_threshold,
+            disconnect_threshold: opt.misbehavior_disconnect_threshold,
+            ban_threshold: opt.misbehavior_ban_threshold,
+            ban_duration: Duration::from_secs(opt.misbehavior_ban_duration),
+        },
+        performance_score: PerformanceScoreConfig {
+            luck_weight: opt.performance_luck_weight,
+            efficiency_weight: opt.performance_efficiency_weight,
+            hashrate_weight: opt.performance_hashrate_weight,
+            target_hashrate: opt.performance_target_hashrate,
+        },
+        tcp_options: TcpOptions {
+            nodelay: !opt.no_tcp_nodelay,
+            keepalive_idle: Duration::from_secs(opt.tcp_keepalive_idle),
+            keepalive_interval: Duration::from_secs(opt.tcp_keepalive_interval),
+            keepalive_retries: opt.tcp_keepalive_retries,
+        },
+    };
+
+    let server = Server::init(listen_addrs, address, node.sender(), accounting.sender(), config).await;
 
     validator_peer::start(node, server.sender());
 
     api::start(opt.api_port, accounting.clone(), server.clone());
 
+    if opt.enable_debug_api {
+        api::start_debug(opt.debug_api_port, server.clone());
+    }
+
+    if let Some(url) = opt.influxdb_push_url {
+        influxdb::start(url, opt.influxdb_token, server.clone());
+    }
+
     match Signals::new([SIGABRT, SIGTERM, SIGHUP, SIGINT, SIGQUIT, SIGUSR1, SIGTSTP]) {
         Ok(signals) => {
             tokio::spawn(handle_signals(signals, accounting.clone(), server.sender()));
@@ -146,23 +639,28 @@ async fn main() {
     std::future::pending::<()>().await;
 }
 
+/// Stops the server taking shares and sends its buffered ones to accounting before accounting saves its state.
+async fn save_states(accounting: &Accounting, server_sender: &Sender<ServerMessage>) {
+    let (flushed, wait_flushed) = oneshot::channel();
+    if server_sender.send(ServerMessage::Exit(flushed)).await.is_ok() {
+        let _ = wait_flushed.await;
+    }
+    let _ = accounting.sender().send(AccountingMessage::Exit).await;
+    accounting.wait_for_exit().await;
+}
+
 async fn handle_signals(mut signals: Signals, accounting: Arc<Accounting>, server_sender: Sender<ServerMessage>) {
     while let Some(signal) = signals.next().await {
         info!("Received signal: {:?}", signal);
-        let accounting_sender = accounting.sender();
         match signal {
             SIGABRT => {
                 info!("Trying to salvage states before aborting...");
-                let _ = accounting_sender.send(AccountingMessage::Exit).await;
-                accounting.wait_for_exit().await;
-                let _ = server_sender.send(ServerMessage::Exit).await;
+                save_states(&accounting, &server_sender).await;
                 std::process::abort();
             }
             SIGTERM | SIGINT | SIGHUP | SIGQUIT => {
                 info!("Saving states before exiting...");
-                let _ = accounting_sender.send(AccountingMessage::Exit).await;
-                accounting.wait_for_exit().await;
-                let _ = server_sender.send(ServerMessage::Exit).await;
+                save_states(&accounting, &server_sender).await;
                 std::process::exit(0);
             }
             SIGUSR1 => {
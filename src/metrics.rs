@@ -0,0 +1,277 @@
+use anyhow::Result;
+use prometheus::{
+    core::Collector,
+    Encoder,
+    Gauge,
+    GaugeVec,
+    Histogram,
+    HistogramOpts,
+    IntCounter,
+    IntCounterVec,
+    IntGauge,
+    IntGaugeVec,
+    Opts,
+    Registry,
+    TextEncoder,
+};
+
+pub struct Metrics {
+    registry: Registry,
+    pub nonce_seen_size: IntGauge,
+    pub nonce_seen_capacity: IntGauge,
+    pub connections_rejected: IntCounterVec,
+    pub proof_deserialization_errors: IntCounterVec,
+    pub protocol_errors: IntCounterVec,
+    pub block_template_age_seconds: IntGauge,
+    pub pool_hashrate_drop_alarm: IntGauge,
+    pub solution_submit_latency_seconds: Histogram,
+    pub prover_state_reconciliations: IntCounter,
+    pub time_to_first_share_seconds: Histogram,
+    pub pool_performance_score: Gauge,
+    pub pool_hashrate: GaugeVec,
+    pub pool_provers_online: IntGauge,
+    pub pool_solutions_found: IntCounter,
+    pub pool_submissions: IntCounter,
+    pub pool_rejected_submissions: IntCounter,
+    pub pool_global_difficulty_modifier: Gauge,
+    pub address_hashrate: GaugeVec,
+    pub address_connections: IntGaugeVec,
+    pub pool_prover_connects: IntCounter,
+    pub pool_prover_disconnects: IntCounter,
+    pub proof_verifications: IntCounter,
+    pub proof_verification_fallbacks: IntCounter,
+}
+
+fn register<T: Collector + Clone + 'static>(registry: &Registry, collector: T) -> T {
+    registry
+        .register(Box::new(collector.clone()))
+        .expect("Failed to register metric");
+    collector
+}
+
+impl Metrics {
+    pub fn init() -> Self {
+        let registry = Registry::new();
+        let nonce_seen_size = register(
+            &registry,
+            IntGauge::new("nonce_seen_size", "Sampled number of entries in the seen nonce set").unwrap(),
+        );
+        let nonce_seen_capacity = register(
+            &registry,
+            IntGauge::new("nonce_seen_capacity", "Configured capacity of the seen nonce set").unwrap(),
+        );
+        let connections_rejected = register(
+            &registry,
+            IntCounterVec::new(
+                Opts::new("connections_rejected_total", "Number of rejected incoming connections"),
+                &["reason"],
+            )
+            .unwrap(),
+        );
+        let proof_deserialization_errors = register(
+            &registry,
+            IntCounterVec::new(
+                Opts::new(
+                    "proof_deserialization_errors_total",
+                    "Number of submitted solutions that could not be decoded",
+                ),
+                &["kind"],
+            )
+            .unwrap(),
+        );
+        let protocol_errors = register(
+            &registry,
+            IntCounterVec::new(
+                Opts::new(
+                    "protocol_errors_total",
+                    "Number of malformed or unexpected prover messages",
+                ),
+                &["kind"],
+            )
+            .unwrap(),
+        );
+        let block_template_age_seconds = register(
+            &registry,
+            IntGauge::new(
+                "block_template_age_seconds",
+                "Seconds since the latest epoch challenge was received from the validator",
+            )
+            .unwrap(),
+        );
+        let pool_hashrate_drop_alarm = register(
+            &registry,
+            IntGauge::new(
+                "pool_hashrate_drop_alarm",
+                "1 while the 5 minute pool speed is below the alarm threshold of the 1 hour speed",
+            )
+            .unwrap(),
+        );
+        let solution_submit_latency_seconds = register(
+            &registry,
+            Histogram::with_opts(HistogramOpts::new(
+                "solution_submit_latency_seconds",
+                "Time from sending a solution to the validator until the validator answers the ping sent behind it",
+            ))
+            .unwrap(),
+        );
+        let prover_state_reconciliations = register(
+            &registry,
+            IntCounter::new(
+                "prover_state_reconciliations_total",
+                "Prover states and address index entries fixed for missing their counterpart",
+            )
+            .unwrap(),
+        );
+        let time_to_first_share_seconds = register(
+            &registry,
+            Histogram::with_opts(
+                HistogramOpts::new(
+                    "time_to_first_share_seconds",
+                    "Time from a prover authenticating to its first accepted share",
+                )
+                .buckets(vec![1.0, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0, 600.0]),
+            )
+            .unwrap(),
+        );
+        let pool_performance_score = register(
+            &registry,
+            Gauge::new(
+                "pool_performance_score",
+                "Weighted combination of pool luck, share efficiency and 5 minute speed against its target",
+            )
+            .unwrap(),
+        );
+        let pool_hashrate = register(
+            &registry,
+            GaugeVec::new(
+                Opts::new("pool_hashrate", "Sampled pool speed over each window"),
+                &["window"],
+            )
+            .unwrap(),
+        );
+        let pool_provers_online = register(
+            &registry,
+            IntGauge::new("pool_provers_online", "Sampled number of authenticated provers").unwrap(),
+        );
+        let pool_solutions_found = register(
+            &registry,
+            IntCounter::new(
+                "pool_solutions_found_total",
+                "Number of solutions found, as of the last sample",
+            )
+            .unwrap(),
+        );
+        let pool_submissions = register(
+            &registry,
+            IntCounter::new(
+                "pool_submissions_total",
+                "Number of share submissions, as of the last sample",
+            )
+            .unwrap(),
+        );
+        let pool_rejected_submissions = register(
+            &registry,
+            IntCounter::new(
+                "pool_rejected_submissions_total",
+                "Number of rejected share submissions, as of the last sample",
+            )
+            .unwrap(),
+        );
+        let pool_global_difficulty_modifier = register(
+            &registry,
+            Gauge::new(
+                "pool_global_difficulty_modifier",
+                "Current multiplier applied to every prover's share difficulty",
+            )
+            .unwrap(),
+        );
+        let address_hashrate = register(
+            &registry,
+            GaugeVec::new(
+                Opts::new("address_hashrate", "Sampled 5 minute speed of each online address"),
+                &["address"],
+            )
+            .unwrap(),
+        );
+        let address_connections = register(
+            &registry,
+            IntGaugeVec::new(
+                Opts::new(
+                    "address_connections",
+                    "Sampled number of prover connections of each online address",
+                ),
+                &["address"],
+            )
+            .unwrap(),
+        );
+        let pool_prover_connects = register(
+            &registry,
+            IntCounter::new("pool_prover_connects_total", "Number of accepted prover connections").unwrap(),
+        );
+        let pool_prover_disconnects = register(
+            &registry,
+            IntCounter::new("pool_prover_disconnects_total", "Number of closed prover connections").unwrap(),
+        );
+        let proof_verifications = register(
+            &registry,
+            IntCounter::new("proof_verifications_total", "Number of proofs verified").unwrap(),
+        );
+        let proof_verification_fallbacks = register(
+            &registry,
+            IntCounter::new(
+                "proof_verification_gpu_fallbacks_total",
+                "Number of proof verifications run off the compute pool after it didn't start them in time",
+            )
+            .unwrap(),
+        );
+        Self {
+            registry,
+            nonce_seen_size,
+            nonce_seen_capacity,
+            connections_rejected,
+            proof_deserialization_errors,
+            protocol_errors,
+            block_template_age_seconds,
+            pool_hashrate_drop_alarm,
+            solution_submit_latency_seconds,
+            prover_state_reconciliations,
+            time_to_first_share_seconds,
+            pool_performance_score,
+            pool_hashrate,
+            pool_provers_online,
+            pool_solutions_found,
+            pool_submissions,
+            pool_rejected_submissions,
+            pool_global_difficulty_modifier,
+            address_hashrate,
+            address_connections,
+            pool_prover_connects,
+            pool_prover_disconnects,
+            proof_verifications,
+            proof_verification_fallbacks,
+        }
+    }
+
+    pub fn connection_rejected(&self, reason: &str) {
+        self.connections_rejected.with_label_values(&[reason]).inc();
+    }
+
+    pub fn proof_deserialization_error(&self, kind: &str) {
+        self.proof_deserialization_errors.with_label_values(&[kind]).inc();
+    }
+
+    pub fn protocol_error(&self, kind: &str) {
+        self.protocol_errors.with_label_values(&[kind]).inc();
+    }
+
+    /// Raises `counter` to `total`, a count the server keeps itself.
+    pub fn catch_up(counter: &IntCounter, total: u64) {
+        counter.inc_by(total.saturating_sub(counter.get()));
+    }
+
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
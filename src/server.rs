@@ -1,19 +1,35 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     fmt::{Display, Formatter},
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroUsize,
     sync::{
-        atomic::{AtomicU32, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
         Arc,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use aleo_pool_types::{PoolEvent, RejectionStats, StatsUpdate, SubmitLatency};
 use aleo_stratum::{codec::ResponseParams, message::StratumMessage};
-use anyhow::ensure;
+use anyhow::{anyhow, ensure};
+use arc_swap::ArcSwap;
 use blake2::Digest;
+use bytes::Bytes;
+use chrono::Utc;
 use flurry::HashSet as FlurryHashSet;
+use futures::future::join_all;
+use hdrhistogram::Histogram;
+use hmac::{Hmac, Mac};
+use ipnet::IpNet;
 use json_rpc_types::{Error, ErrorCode, Id};
+use lru::LruCache;
+use parking_lot::Mutex;
+use rayon::ThreadPool;
+use semver::Version;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
 use snarkos_node_messages::{Data, UnconfirmedSolution};
 use snarkvm::{
     circuit::PrimeField,
@@ -29,55 +45,292 @@ use snarkvm_algorithms::{
 };
 use snarkvm_curves::PairingEngine;
 use snarkvm_utilities::serialize::CanonicalSerialize;
+use socket2::{SockRef, TcpKeepalive};
 use speedometer::Speedometer;
 use tokio::{
     net::{TcpListener, TcpStream},
     sync::{
-        mpsc::{channel, Sender},
+        mpsc::{channel, error::TrySendError, Receiver, Sender},
+        broadcast,
+        oneshot,
+        Notify,
         RwLock,
+        Semaphore,
     },
-    task,
+    task::{self, JoinHandle},
+    time::sleep,
 };
 use tracing::{debug, error, info, trace, warn};
 
-use crate::{connection::Connection, validator_peer::SnarkOSMessage, AccountingMessage};
+use crate::{
+    accounting::{AccountingSender, ShareRecord, ACCOUNTING_CHANNEL_CAPACITY},
+    audit::AuditLog,
+    config::{
+        FeeWallet,
+        MisbehaviorConfig,
+        PerformanceScoreConfig,
+        ServerConfig,
+        SpeedometerConfig,
+        TcpOptions,
+        TenantConfig,
+    },
+    connection::{Connection, ConnectionContext, DEFAULT_WORKER_NAME, PROVER_CHANNEL_CAPACITY},
+    display::format_hashrate,
+    metrics::Metrics,
+    stats_export::{ProverStatsExport, ProverStatsRow},
+    validator_peer::SnarkOSMessage,
+    AccountingMessage,
+};
+
+static NONCE_CLEAR_BACKUP_INTERVAL: Duration = Duration::from_secs(600);
+
+static HISTORY_SAMPLE_INTERVAL: Duration = Duration::from_secs(60);
+
+static THROTTLED_SUBMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Weight of the newest share in the running share quality average.
+static SHARE_QUALITY_SMOOTHING: f64 = 0.05;
+
+static HASHRATE_DROP_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+static SPEED_SPIKE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+static PERFORMANCE_SCORE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Upper bound of the reported luck, so a solution found after very few shares can't dominate the performance score.
+static MAX_LUCK: f64 = 10.0;
+
+/// Number of blocks kept in the difficulty history.
+static DIFFICULTY_HISTORY_SIZE: usize = 2016;
+
+/// Number of finished rounds kept for the contributions API.
+static CONTRIBUTION_ROUNDS: usize = 100;
+
+/// Number of recent accepted submissions kept for the per-stage pipeline timings.
+static PIPELINE_SAMPLE_SIZE: usize = 1000;
+
+/// Number of distinct solution nonces in an epoch.
+static NONCE_SPACE_SIZE: f64 = 18_446_744_073_709_551_616.0;
+
+static NONCE_EXHAUSTION_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+static CHURN_RESET_INTERVAL: Duration = Duration::from_secs(3600);
+static CHURN_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Ratio of disconnects to connects within one churn check above which a warning is logged.
+static CHURN_WARN_RATIO: u64 = 2;
+
+/// Work the compute pool didn't start in time is awaited for this many times its timeout in total.
+static COMPUTE_FALLBACK_TIMEOUT_FACTOR: u32 = 4;
+/// Blocking threads that may run compute pool fallbacks at once, so timed out ones can't pile up.
+static MAX_COMPUTE_FALLBACKS: usize = 2;
+static VERIFICATION_FALLBACK_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Connection events buffered for slow event stream subscribers before they start missing some.
+static CONNECTION_EVENT_CAPACITY: usize = 1024;
+
+/// Window the connection rate of a subnet is measured over for flood protection.
+static CONNECTION_FLOOD_WINDOW: Duration = Duration::from_secs(60);
+/// A flooding subnet is accepted again once its rate falls below this share of the threshold.
+static CONNECTION_FLOOD_RESUME_RATIO: f64 = 0.5;
+
+/// Updates queued for a stats WebSocket subscriber; further ones are dropped rather than slowing down shares.
+static STATS_SUBSCRIBER_CAPACITY: usize = 64;
+
+static DIFFICULTY_FALLBACK_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Weight of the newest measurement in the average proof verification time.
+static VERIFICATION_TIME_SMOOTHING: f64 = 0.05;
+
+/// Degree of the coinbase puzzle polynomials.
+static COINBASE_PUZZLE_DEGREE: u32 = (1 << 13) - 1;
+
+/// Previous block heights an authentication token may still be computed from, to allow for propagation delay.
+static AUTHENTICATION_HEIGHT_TOLERANCE: u32 = 10;
+
+static PROVER_STATE_CHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+static MISBEHAVIOR_PRUNE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// How long the misbehavior score of a disconnected prover is kept for its next connection.
+static MISBEHAVIOR_SCORE_TTL: Duration = Duration::from_secs(3600);
+
+static SESSION_PRUNE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// How long finished sessions are kept, so addresses that stopped connecting don't stay in the history forever.
+static SESSION_HISTORY_TTL: Duration = Duration::from_secs(86400);
+
+static BACKPRESSURE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Fill ratio of a prover's message channel above which the check counts as backpressured.
+static BACKPRESSURE_FILL_RATIO: f64 = 0.8;
+
+/// Consecutive backpressured checks before a prover is reported as backpressured.
+static BACKPRESSURE_CHECKS: u32 = 3;
+
+/// Wait between attempts to hand a queued solution to the validator connection.
+static SOLUTION_SUBMIT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+static STALE_RATE_WARN_PERCENT: f64 = 10.0;
+static STALE_RATE_MIN_SUBMISSIONS: u64 = 100;
+
+fn stale_rate_percent(stale_shares: u64, total_submissions: u64) -> f64 {
+    if total_submissions == 0 {
+        return 0.0;
+    }
+    stale_shares as f64 / total_submissions as f64 * 100.0
+}
+
+/// Why a submission was rejected.
+#[derive(Clone, Copy)]
+enum RejectionReason {
+    Stale,
+    DuplicateNonce,
+    LowDifficulty,
+    InvalidProof,
+    UnknownProver,
+    RateLimited,
+}
+
+#[derive(Default)]
+struct RejectionCounters {
+    stale: AtomicU64,
+    duplicate_nonce: AtomicU64,
+    low_difficulty: AtomicU64,
+    invalid_proof: AtomicU64,
+    unknown_prover: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+impl RejectionCounters {
+    fn add(&self, reason: RejectionReason) {
+        let counter = match reason {
+            RejectionReason::Stale => &self.stale,
+            RejectionReason::DuplicateNonce => &self.duplicate_nonce,
+            RejectionReason::LowDifficulty => &self.low_difficulty,
+            RejectionReason::InvalidProof => &self.invalid_proof,
+            RejectionReason::UnknownProver => &self.unknown_prover,
+            RejectionReason::RateLimited => &self.rate_limited,
+        };
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn stats(&self) -> RejectionStats {
+        RejectionStats {
+            stale: self.stale.load(Ordering::SeqCst),
+            duplicate_nonce: self.duplicate_nonce.load(Ordering::SeqCst),
+            low_difficulty: self.low_difficulty.load(Ordering::SeqCst),
+            invalid_proof: self.invalid_proof.load(Ordering::SeqCst),
+            unknown_prover: self.unknown_prover.load(Ordering::SeqCst),
+            rate_limited: self.rate_limited.load(Ordering::SeqCst),
+        }
+    }
+}
 
 struct ProverState {
     peer_addr: SocketAddr,
     address: Address<Testnet3>,
-    speed_2m: Speedometer,
+    worker_name: String,
+    label: Option<String>,
+    /// Name of the tenant the prover's address belongs to
+    tenant: Option<String>,
+    /// Speed over the configured target window, which the share difficulty follows
+    target_speed: Speedometer,
     speed_5m: Speedometer,
     speed_15m: Speedometer,
     speed_30m: Speedometer,
     speed_1h: Speedometer,
     current_target: u64,
     next_target: u64,
+    min_target: u64,
+    total_submissions: AtomicU64,
+    accepted_shares: AtomicU64,
+    stale_shares: AtomicU64,
+    misbehavior_score: AtomicU32,
+    connected_at: Instant,
+    first_share_at: Option<Instant>,
+    last_submission: Option<Instant>,
+    last_share: Instant,
+    last_fallback: Option<Instant>,
+    fallback_reductions: u32,
+    rejections: RejectionCounters,
+    share_quality: Option<f64>,
+    /// 5m, 15m, 30m and 1h speeds as of the last accepted share or speed cache refresh
+    cached_speed: [f64; 4],
+    peak_speed_5m: f64,
+    /// Consecutive backpressure checks that found the message channel over `BACKPRESSURE_FILL_RATIO`
+    backpressured_checks: u32,
+    channel_full_since: Option<Instant>,
 }
 
 impl ProverState {
-    pub fn new(peer_addr: SocketAddr, address: Address<Testnet3>) -> Self {
+    pub fn new(
+        peer_addr: SocketAddr,
+        address: Address<Testnet3>,
+        worker_name: String,
+        label: Option<String>,
+        min_target: u64,
+        speed_config: SpeedometerConfig,
+    ) -> Self {
+        let cache_interval = Duration::from_secs(speed_config.cache_interval_secs);
         Self {
             peer_addr,
             address,
-            speed_2m: Speedometer::init(Duration::from_secs(120)),
-            speed_5m: Speedometer::init_with_cache(Duration::from_secs(60 * 5), Duration::from_secs(30)),
-            speed_15m: Speedometer::init_with_cache(Duration::from_secs(60 * 15), Duration::from_secs(30)),
-            speed_30m: Speedometer::init_with_cache(Duration::from_secs(60 * 30), Duration::from_secs(30)),
-            speed_1h: Speedometer::init_with_cache(Duration::from_secs(60 * 60), Duration::from_secs(30)),
-            current_target: 512,
-            next_target: 512,
+            worker_name,
+            label,
+            tenant: None,
+            target_speed: Speedometer::init(Duration::from_secs(speed_config.target_window_secs)),
+            speed_5m: Speedometer::init_with_cache(Duration::from_secs(60 * 5), cache_interval),
+            speed_15m: Speedometer::init_with_cache(Duration::from_secs(60 * 15), cache_interval),
+            speed_30m: Speedometer::init_with_cache(Duration::from_secs(60 * 30), cache_interval),
+            speed_1h: Speedometer::init_with_cache(Duration::from_secs(60 * 60), cache_interval),
+            current_target: min_target.max(512),
+            next_target: min_target.max(512),
+            min_target,
+            total_submissions: AtomicU64::new(0),
+            accepted_shares: AtomicU64::new(0),
+            stale_shares: AtomicU64::new(0),
+            misbehavior_score: AtomicU32::new(0),
+            connected_at: Instant::now(),
+            first_share_at: None,
+            last_submission: None,
+            last_share: Instant::now(),
+            last_fallback: None,
+            fallback_reductions: 0,
+            rejections: Default::default(),
+            share_quality: None,
+            cached_speed: [0.0; 4],
+            peak_speed_5m: 0.0,
+            backpressured_checks: 0,
+            channel_full_since: None,
         }
     }
 
-    pub async fn add_share(&mut self, value: u64) {
+    /// Returns the time since authentication if this is the prover's first accepted share.
+    pub async fn add_share(&mut self, value: u64) -> Option<Duration> {
         let now = Instant::now();
-        self.speed_2m.event(value).await;
+        self.accepted_shares.fetch_add(1, Ordering::SeqCst);
+        self.last_share = now;
+        let time_to_first_share = match self.first_share_at {
+            Some(_) => None,
+            None => {
+                self.first_share_at = Some(now);
+                Some(now - self.connected_at)
+            }
+        };
+        self.fallback_reductions = 0;
+        let _ = self
+            .misbehavior_score
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |score| score.checked_sub(1));
+        self.target_speed.event(value).await;
         self.speed_5m.event(value).await;
         self.speed_15m.event(value).await;
         self.speed_30m.event(value).await;
         self.speed_1h.event(value).await;
-        self.next_target = ((self.speed_2m.speed().await * 20.0) as u64).max(1);
+        self.refresh_speed().await;
+        self.next_target = ((self.target_speed.speed().await * 20.0) as u64).max(self.min_target);
         debug!("add_share took {} us", now.elapsed().as_micros());
+        time_to_first_share
     }
 
     pub async fn next_target(&mut self) -> u64 {
@@ -89,6 +342,20 @@ impl ProverState {
         self.current_target
     }
 
+    /// Folds the ratio of an accepted share's difficulty to its assigned difficulty into the running average.
+    pub fn add_share_quality(&mut self, proof_difficulty: u64, assigned_difficulty: u64) {
+        let quality = proof_difficulty as f64 / assigned_difficulty.max(1) as f64;
+        self.share_quality = Some(match self.share_quality {
+            Some(average) => average + (quality - average) * SHARE_QUALITY_SMOOTHING,
+            None => quality,
+        });
+    }
+
+    /// Running average of share difficulty over assigned difficulty; 1.0 means shares are exactly on target.
+    pub fn share_quality(&self) -> Option<f64> {
+        self.share_quality
+    }
+
     pub fn current_target(&self) -> u64 {
         self.current_target
     }
@@ -97,61 +364,302 @@ impl ProverState {
         self.address
     }
 
-    // noinspection DuplicatedCode
-    pub async fn speed(&mut self) -> Vec<f64> {
-        vec![
+    pub fn set_address(&mut self, address: Address<Testnet3>) {
+        self.address = address;
+    }
+
+    pub fn worker_name(&self) -> &str {
+        &self.worker_name
+    }
+
+    pub fn connected_at(&self) -> Instant {
+        self.connected_at
+    }
+
+    pub fn set_worker_name(&mut self, worker_name: String) {
+        self.worker_name = worker_name;
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    pub fn set_tenant(&mut self, tenant: Option<String>) {
+        self.tenant = tenant;
+    }
+
+    /// Counts a submission and returns the time since the previous one.
+    pub fn record_submission(&mut self) -> Option<Duration> {
+        self.total_submissions.fetch_add(1, Ordering::SeqCst);
+        let now = Instant::now();
+        self.last_submission.replace(now).map(|last| now - last)
+    }
+
+    pub fn add_stale_share(&self) {
+        self.stale_shares.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Time since the last accepted share, or since authentication if there is none yet.
+    pub fn idle_time(&self) -> Duration {
+        self.last_share.elapsed()
+    }
+
+    /// Lowers the target of a prover that has submitted nothing for `idle`, at most once per `idle` and
+    /// `max_reductions` times in a row. Returns the new target if it was lowered.
+    pub fn fallback_target(&mut self, idle: Duration, factor: f64, max_reductions: u32) -> Option<u64> {
+        let since_submission = self.last_submission.unwrap_or(self.last_share).elapsed();
+        if since_submission <= idle
+            || self.last_fallback.map_or(false, |last| last.elapsed() <= idle)
+            || self.fallback_reductions >= max_reductions
+            || self.current_target <= self.min_target
+        {
+            return None;
+        }
+        self.current_target = ((self.current_target as f64 * factor) as u64).max(self.min_target);
+        self.next_target = self.current_target;
+        self.last_fallback = Some(Instant::now());
+        self.fallback_reductions += 1;
+        Some(self.current_target)
+    }
+
+    /// Adds to the misbehavior score and returns the new score.
+    pub fn penalize(&self, amount: u32) -> u32 {
+        self.misbehavior_score
+            .fetch_add(amount, Ordering::SeqCst)
+            .saturating_add(amount)
+    }
+
+    pub fn misbehavior_score(&self) -> u32 {
+        self.misbehavior_score.load(Ordering::SeqCst)
+    }
+
+    pub fn total_submissions(&self) -> u64 {
+        self.total_submissions.load(Ordering::SeqCst)
+    }
+
+    pub fn accepted_shares(&self) -> u64 {
+        self.accepted_shares.load(Ordering::SeqCst)
+    }
+
+    pub fn rejected_shares(&self) -> u64 {
+        self.total_submissions().saturating_sub(self.accepted_shares())
+    }
+
+    pub fn stale_shares(&self) -> u64 {
+        self.stale_shares.load(Ordering::SeqCst)
+    }
+
+    pub fn stale_rate_percent(&self) -> f64 {
+        stale_rate_percent(self.stale_shares(), self.total_submissions())
+    }
+
+    pub fn rejections(&self) -> RejectionStats {
+        self.rejections.stats()
+    }
+
+    /// Recalculates the cached speeds, so they also fall for a prover that stopped submitting shares.
+    pub async fn refresh_speed(&mut self) {
+        self.cached_speed = [
             self.speed_5m.speed().await,
             self.speed_15m.speed().await,
             self.speed_30m.speed().await,
             self.speed_1h.speed().await,
-        ]
+        ];
+        self.peak_speed_5m = self.peak_speed_5m.max(self.cached_speed[0]);
+    }
+
+    /// Speeds are only refreshed when a share is accepted and on the speed cache interval, so this never waits on
+    /// the speedometers.
+    pub fn speed(&self) -> Vec<f64> {
+        self.cached_speed.to_vec()
+    }
+
+    pub fn peak_speed_5m(&self) -> f64 {
+        self.peak_speed_5m
+    }
+
+    /// Records the fill ratio of the prover's message channel and returns how long it has been completely full.
+    pub fn record_channel_fill(&mut self, fill_ratio: f64) -> Option<Duration> {
+        if fill_ratio > BACKPRESSURE_FILL_RATIO {
+            self.backpressured_checks = self.backpressured_checks.saturating_add(1);
+        } else {
+            self.backpressured_checks = 0;
+        }
+        if fill_ratio < 1.0 {
+            self.channel_full_since = None;
+            return None;
+        }
+        Some(self.channel_full_since.get_or_insert_with(Instant::now).elapsed())
+    }
+
+    pub fn backpressured(&self) -> bool {
+        self.backpressured_checks >= BACKPRESSURE_CHECKS
     }
 }
 
 impl Display for ProverState {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let addr_str = self.address.to_string();
-        write!(
-            f,
-            "{} ({}...{})",
-            self.peer_addr,
-            &addr_str[0..11],
-            &addr_str[addr_str.len() - 6..]
-        )
+        let short_addr = format!("{}...{}", &addr_str[0..11], &addr_str[addr_str.len() - 6..]);
+        match &self.label {
+            Some(label) => write!(f, "{} ({}) [{}]", self.peer_addr, label, short_addr),
+            None => write!(f, "{} ({})", self.peer_addr, short_addr),
+        }
+    }
+}
+
+/// Accepted shares counted by proof difficulty. Bucket `i` holds difficulties from `bounds[i - 1]` (or 0) up to,
+/// but not including, `bounds[i]` (or `u64::MAX` for the last one).
+struct DifficultyHistogram {
+    bounds: Vec<u64>,
+    counts: Vec<AtomicU64>,
+}
+
+impl DifficultyHistogram {
+    fn new(bounds: Vec<u64>) -> Self {
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self { bounds, counts }
+    }
+
+    fn add(&self, difficulty: u64) {
+        let bucket = self.bounds.partition_point(|bound| *bound <= difficulty);
+        self.counts[bucket].fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn buckets(&self) -> Vec<DifficultyBucket> {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| DifficultyBucket {
+                min: if i == 0 { 0 } else { self.bounds[i - 1] },
+                max: self.bounds.get(i).copied().unwrap_or(u64::MAX),
+                count: count.load(Ordering::SeqCst),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize)]
+pub struct DifficultyBucket {
+    min: u64,
+    max: u64,
+    count: u64,
+}
+
+/// Upper bounds of the share ratio histogram buckets, the last bucket being unbounded.
+static SHARE_RATIO_BOUNDS: &[f64] = &[0.25, 0.5, 0.75, 0.9, 1.0, 1.1, 1.5, 2.0, 4.0, 16.0, 256.0];
+
+/// Shares of the current round counted by proof difficulty over assigned difficulty. Ratios below 1 mean the
+/// assigned difficulty was missed, ratios far above 1 mean luck or too low a difficulty.
+struct ShareRatioHistogram {
+    counts: Vec<AtomicU64>,
+}
+
+impl ShareRatioHistogram {
+    fn new() -> Self {
+        let counts = (0..=SHARE_RATIO_BOUNDS.len()).map(|_| AtomicU64::new(0)).collect();
+        Self { counts }
+    }
+
+    fn add(&self, ratio: f64) {
+        let bucket = SHARE_RATIO_BOUNDS.partition_point(|bound| *bound <= ratio);
+        self.counts[bucket].fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn clear(&self) {
+        for count in &self.counts {
+            count.store(0, Ordering::SeqCst);
+        }
+    }
+
+    fn buckets(&self) -> Vec<ShareRatioBucket> {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| ShareRatioBucket {
+                min: if i == 0 { 0.0 } else { SHARE_RATIO_BOUNDS[i - 1] },
+                max: SHARE_RATIO_BOUNDS.get(i).copied(),
+                count: count.load(Ordering::SeqCst),
+            })
+            .collect()
     }
 }
 
+/// Shares with a ratio from `min` up to, but not including, `max`, which is `None` for the last bucket.
+#[derive(Clone, Debug, Serialize)]
+pub struct ShareRatioBucket {
+    min: f64,
+    max: Option<f64>,
+    count: u64,
+}
+
+/// Connections admitted from one subnet within the flood window, and whether the subnet is being refused.
+#[derive(Default)]
+struct SubnetConnectionFlood {
+    admitted: VecDeque<Instant>,
+    flooded: bool,
+}
+
 struct PoolState {
-    speed_1m: Speedometer,
+    /// Accepted shares over the window picked for the pool size, which the global target modifier follows
+    share_speed: Speedometer,
+    speed_config: SpeedometerConfig,
+    /// Accepted shares over a fixed minute, so share rate spikes are measured the same way whatever the pool size
+    share_speed_1m: Speedometer,
     speed_5m: Speedometer,
     speed_15m: Speedometer,
     speed_30m: Speedometer,
     speed_1h: Speedometer,
     current_global_target_modifier: f64,
     next_global_target_modifier: f64,
+    total_submissions: AtomicU64,
+    stale_shares: AtomicU64,
+    solutions_found: AtomicU64,
+    /// Sum of the chance of each accepted share to reach the proof target
+    expected_solutions: f64,
+    performance_score: f64,
+    rejections: RejectionCounters,
+    submit_latency_us: Arc<Mutex<Histogram<u64>>>,
+    difficulty_histogram: DifficultyHistogram,
+    share_ratio_histogram: ShareRatioHistogram,
 }
 
 impl PoolState {
-    pub fn new() -> Self {
+    pub fn new(difficulty_histogram_bounds: Vec<u64>) -> Self {
+        let speed_config = SpeedometerConfig::auto_select(0);
         Self {
-            speed_1m: Speedometer::init(Duration::from_secs(60)),
+            share_speed: Speedometer::init(Duration::from_secs(speed_config.target_window_secs)),
+            speed_config,
+            share_speed_1m: Speedometer::init(Duration::from_secs(60)),
             speed_5m: Speedometer::init_with_cache(Duration::from_secs(60 * 5), Duration::from_secs(30)),
             speed_15m: Speedometer::init_with_cache(Duration::from_secs(60 * 15), Duration::from_secs(30)),
             speed_30m: Speedometer::init_with_cache(Duration::from_secs(60 * 30), Duration::from_secs(30)),
             speed_1h: Speedometer::init_with_cache(Duration::from_secs(60 * 60), Duration::from_secs(30)),
             current_global_target_modifier: 1.0,
             next_global_target_modifier: 1.0,
+            total_submissions: AtomicU64::new(0),
+            stale_shares: AtomicU64::new(0),
+            solutions_found: AtomicU64::new(0),
+            expected_solutions: 0.0,
+            performance_score: 0.0,
+            rejections: Default::default(),
+            submit_latency_us: Arc::new(Mutex::new(
+                Histogram::new_with_bounds(1, 60_000_000, 3).expect("Invalid histogram bounds"),
+            )),
+            difficulty_histogram: DifficultyHistogram::new(difficulty_histogram_bounds),
+            share_ratio_histogram: ShareRatioHistogram::new(),
         }
     }
 
     pub async fn add_share(&mut self, value: u64) {
         let now = Instant::now();
-        self.speed_1m.event(1).await;
+        self.share_speed.event(1).await;
+        self.share_speed_1m.event(1).await;
         self.speed_5m.event(value).await;
         self.speed_15m.event(value).await;
         self.speed_30m.event(value).await;
         self.speed_1h.event(value).await;
-        self.next_global_target_modifier = (self.speed_1m.speed().await / 200.0).max(1f64);
+        self.next_global_target_modifier = (self.share_speed.speed().await / 200.0).max(1f64);
         // todo: make adjustable through admin api
         debug!("pool state add_share took {} us", now.elapsed().as_micros());
     }
@@ -167,12 +675,162 @@ impl PoolState {
         self.current_global_target_modifier
     }
 
+    /// Applies the next global target modifier ahead of the next block template if it is higher than the current
+    /// one, leaving decreases to the template. Returns the previous and new modifier if it was raised.
+    pub fn raise_global_target_modifier(&mut self) -> Option<(f64, f64)> {
+        if self.next_global_target_modifier <= self.current_global_target_modifier {
+            return None;
+        }
+        let previous_modifier = self.current_global_target_modifier;
+        self.current_global_target_modifier = self.next_global_target_modifier;
+        Some((previous_modifier, self.current_global_target_modifier))
+    }
+
+    /// Accepted shares per second over the last minute.
+    pub async fn share_rate(&self) -> f64 {
+        self.share_speed_1m.speed().await
+    }
+
+    /// Switches the share speed to the windows suited to `prover_count` provers, keeping the recorded shares.
+    /// The switch waits until the count is a fifth past the boundary, so a pool hovering at it keeps its window.
+    /// Returns the new configuration if it changed.
+    pub fn auto_select_speed_config(&mut self, prover_count: usize) -> Option<SpeedometerConfig> {
+        let speed_config = SpeedometerConfig::auto_select(prover_count);
+        if speed_config.target_window_secs == self.speed_config.target_window_secs {
+            return None;
+        }
+        let margin = (prover_count / 5).max(1);
+        // Larger pools get shorter windows.
+        let settled_count = if speed_config.target_window_secs < self.speed_config.target_window_secs {
+            prover_count.saturating_sub(margin)
+        } else {
+            prover_count + margin
+        };
+        if SpeedometerConfig::auto_select(settled_count).target_window_secs != speed_config.target_window_secs {
+            return None;
+        }
+        self.share_speed
+            .set_interval(Duration::from_secs(speed_config.target_window_secs));
+        self.speed_config = speed_config;
+        Some(speed_config)
+    }
+
     pub fn current_global_target_modifier(&self) -> f64 {
         self.current_global_target_modifier
     }
 
+    pub fn add_submission(&self) {
+        self.total_submissions.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn add_stale_share(&self) {
+        self.stale_shares.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn add_solution(&self) {
+        self.solutions_found.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub fn solutions_found(&self) -> u64 {
+        self.solutions_found.load(Ordering::SeqCst)
+    }
+
+    pub fn add_expected_solutions(&mut self, share_target: u64, proof_target: u64) {
+        self.expected_solutions += share_target as f64 / proof_target.max(1) as f64;
+    }
+
+    /// Solutions found relative to the number the accepted shares were expected to find, 1 before any share and
+    /// at most `MAX_LUCK`.
+    pub fn luck(&self) -> f64 {
+        if self.expected_solutions == 0.0 {
+            return 1.0;
+        }
+        (self.solutions_found() as f64 / self.expected_solutions).min(MAX_LUCK)
+    }
+
+    pub fn total_submissions(&self) -> u64 {
+        self.total_submissions.load(Ordering::SeqCst)
+    }
+
+    /// Submissions rejected after reaching the share checks.
+    pub fn rejected_submissions(&self) -> u64 {
+        let rejections = self.rejections();
+        rejections.stale + rejections.duplicate_nonce + rejections.low_difficulty + rejections.invalid_proof
+    }
+
+    /// Fraction of the submissions that were not rejected after reaching the share checks.
+    pub fn efficiency(&self) -> f64 {
+        let total = self.total_submissions();
+        if total == 0 {
+            return 1.0;
+        }
+        (1.0 - self.rejected_submissions() as f64 / total as f64).max(0.0)
+    }
+
+    pub async fn update_performance_score(&mut self, config: &PerformanceScoreConfig) -> f64 {
+        let hashrate = match config.target_hashrate {
+            Some(target_hashrate) => (self.speed_5m.speed().await / target_hashrate).min(1.0),
+            None => 1.0,
+        };
+        self.performance_score = self.luck() * config.luck_weight
+            + self.efficiency() * config.efficiency_weight
+            + hashrate * config.hashrate_weight;
+        self.performance_score
+    }
+
+    pub fn performance_score(&self) -> f64 {
+        self.performance_score
+    }
+
+    pub fn rejections(&self) -> RejectionStats {
+        self.rejections.stats()
+    }
+
+    pub fn add_share_difficulty(&self, proof_difficulty: u64) {
+        self.difficulty_histogram.add(proof_difficulty);
+    }
+
+    pub fn difficulty_histogram(&self) -> Vec<DifficultyBucket> {
+        self.difficulty_histogram.buckets()
+    }
+
+    pub fn add_share_ratio(&self, proof_difficulty: u64, assigned_difficulty: u64) {
+        self.share_ratio_histogram
+            .add(proof_difficulty as f64 / assigned_difficulty.max(1) as f64);
+    }
+
+    pub fn share_ratio_histogram(&self) -> Vec<ShareRatioBucket> {
+        self.share_ratio_histogram.buckets()
+    }
+
+    pub fn clear_share_ratio_histogram(&self) {
+        self.share_ratio_histogram.clear();
+    }
+
+    pub fn submit_latency_us(&self) -> Arc<Mutex<Histogram<u64>>> {
+        self.submit_latency_us.clone()
+    }
+
+    pub fn submit_latency_percentiles(&self) -> SubmitLatency {
+        let histogram = self.submit_latency_us.lock();
+        SubmitLatency {
+            samples: histogram.len(),
+            p50: histogram.value_at_quantile(0.5),
+            p90: histogram.value_at_quantile(0.9),
+            p99: histogram.value_at_quantile(0.99),
+            max: histogram.max(),
+        }
+    }
+
+    pub fn stale_rate_percent(&self) -> f64 {
+        stale_rate_percent(
+            self.stale_shares.load(Ordering::SeqCst),
+            self.total_submissions.load(Ordering::SeqCst),
+        )
+    }
+
     // noinspection DuplicatedCode
-    pub async fn speed(&mut self) -> Vec<f64> {
+    pub async fn speed(&self) -> Vec<f64> {
         vec![
             self.speed_5m.speed().await,
             self.speed_15m.speed().await,
@@ -182,134 +840,1397 @@ impl PoolState {
     }
 }
 
-#[allow(clippy::large_enum_variant)]
-#[derive(Debug)]
-pub enum ServerMessage {
-    ProverConnected(TcpStream, SocketAddr),
-    ProverAuthenticated(SocketAddr, Address<Testnet3>, Sender<StratumMessage>),
-    ProverDisconnected(SocketAddr),
-    ProverSubmit(
-        Id,
-        SocketAddr,
-        u32,
-        u64,
-        KZGCommitment<<Testnet3 as Environment>::PairingCurve>,
-        KZGProof<<Testnet3 as Environment>::PairingCurve>,
-    ),
-    NewEpochChallenge(EpochChallenge<Testnet3>, u64),
-    Exit,
+/// Pool size at one point in time, sampled every minute.
+#[derive(Clone, Copy, Serialize)]
+pub struct HistorySample {
+    pub timestamp: u64,
+    pub prover_count: u32,
+    pub address_count: u32,
 }
 
-impl ServerMessage {
-    fn name(&self) -> &'static str {
-        match self {
-            ServerMessage::ProverConnected(..) => "ProverConnected",
-            ServerMessage::ProverAuthenticated(..) => "ProverAuthenticated",
-            ServerMessage::ProverDisconnected(..) => "ProverDisconnected",
-            ServerMessage::ProverSubmit(..) => "ProverSubmit",
-            ServerMessage::NewEpochChallenge(..) => "NewEpochChallenge",
-            ServerMessage::Exit => "Exit",
+/// Network targets as of a block, recorded when its epoch challenge message arrives.
+#[derive(Clone, Copy, Serialize)]
+pub struct DifficultySample {
+    pub block_height: u32,
+    pub coinbase_target: u64,
+    pub proof_target: u64,
+    pub timestamp: u64,
+}
+
+/// Share difficulty credited to each address, per round of the chain tip staying at one block height.
+#[derive(Default)]
+struct ContributionRounds {
+    block_height: u32,
+    current: HashMap<Address<Testnet3>, u64>,
+    finished: VecDeque<(u32, HashMap<Address<Testnet3>, u64>)>,
+}
+
+impl ContributionRounds {
+    fn add(&mut self, address: Address<Testnet3>, value: u64) {
+        *self.current.entry(address).or_default() += value;
+    }
+
+    /// Starts a new round if the chain tip has moved, keeping the last `CONTRIBUTION_ROUNDS` finished rounds.
+    fn set_block_height(&mut self, block_height: u32) {
+        if block_height == self.block_height {
+            return;
+        }
+        let finished = std::mem::take(&mut self.current);
+        if self.block_height != 0 {
+            self.finished.push_back((self.block_height, finished));
+            while self.finished.len() > CONTRIBUTION_ROUNDS {
+                self.finished.pop_front();
+            }
         }
+        self.block_height = block_height;
     }
-}
 
-impl Display for ServerMessage {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name())
+    fn get(&self, block_height: Option<u32>) -> Option<RoundContributions> {
+        let (block_height, contributions) = match block_height {
+            None => (self.block_height, &self.current),
+            Some(height) if height == self.block_height => (self.block_height, &self.current),
+            Some(height) => self
+                .finished
+                .iter()
+                .find(|(block_height, _)| *block_height == height)
+                .map(|(block_height, contributions)| (*block_height, contributions))?,
+        };
+        Some(RoundContributions {
+            block_height,
+            contributions: contributions
+                .iter()
+                .map(|(address, value)| (address.to_string(), *value))
+                .collect(),
+        })
     }
 }
 
-pub struct Server {
-    sender: Sender<ServerMessage>,
-    validator_sender: Arc<Sender<SnarkOSMessage>>,
-    accounting_sender: Sender<AccountingMessage>,
-    pool_address: Address<Testnet3>,
-    connected_provers: RwLock<HashSet<SocketAddr>>,
-    authenticated_provers: Arc<RwLock<HashMap<SocketAddr, Sender<StratumMessage>>>>,
-    pool_state: Arc<RwLock<PoolState>>,
-    prover_states: Arc<RwLock<HashMap<SocketAddr, RwLock<ProverState>>>>,
-    prover_address_connections: Arc<RwLock<HashMap<Address<Testnet3>, HashSet<SocketAddr>>>>,
-    coinbase_puzzle: CoinbasePuzzle<Testnet3>,
-    latest_epoch_number: AtomicU32,
-    latest_epoch_challenge: Arc<RwLock<Option<EpochChallenge<Testnet3>>>>,
-    latest_proof_target: AtomicU64,
-    nonce_seen: Arc<FlurryHashSet<u64>>,
+#[derive(Serialize)]
+pub struct RoundContributions {
+    block_height: u32,
+    contributions: HashMap<String, u64>,
 }
 
-impl Server {
-    pub async fn init(
-        port: u16,
-        address: Address<Testnet3>,
-        validator_sender: Arc<Sender<SnarkOSMessage>>,
-        accounting_sender: Sender<AccountingMessage>,
-    ) -> Arc<Server> {
-        let (sender, mut receiver) = channel(1024);
+/// Records the time since a submission was received into the latency histogram when dropped,
+/// and counts the submission as in flight until then.
+struct SubmitLatencyRecorder {
+    received_at: Instant,
+    histogram: Arc<Mutex<Histogram<u64>>>,
+    in_flight: Arc<AtomicU64>,
+}
 
-        let (_, listener) = match TcpListener::bind(format!("0.0.0.0:{}", port)).await {
-            Ok(listener) => {
-                let local_ip = listener.local_addr().expect("Could not get local ip");
-                info!("Listening on {}", local_ip);
-                (local_ip, listener)
-            }
-            Err(e) => {
-                panic!("Unable to start the server: {:?}", e);
-            }
-        };
+impl Drop for SubmitLatencyRecorder {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.histogram
+            .lock()
+            .saturating_record(self.received_at.elapsed().as_micros() as u64);
+    }
+}
 
-        info!("Initializing universal SRS");
-        let srs = UniversalSRS::<Testnet3>::load().expect("Failed to load SRS");
-        info!("Universal SRS initialized");
+/// Durations of one pipeline stage over the recent accepted submissions, in microseconds.
+#[derive(Serialize)]
+pub struct StageLatency {
+    p50: u64,
+    p90: u64,
+    p99: u64,
+    max: u64,
+}
+
+impl StageLatency {
+    fn from_samples(mut samples: Vec<u64>) -> Self {
+        samples.sort_unstable();
+        let quantile = |q: f64| match samples.len() {
+            0 => 0,
+            len => samples[((len - 1) as f64 * q).round() as usize],
+        };
+        Self {
+            p50: quantile(0.5),
+            p90: quantile(0.9),
+            p99: quantile(0.99),
+            max: samples.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PipelineStats {
+    shares_in_flight: u64,
+    nonce_seen_size: usize,
+    prover_states_count: usize,
+    accounting_channel_pending_estimate: usize,
+    samples: usize,
+    nonce_check_us: StageLatency,
+    difficulty_check_us: StageLatency,
+    proof_verification_us: StageLatency,
+    accounting_send_us: StageLatency,
+}
+
+/// Counts a rejected submission for the pool and, if it is known, the prover.
+async fn record_rejection(
+    pool_state: &RwLock<PoolState>,
+    prover_state: Option<&RwLock<ProverState>>,
+    reason: RejectionReason,
+) {
+    pool_state.read().await.rejections.add(reason);
+    if let Some(prover_state) = prover_state {
+        prover_state.read().await.rejections.add(reason);
+    }
+}
+
+/// Builds a thread pool with one thread pinned to each of the given CPU cores.
+fn build_compute_pool(cores: &[usize]) -> Arc<ThreadPool> {
+    let core_ids = core_affinity::get_core_ids().unwrap_or_default();
+    let cores = cores.to_vec();
+    info!("Verifying proofs on CPU cores {:?}", cores);
+    Arc::new(
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(cores.len())
+            .thread_name(|index| format!("compute-{}", index))
+            .start_handler(move |index| {
+                let core = cores[index];
+                match core_ids.iter().find(|core_id| core_id.id == core) {
+                    Some(core_id) => {
+                        if !core_affinity::set_for_current(*core_id) {
+                            warn!("Failed to pin compute thread to CPU core {}", core);
+                        }
+                    }
+                    None => warn!("CPU core {} does not exist, compute thread is not pinned", core),
+                }
+            })
+            .build()
+            .expect("Failed to build compute thread pool"),
+    )
+}
+
+/// Runs CPU heavy work on the pinned compute pool if there is one, or on the current task otherwise.
+/// If the pool hasn't returned within `timeout`, e.g. because it is saturated, a blocking thread is offered the work
+/// as well. Whichever of the two starts first does the work and the other skips it, so it never runs twice, and
+/// work neither has started when the result is given up on is dropped.
+/// Returns the result and whether the blocking thread did the work.
+async fn run_compute<T: Send + 'static>(
+    compute_pool: &Option<Arc<ThreadPool>>,
+    fallback_permits: &Arc<Semaphore>,
+    timeout: Duration,
+    work: impl FnOnce() -> T + Send + 'static,
+) -> anyhow::Result<(T, bool)> {
+    let compute_pool = match compute_pool {
+        Some(compute_pool) => compute_pool,
+        None => return catch_compute_panic(work).map(|result| (result, false)),
+    };
+    let (sender, mut receiver) = oneshot::channel();
+    let claim = Arc::new(Mutex::new(Some((work, sender))));
+    let pool_claim = claim.clone();
+    compute_pool.spawn(move || {
+        let claimed = pool_claim.lock().take();
+        if let Some((work, sender)) = claimed {
+            let _ = sender.send((catch_compute_panic(work), false));
+        }
+    });
+    if let Ok(result) = tokio::time::timeout(timeout, &mut receiver).await {
+        let (result, fallback) = result.map_err(|_| anyhow!("compute thread dropped its result"))?;
+        return result.map(|result| (result, fallback));
+    }
+    if let Ok(permit) = fallback_permits.clone().try_acquire_owned() {
+        let fallback_claim = claim.clone();
+        task::spawn_blocking(move || {
+            let claimed = fallback_claim.lock().take();
+            if let Some((work, sender)) = claimed {
+                let _ = sender.send((catch_compute_panic(work), true));
+            }
+            drop(permit);
+        });
+    }
+    let remaining = timeout * (COMPUTE_FALLBACK_TIMEOUT_FACTOR - 1);
+    match tokio::time::timeout(remaining, receiver).await {
+        Ok(Ok((result, fallback))) => result.map(|result| (result, fallback)),
+        Ok(Err(_)) => Err(anyhow!("compute thread dropped its result")),
+        Err(_) => {
+            claim.lock().take();
+            Err(anyhow!(
+                "timed out after {:?}",
+                timeout * COMPUTE_FALLBACK_TIMEOUT_FACTOR
+            ))
+        }
+    }
+}
+
+/// Turns a panic in compute work into an error, as it would otherwise abort the process on the compute pool.
+fn catch_compute_panic<T>(work: impl FnOnce() -> T) -> anyhow::Result<T> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(work)).map_err(|_| anyhow!("compute work panicked"))
+}
+
+/// Folds a proof verification time into the moving average.
+fn record_verification_time(avg_verification_ns: &AtomicU64, elapsed: Duration) {
+    let sample = elapsed.as_nanos() as u64;
+    let _ = avg_verification_ns.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |average| {
+        Some(match average {
+            0 => sample,
+            average => (average as f64 + (sample as f64 - average as f64) * VERIFICATION_TIME_SMOOTHING) as u64,
+        })
+    });
+}
+
+/// Caps a share difficulty so the proof target it stands for, `u64::MAX / difficulty`, stays at or above
+/// `min_valid_target`.
+fn cap_share_difficulty(difficulty: u64, min_valid_target: u64) -> u64 {
+    difficulty.min(u64::MAX / min_valid_target.max(1))
+}
+
+/// Returns the time since `started` in microseconds and restarts it for the next stage.
+fn stage_elapsed_us(started: &mut Instant) -> u64 {
+    let elapsed = started.elapsed().as_micros() as u64;
+    *started = Instant::now();
+    elapsed
+}
+
+/// Channel to an authenticated prover's connection. A prover that does not take a message within the send
+/// timeout gets its connection closed so it cannot stall everyone else.
+#[derive(Clone)]
+pub struct ProverSender {
+    peer_addr: SocketAddr,
+    sender: Sender<StratumMessage>,
+    send_timeout: Duration,
+    /// Wakes the connection task to close the connection
+    close: Arc<Notify>,
+    /// Whether the connection was told to close, shared by every clone
+    closing: Arc<AtomicBool>,
+}
+
+impl ProverSender {
+    /// Share of the connection's message channel taken by messages the prover has not read yet.
+    pub fn fill_ratio(&self) -> f64 {
+        1.0 - self.sender.capacity() as f64 / PROVER_CHANNEL_CAPACITY as f64
+    }
+
+    pub async fn send(&self, message: StratumMessage) -> anyhow::Result<()> {
+        match tokio::time::timeout(self.send_timeout, self.sender.send(message)).await {
+            Ok(result) => result.map_err(|_| anyhow!("connection closed")),
+            Err(_) => {
+                if self.close() {
+                    warn!("Prover {} is not reading messages, disconnecting", self.peer_addr);
+                }
+                Err(anyhow!("timed out after {:?}", self.send_timeout))
+            }
+        }
+    }
+
+    /// Closes the connection, returning whether this was the first request to. The connection task reports the
+    /// disconnect to the server once it has ended.
+    pub fn close(&self) -> bool {
+        if self.closing.swap(true, Ordering::SeqCst) {
+            return false;
+        }
+        self.close.notify_one();
+        true
+    }
+}
+
+/// A stats WebSocket connection, registered under each address it subscribed to.
+#[derive(Clone)]
+pub struct WsSubscriber {
+    id: u64,
+    sender: Sender<StatsUpdate>,
+}
+
+/// Proof verification cost and the CPU cores needed to keep up with the current rate.
+#[derive(Serialize)]
+pub struct CapacityEstimate {
+    avg_verification_ms: f64,
+    verifications_per_second_per_core: f64,
+    current_verification_rate: f64,
+    estimated_cores_needed: f64,
+}
+
+#[derive(Serialize)]
+pub struct WorkerStats {
+    label: Option<String>,
+    worker_name: String,
+    connections: u32,
+    speed: Vec<f64>,
+    /// Whether any of the worker's connections is not keeping up with its messages
+    backpressured: bool,
+}
+
+/// A finished prover connection. Times are unix timestamps in seconds.
+#[derive(Clone, Serialize)]
+pub struct SessionRecord {
+    label: Option<String>,
+    peer_addr: SocketAddr,
+    connected_at: u64,
+    disconnected_at: u64,
+    shares_accepted: u64,
+    peak_speed_5m: f64,
+}
+
+#[derive(Serialize)]
+pub struct FarmStats {
+    workers: u32,
+    speed: Vec<f64>,
+    accepted_shares: u64,
+    rejected_shares: u64,
+}
+
+#[derive(Serialize)]
+pub struct EarningsForecast {
+    label: Option<String>,
+    prover_share: f64,
+    expected_credit_per_block: f64,
+    blocks_per_day: f64,
+    hourly_reward_estimate: Option<f64>,
+    daily_reward_estimate: Option<f64>,
+}
+
+/// Security posture summary of `GET /admin/security/audit`.
+#[derive(Serialize)]
+pub struct SecurityAudit {
+    banned_ips: usize,
+    auth_failures_per_minute: f64,
+    high_misbehavior_provers: Vec<MisbehavingProver>,
+    seconds_since_last_event: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct MisbehavingProver {
+    peer_addr: SocketAddr,
+    address: String,
+    misbehavior_score: u32,
+}
+
+/// Failed authentications and the time of the latest security relevant event, such as a ban or a rejected
+/// connection from a banned IP.
+pub struct SecurityEvents {
+    auth_failures: Speedometer,
+    last_event: Mutex<Option<Instant>>,
+}
+
+impl Default for SecurityEvents {
+    fn default() -> Self {
+        Self {
+            auth_failures: Speedometer::init(Duration::from_secs(60)),
+            last_event: Mutex::new(None),
+        }
+    }
+}
+
+impl SecurityEvents {
+    pub async fn auth_failure(&self) {
+        self.auth_failures.event(1).await;
+        self.record();
+    }
+
+    pub fn record(&self) {
+        *self.last_event.lock() = Some(Instant::now());
+    }
+
+    pub async fn auth_failures_per_minute(&self) -> f64 {
+        self.auth_failures.speed().await * 60.0
+    }
+
+    pub fn since_last_event(&self) -> Option<Duration> {
+        self.last_event.lock().map(|instant| instant.elapsed())
+    }
+}
+
+/// Checks the tokens provers send as their password when authentication is required.
+pub struct ProverAuthenticator {
+    secret: String,
+    /// Height of the latest block template, kept up to date by the server
+    latest_height: Arc<AtomicU32>,
+}
+
+impl ProverAuthenticator {
+    /// Whether `token` is the hex encoded HMAC-SHA256 of `{address}{block_height}{secret}` keyed with the shared
+    /// secret, for the latest block height or one shortly before it.
+    pub fn token_valid(&self, address: &Address<Testnet3>, token: &str) -> bool {
+        let token = match hex::decode(token) {
+            Ok(token) => token,
+            Err(_) => return false,
+        };
+        let latest_height = self.latest_height.load(Ordering::SeqCst);
+        (latest_height.saturating_sub(AUTHENTICATION_HEIGHT_TOLERANCE)..=latest_height).any(|height| {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).expect("HMAC takes keys of any length");
+            mac.update(format!("{}{}{}", address, height, self.secret).as_bytes());
+            mac.verify_slice(&token).is_ok()
+        })
+    }
+}
+
+/// Solutions waiting to be sent to the validator, so a burst of them is not lost while the connection is busy.
+/// Commitments of queued and recently sent solutions are remembered to never submit a solution twice.
+struct SolutionSubmissionQueue {
+    pending: VecDeque<(PuzzleCommitment<Testnet3>, ProverSolution<Testnet3>)>,
+    recently_sent: VecDeque<PuzzleCommitment<Testnet3>>,
+    capacity: usize,
+}
+
+impl SolutionSubmissionQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            pending: VecDeque::with_capacity(capacity),
+            recently_sent: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns whether the solution was queued, which it is not if it is a duplicate or the queue is full.
+    fn push(&mut self, solution: ProverSolution<Testnet3>) -> bool {
+        let commitment = solution.commitment();
+        if self.recently_sent.contains(&commitment)
+            || self.pending.iter().any(|(pending, _)| *pending == commitment)
+            || self.pending.len() >= self.capacity
+        {
+            return false;
+        }
+        self.pending.push_back((commitment, solution));
+        true
+    }
+
+    fn front(&self) -> Option<ProverSolution<Testnet3>> {
+        self.pending.front().map(|(_, solution)| solution.clone())
+    }
+
+    fn mark_front_sent(&mut self) {
+        if let Some((commitment, _)) = self.pending.pop_front() {
+            if self.recently_sent.len() >= self.capacity {
+                self.recently_sent.pop_front();
+            }
+            self.recently_sent.push_back(commitment);
+        }
+    }
+}
+
+/// Notify fields of an epoch challenge, hex encoded once instead of for every prover it is sent to.
+#[derive(Clone, Debug)]
+struct ComputedJobFields {
+    job_id: String,
+    epoch_challenge: String,
+}
+
+impl ComputedJobFields {
+    /// Fails for challenges that don't fit the pool's coinbase puzzle, which provers could never solve.
+    fn new(epoch_challenge: &EpochChallenge<Testnet3>) -> anyhow::Result<Self> {
+        ensure!(
+            epoch_challenge.degree() == COINBASE_PUZZLE_DEGREE,
+            "degree {} does not match the coinbase puzzle degree {}",
+            epoch_challenge.degree(),
+            COINBASE_PUZZLE_DEGREE
+        );
+        Ok(Self {
+            job_id: hex::encode(epoch_challenge.epoch_number().to_le_bytes()),
+            epoch_challenge: hex::encode(epoch_challenge.to_bytes_le()?),
+        })
+    }
+}
+
+/// Epoch number, nonce, commitment and proof of a solution submitted by a prover.
+pub type SubmittedSolution = (
+    u32,
+    u64,
+    KZGCommitment<<Testnet3 as Environment>::PairingCurve>,
+    KZGProof<<Testnet3 as Environment>::PairingCurve>,
+);
+
+/// Where the result of a submitted solution goes.
+#[derive(Debug)]
+enum SubmitResponse {
+    /// Straight to the prover, answering its `mining.submit` request.
+    Single(Id),
+    /// To the task answering the `mining.submit_batch` request the solution came in.
+    Batch(oneshot::Sender<Value>),
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum ServerMessage {
+    ProverConnected(TcpStream, SocketAddr),
+    /// (peer_addr, address, worker_name, sender, close), where notifying `close` closes the connection
+    ProverAuthenticated(
+        SocketAddr,
+        Address<Testnet3>,
+        String,
+        Sender<StratumMessage>,
+        Arc<Notify>,
+    ),
+    ProverDisconnected(SocketAddr),
+    /// The validator connection finished its handshake
+    ValidatorConnected,
+    /// Time between sending a solution to the validator and the validator acknowledging it
+    SolutionAcknowledged(Duration),
+    /// Solutions from a `mining.submit` or, when batched, a `mining.submit_batch` request
+    /// (id, peer_addr, solutions, batched, extra_data, received_at)
+    ProverSubmit(Id, SocketAddr, Vec<SubmittedSolution>, bool, Option<Vec<u8>>, Instant),
+    /// Epoch challenge, proof target, coinbase target and height of the latest block
+    NewEpochChallenge(Arc<EpochChallenge<Testnet3>>, u64, u64, u32),
+    /// Stops accepting connections and shares and notifies the sender once buffered shares are sent to accounting
+    Exit(oneshot::Sender<()>),
+}
+
+impl ServerMessage {
+    fn name(&self) -> &'static str {
+        match self {
+            ServerMessage::ProverConnected(..) => "ProverConnected",
+            ServerMessage::ProverAuthenticated(..) => "ProverAuthenticated",
+            ServerMessage::ProverDisconnected(..) => "ProverDisconnected",
+            ServerMessage::ValidatorConnected => "ValidatorConnected",
+            ServerMessage::SolutionAcknowledged(..) => "SolutionAcknowledged",
+            ServerMessage::ProverSubmit(..) => "ProverSubmit",
+            ServerMessage::NewEpochChallenge(..) => "NewEpochChallenge",
+            ServerMessage::Exit(..) => "Exit",
+        }
+    }
+}
+
+/// Where the pool is in its life, which decides the messages it acts on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PoolLifecycle {
+    /// Not connected to a validator yet.
+    Initializing,
+    /// Connected to a validator, but no epoch challenge has arrived.
+    WaitingForEpochChallenge,
+    /// Handing out work for the latest epoch challenge.
+    Mining {
+        epoch_number: u32,
+        challenge_received_at: Instant,
+    },
+    /// Shutting down, no more work is accepted.
+    Suspended,
+}
+
+impl PoolLifecycle {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PoolLifecycle::Initializing => "initializing",
+            PoolLifecycle::WaitingForEpochChallenge => "waiting_for_epoch_challenge",
+            PoolLifecycle::Mining { .. } => "mining",
+            PoolLifecycle::Suspended => "suspended",
+        }
+    }
+}
+
+impl Display for PoolLifecycle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl Display for ServerMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub struct Server {
+    config: ServerConfig,
+    sender: Sender<ServerMessage>,
+    validator_sender: Arc<Sender<SnarkOSMessage>>,
+    accounting_sender: Arc<AccountingSender>,
+    share_batch: Arc<Mutex<Vec<ShareRecord>>>,
+    solution_queue: Arc<Mutex<SolutionSubmissionQueue>>,
+    solution_queued: Arc<Notify>,
+    pool_address: Address<Testnet3>,
+    connected_provers: RwLock<HashSet<SocketAddr>>,
+    /// Connected provers per subnet, for visibility only
+    subnet_connections: Mutex<HashMap<IpNet, u32>>,
+    authenticated_provers: Arc<RwLock<HashMap<SocketAddr, ProverSender>>>,
+    /// Provers that authenticated before the first epoch challenge, which get it with their target once it arrives
+    warming_up_provers: Mutex<Vec<(SocketAddr, ProverSender)>>,
+    pool_state: Arc<RwLock<PoolState>>,
+    prover_states: Arc<RwLock<HashMap<SocketAddr, RwLock<ProverState>>>>,
+    prover_address_connections: Arc<RwLock<HashMap<Address<Testnet3>, HashSet<SocketAddr>>>>,
+    /// Finished sessions of each address within `SESSION_HISTORY_TTL`, oldest first
+    sessions: RwLock<HashMap<Address<Testnet3>, VecDeque<SessionRecord>>>,
+    coinbase_puzzle: CoinbasePuzzle<Testnet3>,
+    /// Epoch number, challenge and its notify fields are swapped together so readers never see one without the
+    /// others.
+    latest_epoch: ArcSwap<Option<(u32, Arc<EpochChallenge<Testnet3>>, ComputedJobFields)>>,
+    latest_proof_target: AtomicU64,
+    latest_height: Arc<AtomicU32>,
+    /// Set when provers have to authenticate, and handed to their connections
+    authenticator: Option<Arc<ProverAuthenticator>>,
+    last_known_coinbase_target: AtomicU64,
+    lifecycle: Mutex<PoolLifecycle>,
+    /// Banned IPs and when their ban ends
+    banned_ips: Arc<RwLock<HashMap<IpAddr, Instant>>>,
+    security_events: Arc<SecurityEvents>,
+    admin_token: ArcSwap<Option<String>>,
+    /// A rotated out admin token and when it stops being accepted
+    previous_admin_token: Mutex<Option<(String, Instant)>>,
+    /// Misbehavior scores of disconnected provers and when they were recorded, carried over to a new connection
+    /// from the same IP
+    misbehavior_scores: RwLock<HashMap<IpAddr, (u32, Instant)>>,
+    recent_epoch_challenges: Arc<RwLock<VecDeque<Arc<EpochChallenge<Testnet3>>>>>,
+    history: RwLock<VecDeque<HistorySample>>,
+    difficulty_history: RwLock<VecDeque<DifficultySample>>,
+    contributions: Arc<Mutex<ContributionRounds>>,
+    nonce_seen: Arc<FlurryHashSet<(u32, u64)>>,
+    /// SHA-256 of the proofs accepted for verification, cleared along with `nonce_seen`
+    proof_hash_seen: Arc<FlurryHashSet<[u8; 32]>>,
+    shares_in_flight: Arc<AtomicU64>,
+    pipeline_samples: Arc<Mutex<VecDeque<[u64; 4]>>>,
+    avg_verification_ns: Arc<AtomicU64>,
+    compute_pool: Option<Arc<ThreadPool>>,
+    compute_fallback_permits: Arc<Semaphore>,
+    verification_speed: Arc<Speedometer>,
+    /// Admitted connections, not counting the ones refused
+    connection_rate_1m: Speedometer,
+    connection_rate_5m: Speedometer,
+    /// Recently admitted connections per subnet, for flood protection
+    subnet_connection_floods: Mutex<HashMap<IpNet, SubnetConnectionFlood>>,
+    /// Days until the submission rate would use up the nonce space of an epoch, if there are submissions
+    nonce_exhaustion_estimate_days: Mutex<Option<f64>>,
+    /// Prover connections accepted and closed since the start of the hour
+    connects_this_hour: AtomicU64,
+    disconnects_this_hour: AtomicU64,
+    /// Height of the block that started the current round and when it was seen
+    round: Mutex<Option<(u32, Instant)>>,
+    round_shares: Arc<AtomicU64>,
+    /// Address of the first share accepted in the current round
+    round_first_share: Arc<Mutex<Option<Address<Testnet3>>>>,
+    /// Verification results by epoch, nonce, commitment and proof
+    proof_cache: Arc<Mutex<LruCache<Bytes, bool>>>,
+    /// Submission error messages by error code, replacing the built-in ones
+    error_messages: Arc<HashMap<u32, String>>,
+    tenants: Arc<Vec<TenantConfig>>,
+    metrics: Arc<Metrics>,
+    audit_log: Option<Arc<AuditLog>>,
+    supported_stratum_versions: Arc<Vec<Version>>,
+    connection_events: broadcast::Sender<PoolEvent>,
+    /// Stats WebSocket subscribers by the address they receive share updates of
+    address_subscribers: Arc<RwLock<HashMap<Address<Testnet3>, Vec<WsSubscriber>>>>,
+    next_subscriber_id: AtomicU64,
+    accept_tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Server {
+    pub async fn init(
+        listen_addrs: Vec<SocketAddr>,
+        address: Address<Testnet3>,
+        validator_sender: Arc<Sender<SnarkOSMessage>>,
+        accounting_sender: Sender<AccountingMessage>,
+        config: ServerConfig,
+    ) -> Arc<Server> {
+        let (sender, mut receiver) = channel(1024);
+
+        let mut listeners = Vec::with_capacity(listen_addrs.len());
+        for listen_addr in listen_addrs {
+            match TcpListener::bind(listen_addr).await {
+                Ok(listener) => {
+                    let local_ip = listener.local_addr().expect("Could not get local ip");
+                    info!("Listening on {}", local_ip);
+                    listeners.push(listener);
+                }
+                Err(e) => {
+                    panic!("Unable to start the server on {}: {:?}", listen_addr, e);
+                }
+            }
+        }
+
+        info!("Initializing universal SRS");
+        let srs = UniversalSRS::<Testnet3>::load().expect("Failed to load SRS");
+        info!("Universal SRS initialized");
 
         info!("Initializing coinbase verifying key");
-        let coinbase_puzzle = CoinbasePuzzle::<Testnet3>::trim(&srs, PuzzleConfig { degree: (1 << 13) - 1 })
-            .expect("Failed to load coinbase verifying key");
+        let coinbase_puzzle = CoinbasePuzzle::<Testnet3>::trim(
+            &srs,
+            PuzzleConfig {
+                degree: COINBASE_PUZZLE_DEGREE,
+            },
+        )
+        .expect("Failed to load coinbase verifying key");
         info!("Coinbase verifying key initialized");
 
-        let server = Arc::new(Server {
-            sender,
-            validator_sender,
-            accounting_sender,
-            pool_address: address,
-            connected_provers: Default::default(),
-            authenticated_provers: Default::default(),
-            pool_state: Arc::new(RwLock::new(PoolState::new())),
-            prover_states: Default::default(),
-            prover_address_connections: Default::default(),
-            coinbase_puzzle,
-            latest_epoch_number: AtomicU32::new(0),
-            latest_epoch_challenge: Default::default(),
-            latest_proof_target: AtomicU64::new(u64::MAX),
-            nonce_seen: Arc::new(FlurryHashSet::with_capacity(10 << 20)),
-        });
+        let metrics = Arc::new(Metrics::init());
+        metrics.nonce_seen_capacity.set(config.nonce_cache_capacity as i64);
+
+        let nonce_seen = Arc::new(FlurryHashSet::with_capacity(config.nonce_cache_capacity));
+        let proof_hash_seen = Arc::new(FlurryHashSet::with_capacity(config.nonce_cache_capacity));
+
+        let compute_pool = config.compute_cores.as_deref().map(build_compute_pool);
+
+        let latest_height = Arc::new(AtomicU32::new(0));
+        let authenticator = config
+            .authentication_secret
+            .clone()
+            .filter(|_| config.require_authentication)
+            .map(|secret| {
+                Arc::new(ProverAuthenticator {
+                    secret,
+                    latest_height: latest_height.clone(),
+                })
+            });
+
+        let audit_log = config.audit_log_path.as_deref().map(|path| {
+            info!("Writing authentication audit log to {}", path.display());
+            Arc::new(AuditLog::init(path))
+        });
+
+        let max_queued_solutions = config.max_queued_solutions;
+        let proof_cache_capacity = NonZeroUsize::new(config.proof_cache_capacity.max(1)).unwrap();
+        let difficulty_histogram_bounds = config.difficulty_histogram_bounds.clone();
+        let error_messages = config.error_messages.clone();
+        let tenants = config.tenants.clone();
+        let admin_token = config.admin_token.clone();
+        let supported_stratum_versions = config
+            .supported_stratum_versions
+            .iter()
+            .filter_map(|version| Version::parse(version).ok())
+            .collect::<Vec<_>>();
+
+        let server = Arc::new(Server {
+            config,
+            sender,
+            validator_sender,
+            accounting_sender: Arc::new(AccountingSender::new(accounting_sender)),
+            share_batch: Default::default(),
+            solution_queue: Arc::new(Mutex::new(SolutionSubmissionQueue::new(max_queued_solutions))),
+            solution_queued: Default::default(),
+            pool_address: address,
+            connected_provers: Default::default(),
+            subnet_connections: Default::default(),
+            authenticated_provers: Default::default(),
+            warming_up_provers: Default::default(),
+            pool_state: Arc::new(RwLock::new(PoolState::new(difficulty_histogram_bounds))),
+            prover_states: Default::default(),
+            prover_address_connections: Default::default(),
+            sessions: Default::default(),
+            coinbase_puzzle,
+            latest_epoch: Default::default(),
+            latest_proof_target: AtomicU64::new(u64::MAX),
+            latest_height,
+            authenticator,
+            last_known_coinbase_target: AtomicU64::new(0),
+            lifecycle: Mutex::new(PoolLifecycle::Initializing),
+            banned_ips: Default::default(),
+            security_events: Default::default(),
+            admin_token: ArcSwap::from_pointee(admin_token),
+            previous_admin_token: Default::default(),
+            misbehavior_scores: Default::default(),
+            recent_epoch_challenges: Default::default(),
+            history: Default::default(),
+            difficulty_history: Default::default(),
+            contributions: Default::default(),
+            nonce_seen,
+            proof_hash_seen,
+            shares_in_flight: Default::default(),
+            pipeline_samples: Arc::new(Mutex::new(VecDeque::with_capacity(PIPELINE_SAMPLE_SIZE))),
+            avg_verification_ns: Default::default(),
+            compute_pool,
+            compute_fallback_permits: Arc::new(Semaphore::new(MAX_COMPUTE_FALLBACKS)),
+            verification_speed: Arc::new(Speedometer::init(Duration::from_secs(60))),
+            connection_rate_1m: Speedometer::init_with_cache(Duration::from_secs(60), Duration::from_secs(1)),
+            connection_rate_5m: Speedometer::init_with_cache(Duration::from_secs(60 * 5), Duration::from_secs(1)),
+            subnet_connection_floods: Default::default(),
+            nonce_exhaustion_estimate_days: Default::default(),
+            connects_this_hour: AtomicU64::new(0),
+            disconnects_this_hour: AtomicU64::new(0),
+            round: Default::default(),
+            round_shares: Default::default(),
+            round_first_share: Default::default(),
+            proof_cache: Arc::new(Mutex::new(LruCache::new(proof_cache_capacity))),
+            error_messages: Arc::new(error_messages),
+            tenants: Arc::new(tenants),
+            metrics,
+            audit_log,
+            supported_stratum_versions: Arc::new(supported_stratum_versions),
+            connection_events: broadcast::channel(CONNECTION_EVENT_CAPACITY).0,
+            address_subscribers: Default::default(),
+            next_subscriber_id: AtomicU64::new(0),
+            accept_tasks: Default::default(),
+        });
+
+        // clear nonce
+        // The set is cleared on every new epoch challenge; this only bounds its size if epochs stop arriving.
+        {
+            let nonce = server.nonce_seen.clone();
+            let proof_hash = server.proof_hash_seen.clone();
+            let mut ticker = tokio::time::interval(NONCE_CLEAR_BACKUP_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    nonce.pin().clear();
+                    proof_hash.pin().clear();
+                }
+            });
+        }
+
+        // sample nonce set size
+        {
+            let nonce = server.nonce_seen.clone();
+            let metrics = server.metrics.clone();
+            let mut ticker = tokio::time::interval(Duration::from_secs(10));
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    metrics.nonce_seen_size.set(nonce.len() as i64);
+                }
+            });
+        }
+
+        // sample pool and address metrics
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(Duration::from_secs(10));
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    s.sample_metrics().await;
+                }
+            });
+        }
+
+        // monitor epoch challenge age
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(Duration::from_secs(10));
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    if let Some(age) = s.template_age().await {
+                        s.metrics.block_template_age_seconds.set(age.as_secs() as i64);
+                        if age.as_secs() > s.config.stale_template_warn_threshold_secs {
+                            warn!(
+                                "No new epoch challenge received for {} seconds, is the validator in sync?",
+                                age.as_secs()
+                            );
+                        }
+                    }
+                }
+            });
+        }
+
+        // flush accounting share batch
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(Duration::from_millis(s.config.accounting_batch_interval_ms));
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    flush_share_batch(&s.share_batch, &s.accounting_sender).await;
+                }
+            });
+        }
+
+        // sample pool size history
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(HISTORY_SAMPLE_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    let sample = HistorySample {
+                        timestamp: SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or_default(),
+                        prover_count: s.online_provers().await,
+                        address_count: s.online_addresses().await,
+                    };
+                    let mut history = s.history.write().await;
+                    history.push_back(sample);
+                    while history.len() > s.config.history_retention_minutes {
+                        history.pop_front();
+                    }
+                }
+            });
+        }
+
+        // rotate fee wallets
+        if !server.config.fee_wallets.is_empty() {
+            let s = server.clone();
+            task::spawn(async move {
+                let mut current = None;
+                loop {
+                    let active = FeeWallet::active(&s.config.fee_wallets, Utc::now());
+                    if active.is_some() && active != current {
+                        current = active;
+                        if let Some(address) = active {
+                            if let Err(e) = s
+                                .accounting_sender
+                                .send(|sequence| AccountingMessage::FeeWallet(sequence, address.to_string()))
+                                .await
+                            {
+                                error!("Error sending accounting message: {}", e);
+                            }
+                        }
+                    }
+                    match FeeWallet::next_rotation(&s.config.fee_wallets, Utc::now()) {
+                        Some(next) => sleep((next - Utc::now()).to_std().unwrap_or_default()).await,
+                        None => break,
+                    }
+                }
+            });
+        }
+
+        // reconcile prover states with the address index
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(PROVER_STATE_CHECK_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    let reconciled = s.reconcile_prover_states().await;
+                    if reconciled > 0 {
+                        s.metrics.prover_state_reconciliations.inc_by(reconciled as u64);
+                    }
+                }
+            });
+        }
+
+        // watch prover message channels for backpressure
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(BACKPRESSURE_CHECK_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    s.check_backpressure().await;
+                }
+            });
+        }
+
+        // apply the global difficulty modifier early on share rate spikes
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(SPEED_SPIKE_CHECK_INTERVAL);
+            task::spawn(async move {
+                let mut previous_rate = 0.0;
+                loop {
+                    ticker.tick().await;
+                    let rate = s.pool_state.read().await.share_rate().await;
+                    let growth = if previous_rate > 0.0 { rate / previous_rate } else { 0.0 };
+                    previous_rate = rate;
+                    if growth < s.config.spike_detection_threshold {
+                        continue;
+                    }
+                    let raised = s.pool_state.write().await.raise_global_target_modifier();
+                    let (previous_modifier, modifier) = match raised {
+                        Some(modifiers) => modifiers,
+                        None => continue,
+                    };
+                    warn!(
+                        "Share rate grew {:.1}x to {:.2}/s, raising the global difficulty modifier from {} to {}",
+                        growth, rate, previous_modifier, modifier
+                    );
+                    let proof_target = s.latest_proof_target.load(Ordering::SeqCst);
+                    let provers = s.authenticated_provers.read().await.clone();
+                    for (peer_addr, sender) in provers.iter() {
+                        let target = match s.prover_states.read().await.get(peer_addr) {
+                            Some(prover_state) => prover_state.read().await.current_target(),
+                            None => continue,
+                        };
+                        let target = ((target as f64 * modifier) as u64).min(proof_target);
+                        let capped = cap_share_difficulty(target, s.config.min_valid_target);
+                        if capped < target {
+                            warn!(
+                                "Capping the difficulty of prover {} at {} instead of {} to keep its target achievable",
+                                peer_addr, capped, target
+                            );
+                        }
+                        if let Err(e) = sender.send(StratumMessage::SetTarget(capped)).await {
+                            error!("Error sending raised target to prover {}: {}", peer_addr, e);
+                        }
+                    }
+                }
+            });
+        }
+
+        // check for hashrate drops
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(HASHRATE_DROP_CHECK_INTERVAL);
+            task::spawn(async move {
+                let client = reqwest::Client::new();
+                let mut alarm_active = false;
+                loop {
+                    ticker.tick().await;
+                    let speed = s.pool_speed().await;
+                    let (speed_5m, speed_1h) = (speed[0], speed[3]);
+                    let threshold_percent = s.config.hashrate_drop_alarm_threshold_percent;
+                    let dropped = speed_1h > 0.0 && speed_5m < speed_1h * threshold_percent / 100.0;
+                    s.metrics.pool_hashrate_drop_alarm.set(dropped as i64);
+                    if dropped {
+                        warn!(
+                            "Pool speed dropped to {} over 5 minutes from {} over 1 hour",
+                            format_hashrate(speed_5m),
+                            format_hashrate(speed_1h)
+                        );
+                        if !alarm_active {
+                            if let Some(url) = &s.config.alarm_webhook_url {
+                                let body = json!({
+                                    "alarm": "pool_hashrate_drop",
+                                    "speed_5m": speed_5m,
+                                    "speed_1h": speed_1h,
+                                    "threshold_percent": threshold_percent,
+                                });
+                                match client.post(url).json(&body).send().await {
+                                    Ok(response) if !response.status().is_success() => {
+                                        warn!("Alarm webhook returned {}", response.status());
+                                    }
+                                    Ok(_) => {}
+                                    Err(e) => warn!("Failed to call alarm webhook: {}", e),
+                                }
+                            }
+                        }
+                    } else if alarm_active {
+                        info!("Pool speed recovered to {} over 5 minutes", format_hashrate(speed_5m));
+                    }
+                    alarm_active = dropped;
+                }
+            });
+        }
 
-        // clear nonce
+        // lift expired bans and forget old misbehavior scores
         {
-            let nonce = server.nonce_seen.clone();
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(MISBEHAVIOR_PRUNE_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    s.banned_ips.write().await.retain(|ip, until| {
+                        let banned = *until > Instant::now();
+                        if !banned {
+                            info!("Ban of {} expired", ip);
+                        }
+                        banned
+                    });
+                    s.misbehavior_scores
+                        .write()
+                        .await
+                        .retain(|_, (_, recorded_at)| recorded_at.elapsed() < MISBEHAVIOR_SCORE_TTL);
+                }
+            });
+        }
+
+        // forget finished sessions older than the history keeps
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(SESSION_PRUNE_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    let cutoff = SystemTime::now()
+                        .checked_sub(SESSION_HISTORY_TTL)
+                        .and_then(|cutoff| cutoff.duration_since(UNIX_EPOCH).ok())
+                        .map(|cutoff| cutoff.as_secs())
+                        .unwrap_or_default();
+                    s.sessions.write().await.retain(|_, sessions| {
+                        sessions.retain(|session| session.disconnected_at >= cutoff);
+                        !sessions.is_empty()
+                    });
+                }
+            });
+        }
+
+        // refresh the cached prover speeds
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(Duration::from_secs(
+                server.config.speedometer.cache_interval_secs.max(1),
+            ));
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    for prover_state in s.prover_states.read().await.values() {
+                        prover_state.write().await.refresh_speed().await;
+                    }
+                }
+            });
+        }
+
+        // update performance score
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(PERFORMANCE_SCORE_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    let score = s
+                        .pool_state
+                        .write()
+                        .await
+                        .update_performance_score(&s.config.performance_score)
+                        .await;
+                    s.metrics.pool_performance_score.set(score);
+                }
+            });
+        }
+
+        // forget subnets that stopped connecting
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(CONNECTION_FLOOD_WINDOW);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    s.prune_connection_floods();
+                }
+            });
+        }
+
+        // report prover churn every hour
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(CHURN_RESET_INTERVAL);
+            task::spawn(async move {
+                // The first tick completes immediately.
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    info!(
+                        "Prover connections in the last hour: {} connects, {} disconnects",
+                        s.connects_this_hour.swap(0, Ordering::SeqCst),
+                        s.disconnects_this_hour.swap(0, Ordering::SeqCst)
+                    );
+                }
+            });
+        }
+
+        // warn when provers disconnect much faster than they connect
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(CHURN_CHECK_INTERVAL);
+            task::spawn(async move {
+                let mut previous = (0, 0);
+                loop {
+                    ticker.tick().await;
+                    let connects = s.metrics.pool_prover_connects.get();
+                    let disconnects = s.metrics.pool_prover_disconnects.get();
+                    let (window_connects, window_disconnects) = (connects - previous.0, disconnects - previous.1);
+                    previous = (connects, disconnects);
+                    if window_disconnects > window_connects * CHURN_WARN_RATIO {
+                        warn!(
+                            "{} provers disconnected but only {} connected in the last {:?}, connections may be \
+                             unstable",
+                            window_disconnects, window_connects, CHURN_CHECK_INTERVAL
+                        );
+                    }
+                }
+            });
+        }
+
+        // warn when proof verification often falls back off the compute pool
+        if server.compute_pool.is_some() {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(VERIFICATION_FALLBACK_CHECK_INTERVAL);
+            task::spawn(async move {
+                let mut previous = (0, 0);
+                loop {
+                    ticker.tick().await;
+                    let verifications = s.metrics.proof_verifications.get();
+                    let fallbacks = s.metrics.proof_verification_fallbacks.get();
+                    let (window_verifications, window_fallbacks) = (verifications - previous.0, fallbacks - previous.1);
+                    previous = (verifications, fallbacks);
+                    if window_verifications == 0 {
+                        continue;
+                    }
+                    let ratio = window_fallbacks as f64 / window_verifications as f64;
+                    if ratio > s.config.verification_fallback_warn_ratio {
+                        warn!(
+                            "{:.1}% of proof verifications in the last {:?} were not started in time by the compute \
+                             pool, check the load of its CPU cores or add more with --compute-cores",
+                            ratio * 100.0,
+                            VERIFICATION_FALLBACK_CHECK_INTERVAL
+                        );
+                    }
+                }
+            });
+        }
+
+        // export prover stats to a csv file
+        if let Some(path) = server.config.prover_stats_export_path.as_deref() {
+            info!("Exporting prover stats to {}", path.display());
+            let export = Arc::new(ProverStatsExport::init(path));
+            let s = server.clone();
+            let mut ticker =
+                tokio::time::interval(Duration::from_secs(server.config.prover_stats_export_interval_secs));
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    let rows = s.prover_stats_rows().await;
+                    let export = export.clone();
+                    match task::spawn_blocking(move || export.append(&rows)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => error!("Failed to export prover stats: {}", e),
+                        Err(e) => error!("Prover stats export task failed: {}", e),
+                    }
+                }
+            });
+        }
+
+        // estimate when the submission rate would exhaust the nonce space
+        {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(NONCE_EXHAUSTION_CHECK_INTERVAL);
+            task::spawn(async move {
+                let mut previous_submissions = None;
+                loop {
+                    ticker.tick().await;
+                    let submissions = s.pool_state.read().await.total_submissions();
+                    let rate = match previous_submissions.replace(submissions) {
+                        Some(previous) => {
+                            submissions.saturating_sub(previous) as f64 / NONCE_EXHAUSTION_CHECK_INTERVAL.as_secs_f64()
+                        }
+                        None => continue,
+                    };
+                    let estimate = if rate > 0.0 {
+                        Some(NONCE_SPACE_SIZE / (rate * 86400.0))
+                    } else {
+                        None
+                    };
+                    *s.nonce_exhaustion_estimate_days.lock() = estimate;
+                }
+            });
+        }
+
+        // check idle provers
+        {
+            let s = server.clone();
             let mut ticker = tokio::time::interval(Duration::from_secs(60));
             task::spawn(async move {
                 loop {
                     ticker.tick().await;
-                    nonce.pin().clear()
+                    let idle_timeout = Duration::from_secs(s.config.idle_timeout_secs);
+                    let mut idle_provers = Vec::new();
+                    for (peer_addr, prover_state) in s.prover_states.read().await.iter() {
+                        let prover_state = prover_state.read().await;
+                        let idle_time = prover_state.idle_time();
+                        if idle_time > idle_timeout {
+                            warn!(
+                                "Prover {} has not submitted a valid share for {} seconds",
+                                prover_state,
+                                idle_time.as_secs()
+                            );
+                            idle_provers.push(*peer_addr);
+                        }
+                    }
+                    if s.config.disconnect_idle_provers {
+                        for peer_addr in idle_provers {
+                            s.kick_prover(peer_addr, "Idle timeout".to_string()).await;
+                        }
+                    }
                 }
             });
         }
 
-        let s = server.clone();
-        task::spawn(async move {
-            loop {
-                match listener.accept().await {
-                    Ok((stream, peer_addr)) => {
-                        info!("New connection from: {}", peer_addr);
-                        if let Err(e) = s.sender.send(ServerMessage::ProverConnected(stream, peer_addr)).await {
-                            error!("Error accepting connection: {}", e);
+        // lower the target of provers that stopped submitting
+        if server.config.difficulty_fallback_idle_secs > 0 {
+            let s = server.clone();
+            let mut ticker = tokio::time::interval(DIFFICULTY_FALLBACK_CHECK_INTERVAL);
+            task::spawn(async move {
+                loop {
+                    ticker.tick().await;
+                    let idle = Duration::from_secs(s.config.difficulty_fallback_idle_secs);
+                    let mut lowered = Vec::new();
+                    for (peer_addr, prover_state) in s.prover_states.read().await.iter() {
+                        let mut prover_state = prover_state.write().await;
+                        if let Some(target) = prover_state.fallback_target(
+                            idle,
+                            s.config.difficulty_fallback_factor,
+                            s.config.difficulty_fallback_max_reductions,
+                        ) {
+                            info!(
+                                "Prover {} submitted nothing for {:?}, lowering target to {}",
+                                prover_state, idle, target
+                            );
+                            lowered.push((*peer_addr, target));
                         }
                     }
-                    Err(e) => {
-                        error!("Error accepting connection: {:?}", e);
+                    if lowered.is_empty() {
+                        continue;
+                    }
+                    let global_difficulty_modifier = s.pool_state.read().await.current_global_target_modifier();
+                    let proof_target = s.latest_proof_target.load(Ordering::SeqCst);
+                    let provers = s.authenticated_provers.read().await;
+                    for (peer_addr, target) in lowered {
+                        let target = ((target as f64 * global_difficulty_modifier) as u64).min(proof_target);
+                        let target = cap_share_difficulty(target, s.config.min_valid_target);
+                        if let Some(sender) = provers.get(&peer_addr) {
+                            if let Err(e) = sender.send(StratumMessage::SetTarget(target)).await {
+                                error!("Error sending lowered target to prover {}: {}", peer_addr, e);
+                            }
+                        }
                     }
                 }
-            }
-        });
+            });
+        }
+
+        // send queued solutions to the validator
+        {
+            let s = server.clone();
+            task::spawn(async move {
+                loop {
+                    s.solution_queued.notified().await;
+                    // Only the front is removed once sent, so a failed send is retried with the same solution.
+                    loop {
+                        let solution = match s.solution_queue.lock().front() {
+                            Some(solution) => solution,
+                            None => break,
+                        };
+                        // TODO: dummy operator
+                        match s
+                            .validator_sender
+                            .send(SnarkOSMessage::UnconfirmedSolution(UnconfirmedSolution {
+                                puzzle_commitment: solution.commitment(),
+                                solution: Data::Object(solution),
+                            }))
+                            .await
+                        {
+                            Ok(()) => s.solution_queue.lock().mark_front_sent(),
+                            Err(e) => {
+                                error!("Failed to report unconfirmed solution to operator, retrying: {}", e);
+                                sleep(SOLUTION_SUBMIT_RETRY_INTERVAL).await;
+                            }
+                        }
+                    }
+                }
+            });
+        }
+
+        // Every listener feeds the same server channel.
+        for listener in listeners {
+            let s = server.clone();
+            let handle = task::spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, peer_addr)) => {
+                            info!("New connection from: {}", peer_addr);
+                            if s.connection_flooded(peer_addr) {
+                                s.metrics.connection_rejected("flood");
+                                task::spawn(Connection::reject(
+                                    stream,
+                                    peer_addr,
+                                    "Too many connections".to_string(),
+                                ));
+                                continue;
+                            }
+                            if let Err(e) = configure_socket(&stream, &s.config.tcp_options) {
+                                warn!("Failed to set socket options for {}: {}", peer_addr, e);
+                            }
+                            // Never block here: a stalled processing loop would stop us from accepting anything.
+                            match s.sender.try_send(ServerMessage::ProverConnected(stream, peer_addr)) {
+                                Ok(()) => s.record_admitted_connection().await,
+                                Err(TrySendError::Full(ServerMessage::ProverConnected(stream, peer_addr))) => {
+                                    warn!("Server busy, rejecting connection from {}", peer_addr);
+                                    s.metrics.connection_rejected("backpressure");
+                                    task::spawn(Connection::reject(stream, peer_addr, "Server busy".to_string()));
+                                }
+                                Err(e) => {
+                                    error!("Error accepting connection: {}", e);
+                                    s.metrics.connection_rejected("accept_error");
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            error!("Error accepting connection: {:?}", e);
+                            s.metrics.connection_rejected("accept_error");
+                        }
+                    }
+                }
+            });
+            server.accept_tasks.lock().push(handle);
+        }
 
         let s = server.clone();
         task::spawn(async move {
@@ -325,50 +2246,308 @@ impl Server {
         server
     }
 
-    fn seen_nonce(nonce_seen: Arc<FlurryHashSet<u64>>, nonce: u64) -> bool {
-        !nonce_seen.pin().insert(nonce)
+    fn seen_nonce(nonce_seen: Arc<FlurryHashSet<(u32, u64)>>, epoch_number: u32, nonce: u64) -> bool {
+        !nonce_seen.pin().insert((epoch_number, nonce))
+    }
+
+    /// Returns whether connections from the subnet of `peer_addr` come in too fast to take this one, and counts
+    /// it for the subnet if not. Refused attempts don't count, so reconnecting provers can't keep a subnet flooded,
+    /// and a flooding subnet is only accepted again once its rate has fallen well below the threshold.
+    fn connection_flooded(&self, peer_addr: SocketAddr) -> bool {
+        let threshold = self.config.connection_flood_threshold;
+        if threshold <= 0.0 {
+            return false;
+        }
+        let subnet = self.connection_subnet(peer_addr.ip());
+        let now = Instant::now();
+        let mut floods = self.subnet_connection_floods.lock();
+        let flood = floods.entry(subnet).or_default();
+        while flood
+            .admitted
+            .front()
+            .map_or(false, |admitted| now - *admitted > CONNECTION_FLOOD_WINDOW)
+        {
+            flood.admitted.pop_front();
+        }
+        let rate = flood.admitted.len() as f64 / CONNECTION_FLOOD_WINDOW.as_secs_f64();
+        let flooded = if flood.flooded {
+            rate >= threshold * CONNECTION_FLOOD_RESUME_RATIO
+        } else {
+            rate > threshold
+        };
+        if flooded != flood.flooded {
+            flood.flooded = flooded;
+            if flooded {
+                warn!(
+                    "{:.1} connections per second from {}, refusing its new connections",
+                    rate, subnet
+                );
+                self.security_events.record();
+            } else {
+                info!(
+                    "Connection rate from {} back to {:.1} per second, accepting its connections",
+                    subnet, rate
+                );
+            }
+        }
+        if !flooded {
+            flood.admitted.push_back(now);
+        }
+        flooded
+    }
+
+    async fn record_admitted_connection(&self) {
+        self.connection_rate_1m.event(1).await;
+        self.connection_rate_5m.event(1).await;
+    }
+
+    /// Forgets subnets without admitted connections in the flood window.
+    fn prune_connection_floods(&self) {
+        self.subnet_connection_floods.lock().retain(|_, flood| {
+            flood
+                .admitted
+                .back()
+                .map_or(false, |admitted| admitted.elapsed() <= CONNECTION_FLOOD_WINDOW)
+        });
+    }
+
+    /// Admitted connections per second over the last 1 and 5 minutes.
+    pub async fn connection_rate(&self) -> (f64, f64) {
+        (
+            self.connection_rate_1m.speed().await,
+            self.connection_rate_5m.speed().await,
+        )
+    }
+
+    /// Starts a new round if `height` is past the block that started the current one, returning the share count and
+    /// duration in seconds of the round that ended.
+    fn start_round(&self, height: u32) -> Option<(u64, u64)> {
+        let mut round = self.round.lock();
+        let (previous_height, started_at) = match *round {
+            Some((previous_height, _)) if previous_height >= height => return None,
+            Some(previous_round) => previous_round,
+            None => {
+                *round = Some((height, Instant::now()));
+                self.round_shares.store(0, Ordering::SeqCst);
+                *self.round_first_share.lock() = None;
+                return None;
+            }
+        };
+        *round = Some((height, Instant::now()));
+        let round_shares = self.round_shares.swap(0, Ordering::SeqCst);
+        *self.round_first_share.lock() = None;
+        debug!(
+            "Round started at height {} ended with {} shares after {:?}",
+            previous_height,
+            round_shares,
+            started_at.elapsed()
+        );
+        Some((round_shares, started_at.elapsed().as_secs()))
+    }
+
+    pub fn nonce_exhaustion_estimate_days(&self) -> Option<f64> {
+        *self.nonce_exhaustion_estimate_days.lock()
     }
 
     fn clear_nonce(&self) {
-        self.nonce_seen.pin().clear()
+        self.nonce_seen.pin().clear();
+        self.proof_hash_seen.pin().clear();
     }
 
     pub fn sender(&self) -> Sender<ServerMessage> {
         self.sender.clone()
     }
 
+    fn publish_connection_event(&self, event: &'static str, peer_addr: SocketAddr, address: Option<Address<Testnet3>>) {
+        // Sending only fails when nobody is subscribed.
+        let _ = self.connection_events.send(PoolEvent {
+            event: event.to_string(),
+            peer_addr,
+            aleo_address: address.map(|address| address.to_string()),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        });
+    }
+
+    pub fn subscribe_connection_events(&self) -> broadcast::Receiver<PoolEvent> {
+        self.connection_events.subscribe()
+    }
+
+    pub fn stats_subscriber(&self) -> (WsSubscriber, Receiver<StatsUpdate>) {
+        let (sender, receiver) = channel(STATS_SUBSCRIBER_CAPACITY);
+        let id = self.next_subscriber_id.fetch_add(1, Ordering::SeqCst);
+        (WsSubscriber { id, sender }, receiver)
+    }
+
+    /// Replaces the addresses `subscriber` receives share updates of.
+    pub async fn subscribe_address_stats(&self, subscriber: &WsSubscriber, addresses: &[Address<Testnet3>]) {
+        let mut address_subscribers = self.address_subscribers.write().await;
+        Server::remove_subscriber(&mut address_subscribers, subscriber.id);
+        for address in addresses {
+            address_subscribers
+                .entry(*address)
+                .or_default()
+                .push(subscriber.clone());
+        }
+    }
+
+    pub async fn unsubscribe_address_stats(&self, subscriber: &WsSubscriber) {
+        Server::remove_subscriber(&mut *self.address_subscribers.write().await, subscriber.id);
+    }
+
+    fn remove_subscriber(address_subscribers: &mut HashMap<Address<Testnet3>, Vec<WsSubscriber>>, id: u64) {
+        address_subscribers.retain(|_, subscribers| {
+            subscribers.retain(|subscriber| subscriber.id != id);
+            !subscribers.is_empty()
+        });
+    }
+
+    fn latest_epoch_number(&self) -> u32 {
+        self.latest_epoch
+            .load()
+            .as_ref()
+            .as_ref()
+            .map_or(0, |(epoch_number, ..)| *epoch_number)
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
     pub async fn process_message(&self, msg: ServerMessage) {
         trace!("Received message: {}", msg);
         match msg {
             ServerMessage::ProverConnected(stream, peer_addr) => {
+                let banned = self
+                    .banned_ips
+                    .read()
+                    .await
+                    .get(&peer_addr.ip())
+                    .map_or(false, |until| *until > Instant::now());
+                if banned {
+                    info!("Rejecting connection from banned peer {}", peer_addr);
+                    self.metrics.connection_rejected("ban");
+                    self.security_events.record();
+                    return;
+                }
+                if let Some(max_provers) = self.config.max_provers {
+                    if self.connected_provers.read().await.len() >= max_provers {
+                        info!("Pool is full, rejecting connection from {}", peer_addr);
+                        self.metrics.connection_rejected("pool_full");
+                        task::spawn(Connection::reject_request(stream, peer_addr, "Pool full"));
+                        return;
+                    }
+                }
                 self.connected_provers.write().await.insert(peer_addr);
-                Connection::init(stream, peer_addr, self.sender.clone(), self.pool_address).await;
+                self.connects_this_hour.fetch_add(1, Ordering::SeqCst);
+                self.metrics.pool_prover_connects.inc();
+                *self
+                    .subnet_connections
+                    .lock()
+                    .entry(self.connection_subnet(peer_addr.ip()))
+                    .or_default() += 1;
+                self.publish_connection_event("prover_connected", peer_addr, None);
+                Connection::init(
+                    stream,
+                    peer_addr,
+                    self.sender.clone(),
+                    ConnectionContext {
+                        pool_address: self.pool_address,
+                        dead_connection_timeout: Duration::from_secs(self.config.dead_connection_timeout_secs),
+                        max_deserialization_errors: self.config.max_deserialization_errors_per_session,
+                        max_protocol_errors: self.config.max_protocol_errors_before_disconnect,
+                        max_batch_size: self.config.max_batch_size,
+                        metrics: self.metrics.clone(),
+                        audit_log: self.audit_log.clone(),
+                        security_events: self.security_events.clone(),
+                        authenticator: self.authenticator.clone(),
+                        supported_stratum_versions: self.supported_stratum_versions.clone(),
+                    },
+                )
+                .await;
             }
-            ServerMessage::ProverAuthenticated(peer_addr, address, sender) => {
+            ServerMessage::ProverAuthenticated(peer_addr, address, worker_name, sender, close) => {
+                self.publish_connection_event("prover_authenticated", peer_addr, Some(address));
+                let sender = ProverSender {
+                    peer_addr,
+                    sender,
+                    send_timeout: Duration::from_secs(self.config.response_send_timeout_secs),
+                    close,
+                    closing: Default::default(),
+                };
+                // A prover may authorize again on the same connection with another address.
+                let mut pac_write = self.prover_address_connections.write().await;
+                if let Some(prover_state) = self.prover_states.read().await.get(&peer_addr) {
+                    let mut prover_state = prover_state.write().await;
+                    let old_address = prover_state.address();
+                    if old_address != address {
+                        info!(
+                            "Prover {} changed address from {} to {}",
+                            peer_addr, old_address, address
+                        );
+                        if let Some(pac) = pac_write.get_mut(&old_address) {
+                            pac.remove(&peer_addr);
+                            if pac.is_empty() {
+                                pac_write.remove(&old_address);
+                            }
+                        }
+                        pac_write.entry(address).or_default().insert(peer_addr);
+                        prover_state.set_address(address);
+                        prover_state.set_tenant(self.tenant_of(&address));
+                    }
+                    prover_state.set_worker_name(worker_name);
+                    return;
+                }
+                drop(pac_write);
+                if self.config.close_duplicate_connections {
+                    if let Some(duplicate) = self.oldest_duplicate_connection(peer_addr, address, &worker_name).await {
+                        info!(
+                            "Prover {} reconnected from {}, closing its previous connection",
+                            duplicate, peer_addr
+                        );
+                        self.kick_prover(duplicate, "Replaced by a new connection".to_string())
+                            .await;
+                    }
+                }
                 self.authenticated_provers
                     .write()
                     .await
                     .insert(peer_addr, sender.clone());
-                self.prover_states
-                    .write()
-                    .await
-                    .insert(peer_addr, ProverState::new(peer_addr, address).into());
+                self.auto_select_pool_speed_config().await;
+                let mut prover_state = ProverState::new(
+                    peer_addr,
+                    address,
+                    worker_name,
+                    self.config.address_label(&address),
+                    self.config.min_prover_difficulty,
+                    self.config.speedometer_config(&address),
+                );
+                prover_state.set_tenant(self.tenant_of(&address));
+                if let Some((score, _)) = self.misbehavior_scores.write().await.remove(&peer_addr.ip()) {
+                    prover_state.penalize(score);
+                }
+                let initial_target = prover_state.current_target();
+                // Hold the address index lock while touching the prover states so a concurrent disconnect
+                // cannot observe one without the other.
                 let mut pac_write = self.prover_address_connections.write().await;
+                self.prover_states.write().await.insert(peer_addr, prover_state.into());
                 if let Some(address) = pac_write.get_mut(&address) {
                     address.insert(peer_addr);
                 } else {
                     pac_write.insert(address, HashSet::from([peer_addr]));
                 }
                 drop(pac_write);
-                if let Err(e) = sender.send(StratumMessage::SetTarget(512)).await {
+                if let Err(e) = sender.send(StratumMessage::SetTarget(initial_target)).await {
                     error!("Error sending initial target to prover: {}", e);
                 }
-                if let Some(epoch_challenge) = self.latest_epoch_challenge.read().await.as_ref() {
-                    let job_id = hex::encode(self.latest_epoch_number.load(Ordering::SeqCst).to_le_bytes());
+                let latest_epoch = self.latest_epoch.load_full();
+                if let Some((_, _, job_fields)) = latest_epoch.as_ref() {
                     if let Err(e) = sender
                         .send(StratumMessage::Notify(
-                            job_id,
-                            hex::encode(epoch_challenge.to_bytes_le().unwrap()),
+                            job_fields.job_id.clone(),
+                            job_fields.epoch_challenge.clone(),
                             None,
                             true,
                         ))
@@ -379,57 +2558,204 @@ impl Server {
                             peer_addr, address, e
                         );
                     }
+                } else {
+                    debug!("Prover {} ({}) waits for the first epoch challenge", peer_addr, address);
+                    self.warming_up_provers.lock().push((peer_addr, sender));
                 }
             }
             ServerMessage::ProverDisconnected(peer_addr) => {
+                let mut pac_write = self.prover_address_connections.write().await;
                 let state = self.prover_states.write().await.remove(&peer_addr);
-                let address = match state {
-                    Some(state) => Some(state.read().await.address()),
-                    None => None,
-                };
-                if address.is_some() {
-                    let mut pac_write = self.prover_address_connections.write().await;
-                    let pac = pac_write.get_mut(&address.unwrap());
-                    if let Some(pac) = pac {
+                let mut aleo_address = None;
+                let mut session = None;
+                if let Some(state) = state {
+                    let state = state.read().await;
+                    let disconnected_at = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or_default();
+                    session = Some(SessionRecord {
+                        label: state.label.clone(),
+                        peer_addr,
+                        connected_at: disconnected_at.saturating_sub(state.connected_at().elapsed().as_secs()),
+                        disconnected_at,
+                        shares_accepted: state.accepted_shares(),
+                        peak_speed_5m: state.peak_speed_5m(),
+                    });
+                    let score = state.misbehavior_score();
+                    if score > 0 {
+                        let mut scores = self.misbehavior_scores.write().await;
+                        let carried = scores.entry(peer_addr.ip()).or_insert((0, Instant::now()));
+                        *carried = (carried.0.max(score), Instant::now());
+                    }
+                    let address = state.address();
+                    if let Some(pac) = pac_write.get_mut(&address) {
                         pac.remove(&peer_addr);
                         if pac.is_empty() {
-                            pac_write.remove(&address.unwrap());
+                            pac_write.remove(&address);
+                        }
+                    }
+                    aleo_address = Some(address);
+                }
+                drop(pac_write);
+                if let (Some(address), Some(session)) = (aleo_address, session) {
+                    let mut sessions = self.sessions.write().await;
+                    let address_sessions = sessions.entry(address).or_default();
+                    if address_sessions.len() >= self.config.max_sessions_per_address {
+                        address_sessions.pop_front();
+                    }
+                    address_sessions.push_back(session);
+                }
+                if self.connected_provers.write().await.remove(&peer_addr) {
+                    self.disconnects_this_hour.fetch_add(1, Ordering::SeqCst);
+                    self.metrics.pool_prover_disconnects.inc();
+                    let subnet = self.connection_subnet(peer_addr.ip());
+                    let mut subnet_connections = self.subnet_connections.lock();
+                    if let Some(connections) = subnet_connections.get_mut(&subnet) {
+                        *connections -= 1;
+                        if *connections == 0 {
+                            subnet_connections.remove(&subnet);
                         }
                     }
                 }
-                self.connected_provers.write().await.remove(&peer_addr);
                 self.authenticated_provers.write().await.remove(&peer_addr);
+                self.auto_select_pool_speed_config().await;
+                self.publish_connection_event("prover_disconnected", peer_addr, aleo_address);
             }
-            ServerMessage::NewEpochChallenge(epoch_challenge, proof_target) => {
-                let latest_epoch = self.latest_epoch_number.load(Ordering::SeqCst);
+            ServerMessage::ValidatorConnected => {
+                if self.lifecycle() == PoolLifecycle::Initializing {
+                    self.set_lifecycle(PoolLifecycle::WaitingForEpochChallenge);
+                }
+            }
+            ServerMessage::SolutionAcknowledged(latency) => {
+                self.metrics
+                    .solution_submit_latency_seconds
+                    .observe(latency.as_secs_f64());
+                if latency > Duration::from_millis(self.config.max_solution_submit_latency_ms) {
+                    warn!(
+                        "Validator took {} ms to acknowledge a solution; consider co-locating the pool and validator",
+                        latency.as_millis()
+                    );
+                }
+            }
+            ServerMessage::NewEpochChallenge(epoch_challenge, proof_target, coinbase_target, height) => {
+                let lifecycle = self.lifecycle();
+                if matches!(lifecycle, PoolLifecycle::Initializing | PoolLifecycle::Suspended) {
+                    warn!(
+                        "Ignoring epoch challenge {} while the pool is {}",
+                        epoch_challenge.epoch_number(),
+                        lifecycle
+                    );
+                    return;
+                }
+                if let Some(min_proof_target) = self.config.min_network_proof_target {
+                    if proof_target < min_proof_target {
+                        error!(
+                            "Ignoring epoch challenge {} with proof target {} below the network minimum {}",
+                            epoch_challenge.epoch_number(),
+                            proof_target,
+                            min_proof_target
+                        );
+                        return;
+                    }
+                }
+                let job_fields = match ComputedJobFields::new(&epoch_challenge) {
+                    Ok(job_fields) => job_fields,
+                    Err(e) => {
+                        error!("Rejecting epoch challenge {}: {}", epoch_challenge.epoch_number(), e);
+                        return;
+                    }
+                };
+                let latest_epoch = self.latest_epoch_number();
                 if latest_epoch < epoch_challenge.epoch_number()
                     || (epoch_challenge.epoch_number() == 0 && latest_epoch == 0)
                 {
-                    info!("New epoch challenge: {}", epoch_challenge.epoch_number());
-                    self.latest_epoch_number
-                        .store(epoch_challenge.epoch_number(), Ordering::SeqCst);
-                    self.latest_epoch_challenge
-                        .write()
-                        .await
-                        .replace(epoch_challenge.clone());
-                    self.clear_nonce();
+                    let pool_speed_5m = self.pool_state.read().await.speed().await[0];
+                    info!(
+                        "New epoch challenge: {}, proof target: {}, required speed: {} for a {}s block time, expected \
+                         solutions at pool speed: {:.6}/s",
+                        epoch_challenge.epoch_number(),
+                        proof_target,
+                        format_hashrate(proof_target as f64 / self.config.estimated_block_time_secs as f64),
+                        self.config.estimated_block_time_secs,
+                        pool_speed_5m / proof_target.max(1) as f64,
+                    );
+                    let previous_epoch = self.latest_epoch.swap(Arc::new(Some((
+                        epoch_challenge.epoch_number(),
+                        Arc::clone(&epoch_challenge),
+                        job_fields.clone(),
+                    ))));
+                    let tolerance = self.config.stale_accept_tolerance_epochs as usize;
+                    if tolerance == 0 {
+                        self.clear_nonce();
+                    } else {
+                        // Seen nonces are keyed by epoch, so they are kept for the tolerated epochs and only
+                        // cleared by the backup timer.
+                        let mut recent = self.recent_epoch_challenges.write().await;
+                        if let Some((_, challenge, _)) = previous_epoch.as_ref() {
+                            recent.push_back(Arc::clone(challenge));
+                        }
+                        while recent.len() > tolerance {
+                            recent.pop_front();
+                        }
+                    }
                 }
                 if epoch_challenge.epoch_number() < latest_epoch {
                     return;
                 }
+                self.set_lifecycle(PoolLifecycle::Mining {
+                    epoch_number: epoch_challenge.epoch_number(),
+                    challenge_received_at: Instant::now(),
+                });
+                self.contributions.lock().set_block_height(height);
+                self.latest_height.store(height, Ordering::SeqCst);
+                // The coinbase reward scales with the coinbase target, so a change here changes block earnings.
+                let previous_coinbase_target = self.last_known_coinbase_target.swap(coinbase_target, Ordering::SeqCst);
+                if previous_coinbase_target != 0 && previous_coinbase_target != coinbase_target {
+                    warn!(
+                        "Coinbase target changed from {} to {} at height {}",
+                        previous_coinbase_target, coinbase_target, height
+                    );
+                    if let Err(e) = self
+                        .accounting_sender
+                        .send(|sequence| {
+                            AccountingMessage::CoinbaseTargetChanged(
+                                sequence,
+                                previous_coinbase_target,
+                                coinbase_target,
+                                height,
+                            )
+                        })
+                        .await
+                    {
+                        error!("Error sending accounting message: {}", e);
+                    }
+                }
                 info!("Updating target to {}", proof_target);
                 self.latest_proof_target.store(proof_target, Ordering::SeqCst);
+                self.record_difficulty(height, coinbase_target, proof_target).await;
+                let round_reset = self.start_round(height);
+                if round_reset.is_some() {
+                    self.pool_state.read().await.clear_share_ratio_histogram();
+                }
+                let n = proof_target.saturating_mul(self.config.shares_per_block_target);
                 if let Err(e) = self
                     .accounting_sender
-                    .send(AccountingMessage::SetN(proof_target * 5))
+                    .send(|sequence| AccountingMessage::SetN(sequence, n))
                     .await
                 {
                     error!("Error sending accounting message: {}", e);
                 }
                 let global_difficulty_modifier = self.pool_state.write().await.next_global_target_modifier().await;
                 debug!("Global difficulty modifier: {}", global_difficulty_modifier);
-                let job_id = hex::encode(epoch_challenge.epoch_number().to_le_bytes());
-                let epoch_challenge_hex = hex::encode(epoch_challenge.to_bytes_le().unwrap());
+                // Waiting provers are authenticated too, so they get their target before their first job below.
+                let warming_up_provers = std::mem::take(&mut *self.warming_up_provers.lock());
+                if !warming_up_provers.is_empty() {
+                    info!(
+                        "Sending the first epoch challenge to {} waiting provers",
+                        warming_up_provers.len()
+                    );
+                }
                 for (peer_addr, sender) in self.authenticated_provers.read().await.clone().iter() {
                     let states = self.prover_states.read().await;
                     let prover_state = match states.get(peer_addr) {
@@ -448,15 +2774,31 @@ impl Server {
                     if next_difficulty > proof_target {
                         next_difficulty = proof_target;
                     }
+                    let capped_difficulty = cap_share_difficulty(next_difficulty, self.config.min_valid_target);
+                    if capped_difficulty < next_difficulty {
+                        warn!(
+                            "Capping the difficulty of prover {} at {} instead of {} to keep its target achievable",
+                            prover_display, capped_difficulty, next_difficulty
+                        );
+                        next_difficulty = capped_difficulty;
+                    }
                     if current_difficulty != next_difficulty {
                         if let Err(e) = sender.send(StratumMessage::SetTarget(next_difficulty)).await {
                             error!("Error sending difficulty target to prover {}: {}", prover_display, e);
                         }
                     }
+                    if let Some((round_shares, round_duration_secs)) = round_reset {
+                        if let Err(e) = sender
+                            .send(StratumMessage::RoundReset(height, round_shares, round_duration_secs))
+                            .await
+                        {
+                            error!("Error sending round reset to prover {}: {}", prover_display, e);
+                        }
+                    }
                     if let Err(e) = sender
                         .send(StratumMessage::Notify(
-                            job_id.clone(),
-                            epoch_challenge_hex.clone(),
+                            job_fields.job_id.clone(),
+                            job_fields.epoch_challenge.clone(),
                             None,
                             true,
                         ))
@@ -466,177 +2808,505 @@ impl Server {
                     }
                 }
             }
-            ServerMessage::ProverSubmit(id, peer_addr, epoch_number, nonce, commitment, proof) => {
-
-                info!("ServerMessage::ProverSubmit id: {:?}, peer_addr {}, epoch_number  {},  nonce {},  commitment {:?}, proof {:?},", id, peer_addr, epoch_number, nonce, commitment, proof);
-
-
-                let prover_states = self.prover_states.clone();
-                let pool_state = self.pool_state.clone();
-                let authenticated_provers = self.authenticated_provers.clone();
-                let latest_epoch_number = self.latest_epoch_number.load(Ordering::SeqCst);
-                let current_global_difficulty_modifier = self.pool_state.read().await.current_global_target_modifier();
-                let latest_epoch_challenge = self.latest_epoch_challenge.clone();
-                let accounting_sender = self.accounting_sender.clone();
-                let validator_sender = self.validator_sender.clone();
-                let seen_nonce = self.nonce_seen.clone();
-                let global_proof_target = self.latest_proof_target.load(Ordering::SeqCst);
-                let pool_address = self.pool_address;
-                let coinbase_puzzle = self.coinbase_puzzle.clone();
-
-                info!(
-                    // "prover_states: {:?}, pool_state {}, authenticated_provers  {},  
-                    "latest_epoch_number {},  current_global_difficulty_modifier {:?}, 
-                    latest_epoch_challenge {:?}, accounting_sender: {:?}, 
-                    validator_sender , seen_nonce  , 
-                    global_proof_target {},  pool_address {:?}, coinbase_puzzle,", 
-                    // prover_states, 
-                    // pool_state, 
-                    // authenticated_provers, 
-                    latest_epoch_number, 
-                    current_global_difficulty_modifier, 
-                    latest_epoch_challenge,
-
-                    accounting_sender, 
-                    // validator_sender, 
-                    // seen_nonce, 
-                    global_proof_target, 
-                    pool_address, 
-                    // coinbase_puzzle,
-                );
-                
-                task::spawn(async move {
-                    async fn send_result(
-                        sender: &Sender<StratumMessage>,
-                        id: Id,
-                        result: bool,
-                        error_code: Option<ErrorCode>,
-                        desc: Option<String>,
-                    ) {
-                        if result {
+            ServerMessage::ProverSubmit(id, peer_addr, solutions, batched, extra_data, received_at) => {
+                let responses = if batched {
+                    let (result_senders, result_receivers): (Vec<_>, Vec<_>) =
+                        solutions.iter().map(|_| oneshot::channel()).unzip();
+                    let authenticated_provers = self.authenticated_provers.clone();
+                    task::spawn(async move {
+                        // A solution dropped without a result, as when the prover is gone, is reported as null.
+                        let results = join_all(result_receivers)
+                            .await
+                            .into_iter()
+                            .map(|result| result.unwrap_or(Value::Null))
+                            .collect::<Vec<Value>>();
+                        let mut params = Map::new();
+                        params.insert("results".to_string(), Value::Array(results));
+                        let sender = authenticated_provers.read().await.get(&peer_addr).cloned();
+                        if let Some(sender) = sender {
                             if let Err(e) = sender
-                                .send(StratumMessage::Response(id, Some(ResponseParams::Bool(true)), None))
+                                .send(StratumMessage::Response(id, Some(ResponseParams::Object(params)), None))
                                 .await
                             {
-                                error!("Error sending result to prover: {}", e);
+                                error!("Error sending batch result to prover: {}", e);
                             }
-                        } else if let Err(e) = sender
-                            .send(StratumMessage::Response(
-                                id,
-                                None,
-                                Some(Error::with_custom_msg(error_code.unwrap(), desc.unwrap().as_str())),
-                            ))
-                            .await
-                        {
-                            error!("Error sending result to prover: {}", e);
-                        }
-                    }
-                    let provers = authenticated_provers.read().await;
-                    let states = prover_states.read().await;
-                    let sender = match provers.get(&peer_addr) {
-                        Some(sender) => sender,
-                        None => {
-                            error!("Sender not found for peer: {}", peer_addr);
-                            return;
                         }
-                    };
-                    let prover_state = match states.get(&peer_addr) {
-                        Some(state) => state,
-                        None => {
-                            error!("Received solution from unknown prover: {}", peer_addr);
-                            send_result(
-                                sender,
-                                id,
-                                false,
-                                Some(ErrorCode::from_code(24)),
-                                Some("Unknown prover".to_string()),
-                            )
-                            .await;
-                            return;
-                        }
-                    };
-                    let prover_display = format!("{}", prover_state.read().await);
-                    let epoch_challenge = match latest_epoch_challenge.read().await.clone() {
-                        Some(template) => template,
-                        None => {
-                            warn!(
-                                "Received solution from prover {} while no epoch challenge is available",
-                                prover_display
-                            );
-                            send_result(
-                                sender,
-                                id,
-                                false,
-                                Some(ErrorCode::from_code(21)),
-                                Some("No epoch challenge".to_string()),
-                            )
-                            .await;
-                            return;
-                        }
-                    };
-                    if epoch_number != latest_epoch_number {
-                        info!(
-                            "Received stale solution from prover {} with epoch number: {} (expected {})",
-                            prover_display, epoch_number, latest_epoch_number
-                        );
-                        send_result(
-                            sender,
-                            id,
-                            false,
-                            Some(ErrorCode::from_code(21)),
-                            Some("Stale solution".to_string()),
-                        )
-                        .await;
-                        return;
-                    }
-                    if Server::seen_nonce(seen_nonce, nonce) {
-                        warn!("Received duplicate nonce from prover {}", prover_display);
-                        send_result(
-                            sender,
-                            id,
-                            false,
-                            Some(ErrorCode::from_code(22)),
-                            Some("Duplicate nonce".to_string()),
-                        )
+                    });
+                    result_senders.into_iter().map(SubmitResponse::Batch).collect()
+                } else {
+                    vec![SubmitResponse::Single(id)]
+                };
+                for (solution, response) in solutions.into_iter().zip(responses) {
+                    self.process_solution(response, peer_addr, solution, extra_data.clone(), received_at)
                         .await;
+                }
+            }
+            ServerMessage::Exit(flushed) => {
+                self.set_lifecycle(PoolLifecycle::Suspended);
+                for handle in self.accept_tasks.lock().drain(..) {
+                    handle.abort();
+                }
+                info!("Stopped accepting new connections");
+                flush_share_batch(&self.share_batch, &self.accounting_sender).await;
+                let _ = flushed.send(());
+            }
+        }
+    }
+
+    /// Checks a single solution and sends its result to wherever `id` points.
+    async fn process_solution(
+        &self,
+        id: SubmitResponse,
+        peer_addr: SocketAddr,
+        solution: SubmittedSolution,
+        extra_data: Option<Vec<u8>>,
+        received_at: Instant,
+    ) {
+        let (epoch_number, nonce, commitment, proof) = solution;
+
+        info!("ServerMessage::ProverSubmit id: {:?}, peer_addr {}, epoch_number  {},  nonce {},  commitment {:?}, proof {:?},", id, peer_addr, epoch_number, nonce, commitment, proof);
+
+
+        let prover_states = self.prover_states.clone();
+        let pool_state = self.pool_state.clone();
+        let authenticated_provers = self.authenticated_provers.clone();
+        let latest_epoch = self.latest_epoch.load_full();
+        let latest_epoch_number = latest_epoch
+            .as_ref()
+            .as_ref()
+            .map_or(0, |(epoch_number, ..)| *epoch_number);
+        let current_global_difficulty_modifier = self.pool_state.read().await.current_global_target_modifier();
+        let accounting_sender = self.accounting_sender.clone();
+        let share_batch = self.share_batch.clone();
+        let accounting_batch_size = self.config.accounting_batch_size;
+        let solution_queue = self.solution_queue.clone();
+        let solution_queued = self.solution_queued.clone();
+        let seen_nonce = self.nonce_seen.clone();
+        let proof_hash_seen = self.proof_hash_seen.clone();
+        let global_proof_target = self.latest_proof_target.load(Ordering::SeqCst);
+        let pool_address = self.pool_address;
+        let coinbase_puzzle = self.coinbase_puzzle.clone();
+        let extended_share_response = self.config.extended_share_response;
+        let misbehavior = self.config.misbehavior;
+        let banned_ips = self.banned_ips.clone();
+        let security_events = self.security_events.clone();
+        let recent_epoch_challenges = self.recent_epoch_challenges.clone();
+        let stale_accept_tolerance_epochs = self.config.stale_accept_tolerance_epochs;
+        let stale_penalty_factor = self.config.stale_penalty_factor;
+        let pool_fee_address = self.config.pool_fee_address;
+        let min_valid_target = self.config.min_valid_target;
+        let max_shares_per_session = self.config.max_shares_per_session;
+        let address_subscribers = self.address_subscribers.clone();
+        let round_shares = self.round_shares.clone();
+        let round_first_share = self.round_first_share.clone();
+        let round_height = self.round.lock().map_or(0, |(height, _)| height);
+        let testnet_mode = self.config.testnet_mode;
+        let pipeline_samples = self.pipeline_samples.clone();
+        let contributions = self.contributions.clone();
+        let avg_verification_ns = self.avg_verification_ns.clone();
+        let verification_speed = self.verification_speed.clone();
+        let compute_pool = self.compute_pool.clone();
+        let compute_fallback_permits = self.compute_fallback_permits.clone();
+        let verification_timeout = Duration::from_millis(self.config.verification_timeout_ms);
+        let metrics = self.metrics.clone();
+        let ttfs_warn_threshold = Duration::from_secs(self.config.ttfs_warn_threshold_secs);
+        let proof_cache = self.proof_cache.clone();
+        let error_messages = self.error_messages.clone();
+        let lifecycle = self.lifecycle();
+        // Every response path is covered by this going out of scope.
+        self.shares_in_flight.fetch_add(1, Ordering::SeqCst);
+        let latency_recorder = SubmitLatencyRecorder {
+            received_at,
+            histogram: self.pool_state.read().await.submit_latency_us(),
+            in_flight: self.shares_in_flight.clone(),
+        };
+
+        info!(
+            // "prover_states: {:?}, pool_state {}, authenticated_provers  {},  
+            "latest_epoch_number {},  current_global_difficulty_modifier {:?}, 
+            latest_epoch_challenge {:?}, accounting_sender: {:?}, 
+            validator_sender , seen_nonce  , 
+            global_proof_target {},  pool_address {:?}, coinbase_puzzle,", 
+            // prover_states, 
+            // pool_state, 
+            // authenticated_provers, 
+            latest_epoch_number, 
+            current_global_difficulty_modifier, 
+            latest_epoch,
+
+            accounting_sender, 
+            // validator_sender, 
+            // seen_nonce, 
+            global_proof_target, 
+            pool_address, 
+            // coinbase_puzzle,
+        );
+        
+        task::spawn(async move {
+            async fn send_result(
+                sender: &ProverSender,
+                error_messages: &HashMap<u32, String>,
+                id: SubmitResponse,
+                result: bool,
+                error_code: Option<ErrorCode>,
+                desc: Option<String>,
+                details: Option<Map<String, Value>>,
+            ) {
+                // A configured message replaces the default one of every error with its code.
+                let desc = error_code
+                    .and_then(|code| u32::try_from(code.code()).ok())
+                    .and_then(|code| error_messages.get(&code))
+                    .cloned()
+                    .or(desc);
+                let id = match id {
+                    SubmitResponse::Single(id) => id,
+                    SubmitResponse::Batch(result_sender) => {
+                        let result = if result {
+                            json!({
+                                "result": details.map_or(Value::Bool(true), Value::Object),
+                                "error": null,
+                            })
+                        } else {
+                            json!({
+                                "result": null,
+                                "error": Error::<()>::with_custom_msg(error_code.unwrap(), desc.unwrap().as_str()),
+                            })
+                        };
+                        // The batch response is only dropped when nobody is waiting for it anymore.
+                        let _ = result_sender.send(result);
                         return;
                     }
-                    let mut prover_target =
-                        (prover_state.read().await.current_target() as f64 * current_global_difficulty_modifier) as u64;
-                    if prover_target > global_proof_target {
-                        prover_target = global_proof_target;
-                    }
-                    let proof_difficulty = match &commitment.to_bytes_le() {
-                        Ok(bytes) => u64::MAX / sha256d_to_u64(bytes),
-                        Err(e) => {
-                            warn!("Received invalid solution from prover {}: {}", prover_display, e);
-                            send_result(
-                                sender,
-                                id,
-                                false,
-                                Some(ErrorCode::from_code(23)),
-                                Some("Invalid solution".to_string()),
-                            )
-                            .await;
-                            return;
-                        }
+                };
+                if result {
+                    let params = match details {
+                        Some(details) => ResponseParams::Object(details),
+                        None => ResponseParams::Bool(true),
                     };
-                    if proof_difficulty < prover_target {
+                    if let Err(e) = sender.send(StratumMessage::Response(id, Some(params), None)).await {
+                        error!("Error sending result to prover: {}", e);
+                    }
+                } else if let Err(e) = sender
+                    .send(StratumMessage::Response(
+                        id,
+                        None,
+                        Some(Error::with_custom_msg(error_code.unwrap(), desc.unwrap().as_str())),
+                    ))
+                    .await
+                {
+                    error!("Error sending result to prover: {}", e);
+                }
+            }
+            let provers = authenticated_provers.read().await;
+            let states = prover_states.read().await;
+            let sender = match provers.get(&peer_addr) {
+                Some(sender) => sender,
+                None => {
+                    error!("Sender not found for peer: {}", peer_addr);
+                    return;
+                }
+            };
+            let prover_state = match states.get(&peer_addr) {
+                Some(state) => state,
+                None => {
+                    error!("Received solution from unknown prover: {}", peer_addr);
+                    record_rejection(&pool_state, None, RejectionReason::UnknownProver).await;
+                    send_result(
+                        sender,
+                        &error_messages,
+                        id,
+                        false,
+                        Some(ErrorCode::from_code(24)),
+                        Some("Unknown prover".to_string()),
+                        None,
+                    )
+                    .await;
+                    return;
+                }
+            };
+            let prover_display = format!("{}", prover_state.read().await);
+            if !matches!(lifecycle, PoolLifecycle::Mining { .. }) {
+                warn!(
+                    "Received solution from prover {} while the pool is {}",
+                    prover_display, lifecycle
+                );
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(21)),
+                    Some(format!("Pool is {}", lifecycle)),
+                    None,
+                )
+                .await;
+                return;
+            }
+            let (since_last_submission, misbehavior_score) = {
+                let mut prover_state = prover_state.write().await;
+                (prover_state.record_submission(), prover_state.misbehavior_score())
+            };
+            pool_state.read().await.add_submission();
+            if misbehavior_score >= misbehavior.throttle_threshold
+                && since_last_submission.map_or(false, |elapsed| elapsed < THROTTLED_SUBMIT_INTERVAL)
+            {
+                debug!(
+                    "Throttling prover {} with misbehavior score {}",
+                    prover_display, misbehavior_score
+                );
+                record_rejection(&pool_state, Some(prover_state), RejectionReason::RateLimited).await;
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(20)),
+                    Some("Submitting too fast".to_string()),
+                    None,
+                )
+                .await;
+                return;
+            }
+            let mut epoch_challenge = match latest_epoch.as_ref() {
+                Some((_, template, _)) => Arc::clone(template),
+                None => {
+                    warn!(
+                        "Received solution from prover {} while no epoch challenge is available",
+                        prover_display
+                    );
+                    send_result(
+                        sender,
+                        &error_messages,
+                        id,
+                        false,
+                        Some(ErrorCode::from_code(21)),
+                        Some("No epoch challenge".to_string()),
+                        None,
+                    )
+                    .await;
+                    return;
+                }
+            };
+            let mut stale_accepted = false;
+            if epoch_number < latest_epoch_number && latest_epoch_number - epoch_number <= stale_accept_tolerance_epochs
+            {
+                if let Some(previous) = recent_epoch_challenges
+                    .read()
+                    .await
+                    .iter()
+                    .find(|challenge| challenge.epoch_number() == epoch_number)
+                {
+                    debug!(
+                        "Accepting stale solution from prover {} for epoch {} within tolerance",
+                        prover_display, epoch_number
+                    );
+                    epoch_challenge = Arc::clone(previous);
+                    stale_accepted = true;
+                }
+            }
+            if epoch_number != latest_epoch_number && !stale_accepted {
+                if testnet_mode {
+                    debug!(
+                        "Received stale solution from prover {} with epoch number: {} (expected {})",
+                        prover_display, epoch_number, latest_epoch_number
+                    );
+                } else {
+                    info!(
+                        "Received stale solution from prover {} with epoch number: {} (expected {})",
+                        prover_display, epoch_number, latest_epoch_number
+                    );
+                }
+                let prover_state_lock = prover_state.read().await;
+                prover_state_lock.add_stale_share();
+                pool_state.read().await.add_stale_share();
+                let stale_rate = prover_state_lock.stale_rate_percent();
+                if prover_state_lock.total_submissions() >= STALE_RATE_MIN_SUBMISSIONS
+                    && stale_rate > STALE_RATE_WARN_PERCENT
+                {
+                    if testnet_mode {
+                        debug!(
+                            "Prover {} has a stale rate of {:.2}%, its network latency might be too high",
+                            prover_display, stale_rate
+                        );
+                    } else {
                         warn!(
-                            "Received solution with difficulty {} from prover {} (expected {})",
-                            proof_difficulty, prover_display, prover_target
+                            "Prover {} has a stale rate of {:.2}%, its network latency might be too high",
+                            prover_display, stale_rate
                         );
-                        send_result(
-                            sender,
-                            id,
-                            false,
-                            Some(ErrorCode::from_code(23)),
-                            Some("Difficulty target not met".to_string()),
-                        )
-                        .await;
-                        return;
                     }
+                }
+                drop(prover_state_lock);
+                record_rejection(&pool_state, Some(prover_state), RejectionReason::Stale).await;
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(21)),
+                    Some("Stale solution".to_string()),
+                    None,
+                )
+                .await;
+                penalize_prover(
+                    prover_state,
+                    misbehavior.stale_penalty,
+                    &misbehavior,
+                    sender,
+                    &banned_ips,
+                    &security_events,
+                )
+                .await;
+                return;
+            }
+            let mut stage_started = Instant::now();
+            let mut stage_us = [0u64; 4];
+            if Server::seen_nonce(seen_nonce, epoch_number, nonce) {
+                if testnet_mode {
+                    debug!("Received duplicate nonce from prover {}", prover_display);
+                } else {
+                    warn!("Received duplicate nonce from prover {}", prover_display);
+                }
+                record_rejection(&pool_state, Some(prover_state), RejectionReason::DuplicateNonce).await;
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(22)),
+                    Some("Duplicate nonce".to_string()),
+                    None,
+                )
+                .await;
+                penalize_prover(
+                    prover_state,
+                    misbehavior.duplicate_nonce_penalty,
+                    &misbehavior,
+                    sender,
+                    &banned_ips,
+                    &security_events,
+                )
+                .await;
+                return;
+            }
+            // A proof that fails to serialize here fails verification later.
+            if proof_hash(&proof).map_or(false, |hash| !proof_hash_seen.pin().insert(hash)) {
+                warn!("Received duplicate proof from prover {}", prover_display);
+                record_rejection(&pool_state, Some(prover_state), RejectionReason::DuplicateNonce).await;
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(22)),
+                    Some("Duplicate proof".to_string()),
+                    None,
+                )
+                .await;
+                penalize_prover(
+                    prover_state,
+                    misbehavior.duplicate_nonce_penalty,
+                    &misbehavior,
+                    sender,
+                    &banned_ips,
+                    &security_events,
+                )
+                .await;
+                return;
+            }
+            stage_us[0] = stage_elapsed_us(&mut stage_started);
+            let mut prover_target =
+                (prover_state.read().await.current_target() as f64 * current_global_difficulty_modifier) as u64;
+            if prover_target > global_proof_target {
+                prover_target = global_proof_target;
+            }
+            prover_target = cap_share_difficulty(prover_target, min_valid_target);
+            let proof_difficulty = match &commitment.to_bytes_le() {
+                Ok(bytes) => u64::MAX / sha256d_to_u64(bytes),
+                Err(e) => {
+                    warn!("Received invalid solution from prover {}: {}", prover_display, e);
+                    record_rejection(&pool_state, Some(prover_state), RejectionReason::InvalidProof).await;
+                    send_result(
+                        sender,
+                        &error_messages,
+                        id,
+                        false,
+                        Some(ErrorCode::from_code(23)),
+                        Some("Invalid solution".to_string()),
+                        None,
+                    )
+                    .await;
+                    penalize_prover(
+                        prover_state,
+                        misbehavior.invalid_proof_penalty,
+                        &misbehavior,
+                        sender,
+                        &banned_ips,
+                        &security_events,
+                    )
+                    .await;
+                    return;
+                }
+            };
+            pool_state.read().await.add_share_ratio(proof_difficulty, prover_target);
+            if proof_difficulty < prover_target {
+                warn!(
+                    "Received solution with difficulty {} from prover {} (expected {})",
+                    proof_difficulty, prover_display, prover_target
+                );
+                record_rejection(&pool_state, Some(prover_state), RejectionReason::LowDifficulty).await;
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(23)),
+                    Some("Difficulty target not met".to_string()),
+                    None,
+                )
+                .await;
+                penalize_prover(
+                    prover_state,
+                    misbehavior.low_difficulty_penalty,
+                    &misbehavior,
+                    sender,
+                    &banned_ips,
+                    &security_events,
+                )
+                .await;
+                return;
+            }
+            stage_us[1] = stage_elapsed_us(&mut stage_started);
+            // The polynomial is derived from the epoch challenge, so a solution computed for another
+            // epoch can never verify. Reject it before doing the expensive work.
+            if epoch_challenge.epoch_number() != epoch_number {
+                info!(
+                    "Received solution from prover {} for epoch {} while verifying against epoch {}",
+                    prover_display,
+                    epoch_number,
+                    epoch_challenge.epoch_number()
+                );
+                record_rejection(&pool_state, Some(prover_state), RejectionReason::Stale).await;
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(21)),
+                    Some("Stale solution".to_string()),
+                    None,
+                )
+                .await;
+                return;
+            }
+            let cache_key = proof_cache_key(epoch_number, nonce, &commitment, &proof).ok();
+            let cached = cache_key.as_ref().and_then(|key| proof_cache.lock().get(key).copied());
+            let (verified, proof) = match cached {
+                Some(verified) => {
+                    debug!("Found proof from prover {} in the proof cache", prover_display);
+                    (verified, proof)
+                }
+                None => {
                     debug!("Verifying solution from prover {}", prover_display);
+                    let verification_started = Instant::now();
                     let polynomial = match prover_polynomial(&epoch_challenge, pool_address, nonce) {
                         Ok(polynomial) => polynomial,
                         Err(e) => {
@@ -644,12 +3314,24 @@ impl Server {
                                 "Failed to construct prover polynomial from prover {}: {}",
                                 prover_display, e
                             );
+                            record_rejection(&pool_state, Some(prover_state), RejectionReason::InvalidProof).await;
                             send_result(
                                 sender,
+                                &error_messages,
                                 id,
                                 false,
                                 Some(ErrorCode::from_code(20)),
                                 Some("Invalid polynomial".to_string()),
+                                None,
+                            )
+                            .await;
+                            penalize_prover(
+                                prover_state,
+                                misbehavior.invalid_proof_penalty,
+                                &misbehavior,
+                                sender,
+                                &banned_ips,
+                                &security_events,
                             )
                             .await;
                             return;
@@ -659,12 +3341,24 @@ impl Server {
                         Ok(point) => point,
                         Err(e) => {
                             warn!("Failed to hash commitment from prover {}: {}", prover_display, e);
+                            record_rejection(&pool_state, Some(prover_state), RejectionReason::InvalidProof).await;
                             send_result(
                                 sender,
+                                &error_messages,
                                 id,
                                 false,
                                 Some(ErrorCode::from_code(20)),
                                 Some("Invalid commitment".to_string()),
+                                None,
+                            )
+                            .await;
+                            penalize_prover(
+                                prover_state,
+                                misbehavior.invalid_proof_penalty,
+                                &misbehavior,
+                                sender,
+                                &banned_ips,
+                                &security_events,
                             )
                             .await;
                             return;
@@ -673,81 +3367,548 @@ impl Server {
                     let product_eval_at_point =
                         polynomial.evaluate(point) * epoch_challenge.epoch_polynomial().evaluate(point);
 
-              
-                    warn!("KZG10::check, commitment {:?}, point {}, value {:?}, proof {:?}",  commitment, point, product_eval_at_point, proof);
-                    match KZG10::check(
-                        coinbase_puzzle.coinbase_verifying_key(),
-                        &commitment,
-                        point,
-                        product_eval_at_point,
-                        &proof,
-                    ) {
-                        Ok(true) => {
-                            debug!("Verified proof from prover {}", prover_display);
-                        }
-                        _ => {
-                            warn!("Failed to verify proof from prover {}", prover_display);
-                            send_result(
-                                sender,
-                                id,
-                                false,
-                                Some(ErrorCode::from_code(20)),
-                                Some("Invalid proof".to_string()),
-                            )
-                            .await;
-                            return;
-                        }
-                    }
+      
+                    warn!("KZG10::check, commitment {:?}, point {}, value {:?}, proof {:?}",  commitment, point, product_eval_at_point, proof);
+                    // The proof is kept for the solution submission below, as a timed out check may still hold it.
+                    let check_proof = proof.clone();
+                    let verified = run_compute(
+                        &compute_pool,
+                        &compute_fallback_permits,
+                        verification_timeout,
+                        move || {
+                            KZG10::check(
+                                coinbase_puzzle.coinbase_verifying_key(),
+                                &commitment,
+                                point,
+                                product_eval_at_point,
+                                &check_proof,
+                            )
+                        },
+                    )
+                    .await;
+                    metrics.proof_verifications.inc();
+                    let verified = match verified {
+                        Ok((verified, fallback)) => {
+                            if fallback {
+                                metrics.proof_verification_fallbacks.inc();
+                                debug!(
+                                    "Verified proof from prover {} off the compute pool after it timed out",
+                                    prover_display
+                                );
+                            }
+                            verified
+                        }
+                        Err(e) => {
+                            // Not the prover's fault, so no penalty and nothing cached.
+                            warn!("Failed to verify proof from prover {}: {}", prover_display, e);
+                            send_result(
+                                sender,
+                                &error_messages,
+                                id,
+                                false,
+                                Some(ErrorCode::from_code(20)),
+                                Some("Verification failed".to_string()),
+                                None,
+                            )
+                            .await;
+                            return;
+                        }
+                    };
+                    record_verification_time(&avg_verification_ns, verification_started.elapsed());
+                    verification_speed.event(1).await;
+                    let verified = matches!(verified, Ok(true));
+                    if let Some(key) = cache_key {
+                        proof_cache.lock().put(key, verified);
+                    }
+                    (verified, proof)
+                }
+            };
+            if !verified {
+                warn!("Failed to verify proof from prover {}", prover_display);
+                record_rejection(&pool_state, Some(prover_state), RejectionReason::InvalidProof).await;
+                send_result(
+                    sender,
+                    &error_messages,
+                    id,
+                    false,
+                    Some(ErrorCode::from_code(20)),
+                    Some("Invalid proof".to_string()),
+                    None,
+                )
+                .await;
+                penalize_prover(
+                    prover_state,
+                    misbehavior.invalid_proof_penalty,
+                    &misbehavior,
+                    sender,
+                    &banned_ips,
+                    &security_events,
+                )
+                .await;
+                return;
+            }
+            debug!("Verified proof from prover {}", prover_display);
+            stage_us[2] = stage_elapsed_us(&mut stage_started);
+
+            {
+                let mut prover_state = prover_state.write().await;
+                if let Some(time_to_first_share) = prover_state.add_share(prover_target).await {
+                    metrics
+                        .time_to_first_share_seconds
+                        .observe(time_to_first_share.as_secs_f64());
+                    if time_to_first_share > ttfs_warn_threshold {
+                        warn!(
+                            "Prover {} took {} seconds to its first share, initial difficulty may be too high",
+                            prover_display,
+                            time_to_first_share.as_secs()
+                        );
+                    } else {
+                        debug!(
+                            "Prover {} submitted its first share after {} ms",
+                            prover_display,
+                            time_to_first_share.as_millis()
+                        );
+                    }
+                }
+                prover_state.add_share_quality(proof_difficulty, prover_target);
+            }
+            {
+                let mut pool_state = pool_state.write().await;
+                pool_state.add_share(prover_target).await;
+                pool_state.add_expected_solutions(prover_target, global_proof_target);
+                pool_state.add_share_difficulty(proof_difficulty);
+            }
+            let (address, tenant) = {
+                let prover_state = prover_state.read().await;
+                (prover_state.address(), prover_state.tenant().map(str::to_string))
+            };
+            let prover_address = address.to_string();
+            // The commitment identifies the share its extra data belongs to.
+            let extra_data =
+                extra_data.map(|extra_data| (PuzzleCommitment::<Testnet3>::new(commitment).to_string(), extra_data));
+            let mut share_value = proof_difficulty.min(global_proof_target * 2);
+            if stale_accepted {
+                share_value = (share_value as f64 * stale_penalty_factor) as u64;
+            }
+            contributions.lock().add(address, share_value);
+            round_shares.fetch_add(1, Ordering::SeqCst);
+            round_first_share.lock().get_or_insert(address);
+            if accounting_batch_size <= 1 {
+                if let Err(e) = accounting_sender
+                    .send(|sequence| {
+                        AccountingMessage::NewShare(sequence, prover_address, share_value, extra_data, tenant)
+                    })
+                    .await
+                {
+                    error!("Failed to send accounting message: {}", e);
+                }
+            } else {
+                let batch_full = {
+                    let mut share_batch = share_batch.lock();
+                    share_batch.push((prover_address, share_value, extra_data, tenant));
+                    share_batch.len() >= accounting_batch_size
+                };
+                if batch_full {
+                    flush_share_batch(&share_batch, &accounting_sender).await;
+                }
+            }
+            stage_us[3] = stage_elapsed_us(&mut stage_started);
+            {
+                let mut pipeline_samples = pipeline_samples.lock();
+                if pipeline_samples.len() >= PIPELINE_SAMPLE_SIZE {
+                    pipeline_samples.pop_front();
+                }
+                pipeline_samples.push_back(stage_us);
+            }
+            let details = if extended_share_response {
+                let pool_speed = pool_state.read().await.speed().await;
+                let prover_speed = prover_state.read().await.speed();
+                let mut details = Map::new();
+                details.insert("difficulty_accepted".to_string(), Value::from(prover_target));
+                details.insert("pool_hashrate_5m".to_string(), Value::from(pool_speed[0]));
+                details.insert("prover_hashrate_5m".to_string(), Value::from(prover_speed[0]));
+                Some(details)
+            } else {
+                None
+            };
+            send_result(sender, &error_messages, id, true, None, None, details).await;
+            if let Some(subscribers) = address_subscribers.read().await.get(&address) {
+                let prover_state = prover_state.read().await;
+                let update = StatsUpdate::AddressStats {
+                    address: address.to_string(),
+                    worker_name: prover_state.worker_name().to_string(),
+                    difficulty: prover_target,
+                    prover_speed_5m: prover_state.speed()[0],
+                    timestamp: SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or_default(),
+                };
+                // A subscriber that is not keeping up misses updates instead of holding up the share.
+                for subscriber in subscribers {
+                    let _ = subscriber.sender.try_send(update.clone());
+                }
+            }
+            // Reconnecting starts a fresh prover state, which bounds how much one session can accumulate.
+            if let Some(max_shares) = max_shares_per_session {
+                if prover_state.read().await.accepted_shares() >= max_shares {
+                    info!(
+                        "Prover {} reached the session limit of {} shares, disconnecting",
+                        prover_display, max_shares
+                    );
+                    if let Err(e) = sender
+                        .send(StratumMessage::Disconnect("Session limit reached".to_string()))
+                        .await
+                    {
+                        error!("Error sending disconnect to prover {}: {}", prover_display, e);
+                    }
+                }
+            }
+            drop(latency_recorder);
+            drop(provers);
+            drop(states);
+            debug!(
+                "Received valid proof from prover {} with difficulty {} ({:.3}% of network target)",
+                prover_display,
+                proof_difficulty,
+                proof_difficulty as f64 / global_proof_target.max(1) as f64 * 100.0
+            );
+            // TODO: testnet3 rewards
+            // Solutions for a previous epoch can no longer be included in a block.
+            if proof_difficulty >= global_proof_target && !stale_accepted {
+                pool_state.read().await.add_solution();
+                info!(
+                    "Received unconfirmed solution from prover {} with difficulty {} (target {})",
+                    prover_display, proof_difficulty, global_proof_target
+                );
+                // The solution has to be credited to the shares found before it, including this one.
+                flush_share_batch(&share_batch, &accounting_sender).await;
+                // Testnet3 has no block template whose coinbase recipient a prover could change. Provers only send
+                // the nonce, commitment and proof, and the proof was verified against a polynomial built from the
+                // pool address, so a solution always rewards the pool address.
+                let solution = ProverSolution::<Testnet3>::new(
+                    PartialSolution::<Testnet3>::new(pool_address, nonce, commitment),
+                    proof,
+                );
+                if solution_queue.lock().push(solution) {
+                    solution_queued.notify_one();
+                } else {
+                    error!(
+                        "Solution from prover {} is a duplicate or the submission queue is full, dropping it",
+                        prover_display
+                    );
+                }
+                let round_first_share = *round_first_share.lock();
+                if let Err(e) = accounting_sender
+                    .send(|sequence| {
+                        AccountingMessage::NewSolution(
+                            sequence,
+                            PuzzleCommitment::new(commitment),
+                            pool_fee_address.map(|address| address.to_string()),
+                            round_first_share.map(|address| address.to_string()),
+                        )
+                    })
+                    .await
+                {
+                    error!("Failed to send accounting message: {}", e);
+                }
+                let share_ratio_histogram = pool_state.read().await.share_ratio_histogram();
+                if let Err(e) = accounting_sender
+                    .send(|sequence| AccountingMessage::RoundHistogram(sequence, round_height, share_ratio_histogram))
+                    .await
+                {
+                    error!("Failed to send accounting message: {}", e);
+                }
+            }
+        });
+    }
+
+    /// Tells every authenticated prover to reconnect to another pool while this one restarts.
+    pub async fn announce_restart(&self, host: String, port: u16, wait_seconds: u32) {
+        let senders = self
+            .authenticated_provers
+            .read()
+            .await
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        info!("Announcing restart to {} provers", senders.len());
+        let notify = async {
+            for sender in senders {
+                if let Err(e) = sender
+                    .send(StratumMessage::ServerRestart(host.clone(), port, wait_seconds))
+                    .await
+                {
+                    warn!("Error sending restart notification: {}", e);
+                }
+            }
+        };
+        let notify_timeout = Duration::from_secs(self.config.restart_notify_timeout_secs);
+        if tokio::time::timeout(notify_timeout, notify).await.is_err() {
+            warn!("Timed out announcing restart to provers");
+        }
+    }
+
+    /// Sends a disconnect notification to an authenticated prover. The connection closes itself after sending it,
+    /// which triggers the usual disconnect cleanup.
+    pub async fn kick_prover(&self, peer_addr: SocketAddr, reason: String) -> bool {
+        let sender = match self.authenticated_provers.read().await.get(&peer_addr) {
+            Some(sender) => sender.clone(),
+            None => return false,
+        };
+        info!("Kicking prover {}: {}", peer_addr, reason);
+        if let Err(e) = sender.send(StratumMessage::Disconnect(reason)).await {
+            error!("Error sending disconnect to prover {}: {}", peer_addr, e);
+            return false;
+        }
+        true
+    }
+
+    /// Name of the tenant the provers of `address` belong to.
+    fn tenant_of(&self, address: &Address<Testnet3>) -> Option<String> {
+        TenantConfig::find(&self.tenants, &address.to_string()).map(|tenant| tenant.name.clone())
+    }
 
-                    prover_state.write().await.add_share(prover_target).await;
-                    pool_state.write().await.add_share(prover_target).await;
-                    if let Err(e) = accounting_sender
-                        .send(AccountingMessage::NewShare(
-                            prover_state.read().await.address().to_string(),
-                            proof_difficulty.min(global_proof_target * 2),
-                        ))
-                        .await
-                    {
-                        error!("Failed to send accounting message: {}", e);
-                    }
-                    send_result(sender, id, true, None, None).await;
-                    drop(provers);
-                    drop(states);
-                    debug!(
-                        "Received valid proof from prover {} with difficulty {}",
-                        prover_display, proof_difficulty
-                    );
-                    // TODO: testnet3 rewards
-                    if proof_difficulty >= global_proof_target {
-                        info!(
-                            "Received unconfirmed solution from prover {} with difficulty {} (target {})",
-                            prover_display, proof_difficulty, global_proof_target
+    /// The longest connected prover with the same address and worker name as `peer_addr` and the same IP,
+    /// which is most likely a session the miner lost track of. Rigs behind one IP that all use the default
+    /// worker name are told apart by nothing else, so they are never duplicates.
+    async fn oldest_duplicate_connection(
+        &self,
+        peer_addr: SocketAddr,
+        address: Address<Testnet3>,
+        worker_name: &str,
+    ) -> Option<SocketAddr> {
+        if worker_name == DEFAULT_WORKER_NAME {
+            return None;
+        }
+        let peers = self.prover_address_connections.read().await.get(&address).cloned()?;
+        let states = self.prover_states.read().await;
+        let mut oldest: Option<(SocketAddr, Instant)> = None;
+        for peer in peers {
+            if peer == peer_addr || peer.ip() != peer_addr.ip() {
+                continue;
+            }
+            if let Some(state) = states.get(&peer) {
+                let state = state.read().await;
+                if state.worker_name() == worker_name
+                    && oldest.map_or(true, |(_, connected_at)| state.connected_at() < connected_at)
+                {
+                    oldest = Some((peer, state.connected_at()));
+                }
+            }
+        }
+        oldest.map(|(peer, _)| peer)
+    }
+
+    /// Moves the pool share speed to the window suited to the number of authenticated provers.
+    async fn auto_select_pool_speed_config(&self) {
+        let prover_count = self.authenticated_provers.read().await.len();
+        if let Some(speed_config) = self.pool_state.write().await.auto_select_speed_config(prover_count) {
+            info!(
+                "Pool share speed window set to {} seconds for {} provers",
+                speed_config.target_window_secs, prover_count
+            );
+        }
+    }
+
+    /// Updates the backpressure state of every prover and disconnects those whose message channel has been full
+    /// for longer than the backpressure timeout.
+    async fn check_backpressure(&self) {
+        let provers = self.authenticated_provers.read().await.clone();
+        let timeout = Duration::from_secs(self.config.backpressure_timeout_secs);
+        let states = self.prover_states.read().await;
+        for (peer_addr, sender) in provers.iter() {
+            if let Some(prover_state) = states.get(peer_addr) {
+                if let Some(full_for) = prover_state.write().await.record_channel_fill(sender.fill_ratio()) {
+                    // The connection task reports the disconnect once it has closed.
+                    if full_for > timeout && sender.close() {
+                        warn!(
+                            "Message channel of prover {} has been full for over {:?}, disconnecting",
+                            peer_addr, timeout
                         );
-                        // TODO: dummy operator
-                        if let Err(e) = validator_sender
-                            .send(SnarkOSMessage::UnconfirmedSolution(UnconfirmedSolution {
-                                puzzle_commitment: PuzzleCommitment::new(commitment),
-                                solution: Data::Object(ProverSolution::<Testnet3>::new(
-                                    PartialSolution::<Testnet3>::new(pool_address, nonce, commitment),
-                                    proof,
-                                )),
-                            }))
-                            .await
-                        {
-                            error!("Failed to report unconfirmed block to operator: {}", e);
-                        }
-                        if let Err(e) = {
-                            accounting_sender
-                                .send(AccountingMessage::NewSolution(PuzzleCommitment::new(commitment)))
-                                .await
-                        } {
-                            error!("Failed to send accounting message: {}", e);
-                        }
                     }
+                }
+            }
+        }
+    }
+
+    async fn sample_metrics(&self) {
+        let metrics = &self.metrics;
+        {
+            let pool_state = self.pool_state.read().await;
+            for (window, speed) in ["5m", "15m", "30m", "1h"].iter().zip(pool_state.speed().await) {
+                metrics.pool_hashrate.with_label_values(&[window]).set(speed);
+            }
+            Metrics::catch_up(&metrics.pool_solutions_found, pool_state.solutions_found());
+            Metrics::catch_up(&metrics.pool_submissions, pool_state.total_submissions());
+            Metrics::catch_up(&metrics.pool_rejected_submissions, pool_state.rejected_submissions());
+            metrics
+                .pool_global_difficulty_modifier
+                .set(pool_state.current_global_target_modifier());
+        }
+        metrics.pool_provers_online.set(self.online_provers().await as i64);
+        // Addresses that went offline since the last sample drop out of the series.
+        metrics.address_hashrate.reset();
+        metrics.address_connections.reset();
+        for address in self.online_address_list().await {
+            let label = address.to_string();
+            metrics
+                .address_hashrate
+                .with_label_values(&[&label])
+                .set(self.address_speed(address).await[0]);
+            metrics
+                .address_connections
+                .with_label_values(&[&label])
+                .set(self.address_prover_count(address).await as i64);
+        }
+    }
+
+    async fn prover_stats_rows(&self) -> Vec<ProverStatsRow> {
+        let provers = self
+            .authenticated_provers
+            .read()
+            .await
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        let states = self.prover_states.read().await;
+        let mut rows = Vec::with_capacity(provers.len());
+        for peer_addr in provers {
+            if let Some(prover_state) = states.get(&peer_addr) {
+                let prover_state = prover_state.read().await;
+                let speed = prover_state.speed();
+                rows.push(ProverStatsRow {
+                    address: prover_state.address().to_string(),
+                    peer_addr,
+                    speed_5m: speed[0],
+                    speed_1h: speed[3],
+                    accepted_shares: prover_state.accepted_shares(),
+                    rejected_shares: prover_state.rejected_shares(),
+                    current_difficulty: prover_state.current_target(),
                 });
             }
-            ServerMessage::Exit => {}
         }
+        rows
+    }
+
+    /// Number of provers currently reported as backpressured.
+    pub async fn backpressured_provers(&self) -> u32 {
+        let mut count = 0;
+        for prover_state in self.prover_states.read().await.values() {
+            if prover_state.read().await.backpressured() {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Fixes prover states and address index entries that lost their counterpart. A state of a prover that is
+    /// still connected is indexed again, anything else is removed. Returns the number of fixed entries.
+    async fn reconcile_prover_states(&self) -> usize {
+        let authenticated = self
+            .authenticated_provers
+            .read()
+            .await
+            .keys()
+            .copied()
+            .collect::<HashSet<_>>();
+        let mut reconciled = 0;
+        let mut pac_write = self.prover_address_connections.write().await;
+        let mut states = self.prover_states.write().await;
+        let mut orphaned_states = Vec::new();
+        for (peer_addr, state) in states.iter() {
+            let address = state.read().await.address();
+            if !pac_write.get(&address).map_or(false, |peers| peers.contains(peer_addr)) {
+                orphaned_states.push((*peer_addr, address));
+            }
+        }
+        for (peer_addr, address) in orphaned_states {
+            reconciled += 1;
+            if authenticated.contains(&peer_addr) {
+                warn!("Prover {} ({}) was missing from the address index", peer_addr, address);
+                pac_write.entry(address).or_default().insert(peer_addr);
+            } else {
+                warn!(
+                    "Removing orphaned state of disconnected prover {} ({})",
+                    peer_addr, address
+                );
+                states.remove(&peer_addr);
+            }
+        }
+        pac_write.retain(|address, peers| {
+            peers.retain(|peer_addr| {
+                let orphaned = !states.contains_key(peer_addr);
+                if orphaned {
+                    warn!(
+                        "Removing prover {} without a state from the index of {}",
+                        peer_addr, address
+                    );
+                    reconciled += 1;
+                }
+                !orphaned
+            });
+            !peers.is_empty()
+        });
+        reconciled
+    }
+
+    pub async fn template_age(&self) -> Option<Duration> {
+        match self.lifecycle() {
+            PoolLifecycle::Mining {
+                challenge_received_at, ..
+            } => Some(challenge_received_at.elapsed()),
+            _ => None,
+        }
+    }
+
+    pub fn lifecycle(&self) -> PoolLifecycle {
+        *self.lifecycle.lock()
+    }
+
+    fn set_lifecycle(&self, next: PoolLifecycle) {
+        let previous = std::mem::replace(&mut *self.lifecycle.lock(), next);
+        if previous.name() != next.name() {
+            info!("Pool lifecycle changed from {} to {}", previous, next);
+        }
+    }
+
+    pub async fn is_healthy(&self) -> bool {
+        match self.template_age().await {
+            Some(age) => age.as_secs() <= self.config.stale_template_warn_threshold_secs,
+            None => false,
+        }
+    }
+
+    pub async fn connected_provers(&self) -> u32 {
+        self.connected_provers.read().await.len() as u32
+    }
+
+    pub fn max_provers(&self) -> Option<usize> {
+        self.config.max_provers
+    }
+
+    /// The subnet `ip` is counted under, at the configured prefix length of its address family.
+    fn connection_subnet(&self, ip: IpAddr) -> IpNet {
+        let prefix_len = match ip {
+            IpAddr::V4(_) => self.config.subnet_monitoring_prefix_len,
+            IpAddr::V6(_) => self.config.subnet_monitoring_prefix_len_v6,
+        };
+        IpNet::new(ip, prefix_len)
+            .map(|subnet| subnet.trunc())
+            .unwrap_or_else(|_| IpNet::from(ip))
+    }
+
+    /// Subnets with the most connected provers, busiest first.
+    pub fn top_subnets(&self, limit: usize) -> Vec<(IpNet, u32)> {
+        let mut subnets = self
+            .subnet_connections
+            .lock()
+            .iter()
+            .map(|(subnet, connections)| (*subnet, *connections))
+            .collect::<Vec<_>>();
+        subnets.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        subnets.truncate(limit);
+        subnets
     }
 
     pub async fn online_provers(&self) -> u32 {
@@ -758,8 +3919,131 @@ impl Server {
         self.prover_address_connections.read().await.len() as u32
     }
 
+    pub async fn online_address_list(&self) -> Vec<Address<Testnet3>> {
+        self.prover_address_connections.read().await.keys().copied().collect()
+    }
+
+    pub async fn pool_solutions_found(&self) -> u64 {
+        self.pool_state.read().await.solutions_found()
+    }
+
     pub async fn pool_speed(&self) -> Vec<f64> {
-        self.pool_state.write().await.speed().await
+        self.pool_state.read().await.speed().await
+    }
+
+    /// Returns up to `limit` of the most recent history samples, keeping one sample per `interval` seconds.
+    pub async fn history(&self, interval: u64, limit: usize) -> Vec<HistorySample> {
+        let step = (interval / HISTORY_SAMPLE_INTERVAL.as_secs()).max(1) as usize;
+        let mut samples = self
+            .history
+            .read()
+            .await
+            .iter()
+            .rev()
+            .step_by(step)
+            .take(limit)
+            .copied()
+            .collect::<Vec<_>>();
+        samples.reverse();
+        samples
+    }
+
+    async fn record_difficulty(&self, block_height: u32, coinbase_target: u64, proof_target: u64) {
+        let mut difficulty_history = self.difficulty_history.write().await;
+        if difficulty_history
+            .back()
+            .map_or(false, |sample| sample.block_height >= block_height)
+        {
+            return;
+        }
+        difficulty_history.push_back(DifficultySample {
+            block_height,
+            coinbase_target,
+            proof_target,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        });
+        while difficulty_history.len() > DIFFICULTY_HISTORY_SIZE {
+            difficulty_history.pop_front();
+        }
+    }
+
+    /// Network targets of the last blocks, oldest first.
+    pub async fn difficulty_history(&self) -> Vec<DifficultySample> {
+        self.difficulty_history.read().await.iter().copied().collect()
+    }
+
+    /// Percentage change of the coinbase target from the first block of the last 24 hours to the latest one.
+    pub async fn difficulty_trend_24h(&self) -> Option<f64> {
+        let difficulty_history = self.difficulty_history.read().await;
+        let latest = difficulty_history.back()?;
+        let first = difficulty_history
+            .iter()
+            .find(|sample| sample.timestamp + 86400 >= latest.timestamp)?;
+        if first.block_height == latest.block_height || first.coinbase_target == 0 {
+            return None;
+        }
+        Some((latest.coinbase_target as f64 - first.coinbase_target as f64) / first.coinbase_target as f64 * 100.0)
+    }
+
+    /// Share difficulty per address for the round at `block_height`, or for the current round.
+    pub fn contributions(&self, block_height: Option<u32>) -> Option<RoundContributions> {
+        self.contributions.lock().get(block_height)
+    }
+
+    pub async fn capacity_estimate(&self) -> CapacityEstimate {
+        let avg_verification_ns = self.avg_verification_ns.load(Ordering::SeqCst);
+        let verifications_per_second_per_core = if avg_verification_ns > 0 {
+            1_000_000_000.0 / avg_verification_ns as f64
+        } else {
+            0.0
+        };
+        let current_verification_rate = self.verification_speed.speed().await;
+        CapacityEstimate {
+            avg_verification_ms: avg_verification_ns as f64 / 1_000_000.0,
+            verifications_per_second_per_core,
+            current_verification_rate,
+            estimated_cores_needed: current_verification_rate * avg_verification_ns as f64 / 1_000_000_000.0,
+        }
+    }
+
+    pub async fn difficulty_histogram(&self) -> Vec<DifficultyBucket> {
+        self.pool_state.read().await.difficulty_histogram()
+    }
+
+    pub async fn pool_submit_latency(&self) -> SubmitLatency {
+        self.pool_state.read().await.submit_latency_percentiles()
+    }
+
+    pub async fn pipeline_stats(&self) -> PipelineStats {
+        let samples = self.pipeline_samples.lock().iter().copied().collect::<Vec<_>>();
+        let stage = |index: usize| StageLatency::from_samples(samples.iter().map(|sample| sample[index]).collect());
+        PipelineStats {
+            shares_in_flight: self.shares_in_flight.load(Ordering::SeqCst),
+            nonce_seen_size: self.nonce_seen.len(),
+            prover_states_count: self.prover_states.read().await.len(),
+            accounting_channel_pending_estimate: ACCOUNTING_CHANNEL_CAPACITY
+                .saturating_sub(self.accounting_sender.capacity()),
+            samples: samples.len(),
+            nonce_check_us: stage(0),
+            difficulty_check_us: stage(1),
+            proof_verification_us: stage(2),
+            accounting_send_us: stage(3),
+        }
+    }
+
+    pub async fn pool_stale_rate_percent(&self) -> f64 {
+        self.pool_state.read().await.stale_rate_percent()
+    }
+
+    pub fn address_label(&self, address: &Address<Testnet3>) -> Option<String> {
+        self.config.address_label(address)
+    }
+
+    pub async fn pool_performance_score(&self) -> f64 {
+        self.pool_state.read().await.performance_score()
     }
 
     pub async fn address_prover_count(&self, address: Address<Testnet3>) -> u32 {
@@ -780,10 +4064,10 @@ impl Server {
         }
         for prover_connection in prover_connections.unwrap() {
             if let Some(prover_state) = self.prover_states.read().await.get(prover_connection) {
-                let mut prover_state_lock = prover_state.write().await;
-                prover_state_lock
-                    .speed()
+                prover_state
+                    .read()
                     .await
+                    .speed()
                     .iter()
                     .zip(speed.iter_mut())
                     .for_each(|(s, speed)| {
@@ -793,6 +4077,295 @@ impl Server {
         }
         speed
     }
+
+    pub async fn worker_speed(&self, address: Address<Testnet3>, worker_name: &str) -> Vec<f64> {
+        let mut speed = vec![0.0, 0.0, 0.0, 0.0];
+        if let Some(prover_connections) = self.prover_address_connections.read().await.get(&address) {
+            let states = self.prover_states.read().await;
+            for prover_connection in prover_connections {
+                if let Some(prover_state) = states.get(prover_connection) {
+                    let prover_state = prover_state.read().await;
+                    if prover_state.worker_name() != worker_name {
+                        continue;
+                    }
+                    prover_state
+                        .speed()
+                        .iter()
+                        .zip(speed.iter_mut())
+                        .for_each(|(s, speed)| {
+                            *speed += s;
+                        });
+                }
+            }
+        }
+        speed
+    }
+
+    /// Connection count and combined speed of each worker name in use by the address.
+    pub async fn address_workers(&self, address: Address<Testnet3>) -> Vec<WorkerStats> {
+        let mut connections = BTreeMap::<String, (u32, bool)>::new();
+        if let Some(prover_connections) = self.prover_address_connections.read().await.get(&address) {
+            let states = self.prover_states.read().await;
+            for prover_connection in prover_connections {
+                if let Some(prover_state) = states.get(prover_connection) {
+                    let prover_state = prover_state.read().await;
+                    let worker = connections.entry(prover_state.worker_name().to_string()).or_default();
+                    worker.0 += 1;
+                    worker.1 |= prover_state.backpressured();
+                }
+            }
+        }
+        let label = self.config.address_label(&address);
+        let mut workers = Vec::with_capacity(connections.len());
+        for (worker_name, (connections, backpressured)) in connections {
+            let speed = self.worker_speed(address, &worker_name).await;
+            workers.push(WorkerStats {
+                label: label.clone(),
+                worker_name,
+                connections,
+                speed,
+                backpressured,
+            });
+        }
+        workers
+    }
+
+    /// Up to `limit` finished sessions of the address, most recent first.
+    pub async fn address_sessions(&self, address: Address<Testnet3>, limit: usize) -> Vec<SessionRecord> {
+        match self.sessions.read().await.get(&address) {
+            Some(sessions) => sessions.iter().rev().take(limit).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub async fn address_stale_rate_percent(&self, address: Address<Testnet3>) -> f64 {
+        let mut stale_shares = 0;
+        let mut total_submissions = 0;
+        if let Some(prover_connections) = self.prover_address_connections.read().await.get(&address) {
+            let states = self.prover_states.read().await;
+            for prover_connection in prover_connections {
+                if let Some(prover_state) = states.get(prover_connection) {
+                    let prover_state = prover_state.read().await;
+                    stale_shares += prover_state.stale_shares();
+                    total_submissions += prover_state.total_submissions();
+                }
+            }
+        }
+        stale_rate_percent(stale_shares, total_submissions)
+    }
+
+    pub async fn pool_rejections(&self) -> RejectionStats {
+        self.pool_state.read().await.rejections()
+    }
+
+    pub async fn address_rejections(&self, address: Address<Testnet3>) -> RejectionStats {
+        let mut rejections = RejectionStats::default();
+        if let Some(prover_connections) = self.prover_address_connections.read().await.get(&address) {
+            let states = self.prover_states.read().await;
+            for prover_connection in prover_connections {
+                if let Some(prover_state) = states.get(prover_connection) {
+                    rejections += prover_state.read().await.rejections();
+                }
+            }
+        }
+        rejections
+    }
+
+    pub async fn address_share_quality(&self, address: Address<Testnet3>) -> Option<f64> {
+        let mut qualities = Vec::new();
+        if let Some(prover_connections) = self.prover_address_connections.read().await.get(&address) {
+            let states = self.prover_states.read().await;
+            for prover_connection in prover_connections {
+                if let Some(prover_state) = states.get(prover_connection) {
+                    if let Some(quality) = prover_state.read().await.share_quality() {
+                        qualities.push(quality);
+                    }
+                }
+            }
+        }
+        if qualities.is_empty() {
+            return None;
+        }
+        Some(qualities.iter().sum::<f64>() / qualities.len() as f64)
+    }
+
+    pub async fn address_earnings_forecast(&self, address: Address<Testnet3>) -> EarningsForecast {
+        let address_speed_5m = self.address_speed(address).await[0];
+        let pool_speed_5m = self.pool_speed().await[0];
+        let prover_share = if pool_speed_5m > 0.0 {
+            (address_speed_5m / pool_speed_5m).min(1.0)
+        } else {
+            0.0
+        };
+        let n = self
+            .latest_proof_target
+            .load(Ordering::SeqCst)
+            .saturating_mul(self.config.shares_per_block_target);
+        let blocks_per_day = 86400.0 / self.config.estimated_block_time_secs as f64;
+        let daily_reward_estimate = self
+            .config
+            .expected_block_reward
+            .map(|block_reward| blocks_per_day * block_reward as f64 * prover_share);
+        EarningsForecast {
+            label: self.config.address_label(&address),
+            prover_share,
+            expected_credit_per_block: prover_share * n as f64,
+            blocks_per_day,
+            hourly_reward_estimate: daily_reward_estimate.map(|daily| daily / 24.0),
+            daily_reward_estimate,
+        }
+    }
+
+    /// Whether `token` is the admin token, or a rotated out one still in its grace period. Always true if no
+    /// admin token is configured.
+    pub fn admin_token_valid(&self, token: Option<&str>) -> bool {
+        let admin_token = self.admin_token.load();
+        let admin_token = match admin_token.as_deref() {
+            Some(admin_token) => admin_token,
+            None => return true,
+        };
+        let token = match token {
+            Some(token) => token,
+            None => return false,
+        };
+        if tokens_equal(token, admin_token) {
+            return true;
+        }
+        matches!(
+            &*self.previous_admin_token.lock(),
+            Some((previous, expires_at)) if tokens_equal(token, previous) && Instant::now() < *expires_at
+        )
+    }
+
+    /// Replaces the admin token with a random 256-bit one and returns it, keeping the old token valid for the
+    /// configured grace period. Returns `None` if no admin token is configured.
+    pub fn rotate_admin_token(&self) -> Option<String> {
+        if self.admin_token.load().is_none() {
+            return None;
+        }
+        let token = hex::encode(rand::random::<[u8; 32]>());
+        let previous = self.admin_token.swap(Arc::new(Some(token.clone())));
+        if let Some(previous) = &*previous {
+            let grace = Duration::from_secs(self.config.token_rotation_grace_secs);
+            *self.previous_admin_token.lock() = Some((previous.clone(), Instant::now() + grace));
+        }
+        info!("Admin token rotated");
+        Some(token)
+    }
+
+    /// Bans, failed authentications and provers close to being banned.
+    pub async fn security_audit(&self) -> SecurityAudit {
+        let ban_threshold = self.config.misbehavior.ban_threshold;
+        let mut high_misbehavior_provers = Vec::new();
+        for state in self.prover_states.read().await.values() {
+            let state = state.read().await;
+            let misbehavior_score = state.misbehavior_score();
+            if misbehavior_score as f64 >= ban_threshold as f64 * 0.8 {
+                high_misbehavior_provers.push(MisbehavingProver {
+                    peer_addr: state.peer_addr,
+                    address: state.address().to_string(),
+                    misbehavior_score,
+                });
+            }
+        }
+        high_misbehavior_provers.sort_by(|a, b| b.misbehavior_score.cmp(&a.misbehavior_score));
+        SecurityAudit {
+            banned_ips: self
+                .banned_ips
+                .read()
+                .await
+                .values()
+                .filter(|until| **until > Instant::now())
+                .count(),
+            auth_failures_per_minute: self.security_events.auth_failures_per_minute().await,
+            high_misbehavior_provers,
+            seconds_since_last_event: self.security_events.since_last_event().map(|elapsed| elapsed.as_secs()),
+        }
+    }
+
+    pub async fn farm_stats(&self, subnet: IpNet) -> FarmStats {
+        let mut stats = FarmStats {
+            workers: 0,
+            speed: vec![0.0, 0.0, 0.0, 0.0],
+            accepted_shares: 0,
+            rejected_shares: 0,
+        };
+        for (peer_addr, prover_state) in self.prover_states.read().await.iter() {
+            if !subnet.contains(&peer_addr.ip()) {
+                continue;
+            }
+            let prover_state_lock = prover_state.read().await;
+            stats.workers += 1;
+            stats.accepted_shares += prover_state_lock.accepted_shares();
+            stats.rejected_shares += prover_state_lock.rejected_shares();
+            prover_state_lock
+                .speed()
+                .iter()
+                .zip(stats.speed.iter_mut())
+                .for_each(|(s, speed)| {
+                    *speed += s;
+                });
+        }
+        stats
+    }
+}
+
+/// Sends all buffered shares to accounting as one message.
+async fn flush_share_batch(share_batch: &Mutex<Vec<ShareRecord>>, accounting_sender: &AccountingSender) {
+    // Take the shares under the sequence lock so batches stay in order.
+    if let Err(e) = accounting_sender
+        .send_if(|sequence| {
+            let shares = std::mem::take(&mut *share_batch.lock());
+            (!shares.is_empty()).then(|| AccountingMessage::NewShares(sequence, shares))
+        })
+        .await
+    {
+        error!("Failed to send accounting message: {}", e);
+    }
+}
+
+fn configure_socket(stream: &TcpStream, options: &TcpOptions) -> std::io::Result<()> {
+    stream.set_nodelay(options.nodelay)?;
+    let keepalive = TcpKeepalive::new().with_time(options.keepalive_idle);
+    #[cfg(target_os = "linux")]
+    let keepalive = keepalive
+        .with_interval(options.keepalive_interval)
+        .with_retries(options.keepalive_retries);
+    SockRef::from(stream).set_tcp_keepalive(&keepalive)
+}
+
+/// Raises a prover's misbehavior score and applies the response matching the new score.
+async fn penalize_prover(
+    prover_state: &RwLock<ProverState>,
+    amount: u32,
+    config: &MisbehaviorConfig,
+    sender: &ProverSender,
+    banned_ips: &RwLock<HashMap<IpAddr, Instant>>,
+    security_events: &SecurityEvents,
+) {
+    let prover_state = prover_state.read().await;
+    let score = prover_state.penalize(amount);
+    let reason = if score >= config.ban_threshold {
+        warn!("Banning prover {} with misbehavior score {}", prover_state, score);
+        banned_ips
+            .write()
+            .await
+            .insert(prover_state.peer_addr.ip(), Instant::now() + config.ban_duration);
+        security_events.record();
+        "Banned for misbehavior"
+    } else if score >= config.disconnect_threshold {
+        warn!("Disconnecting prover {} with misbehavior score {}", prover_state, score);
+        security_events.record();
+        "Disconnected for misbehavior"
+    } else {
+        if score >= config.warn_threshold {
+            warn!("Prover {} has misbehavior score {}", prover_state, score);
+        }
+        return;
+    };
+    if let Err(e) = sender.send(StratumMessage::Disconnect(reason.to_string())).await {
+        error!("Error sending disconnect to prover {}: {}", prover_state, e);
+    }
 }
 
 fn prover_polynomial(
@@ -834,6 +4407,30 @@ fn hash_to_coefficients<F: PrimeField>(input: &[u8], num_coefficients: u32) -> V
         .collect()
 }
 
+/// The proof alone doesn't decide the verification result, so the key also covers what it is checked against.
+fn proof_cache_key(
+    epoch_number: u32,
+    nonce: u64,
+    commitment: &KZGCommitment<<Testnet3 as Environment>::PairingCurve>,
+    proof: &KZGProof<<Testnet3 as Environment>::PairingCurve>,
+) -> anyhow::Result<Bytes> {
+    let mut bytes = Vec::with_capacity(256);
+    bytes.extend_from_slice(&epoch_number.to_le_bytes());
+    bytes.extend_from_slice(&nonce.to_le_bytes());
+    bytes.extend_from_slice(&commitment.to_bytes_le()?);
+    bytes.extend_from_slice(&proof.to_bytes_le()?);
+    Ok(Bytes::from(bytes))
+}
+
+fn proof_hash(proof: &KZGProof<<Testnet3 as Environment>::PairingCurve>) -> anyhow::Result<[u8; 32]> {
+    Ok(Sha256::digest(proof.to_bytes_le()?).into())
+}
+
+/// Compares the SHA-256 digests of two tokens, so the time taken doesn't reveal how much of a guessed token is right.
+fn tokens_equal(a: &str, b: &str) -> bool {
+    Sha256::digest(a.as_bytes()) == Sha256::digest(b.as_bytes())
+}
+
 fn hash_commitment<E: PairingEngine>(commitment: &KZGCommitment<E>) -> anyhow::Result<E::Fr> {
     // Convert the commitment into bytes.
     let mut bytes = Vec::with_capacity(96);
@@ -843,3 +4440,156 @@ fn hash_commitment<E: PairingEngine>(commitment: &KZGCommitment<E>) -> anyhow::R
     // Return the hash of the commitment.
     Ok(E::Fr::from_bytes_le_mod_order(&blake2::Blake2b512::digest(&bytes)))
 }
+
+#[cfg(test)]
+mod tests {
+    use snarkvm::prelude::PrivateKey;
+
+    use super::*;
+
+    fn prover_state(min_target: u64) -> ProverState {
+        let private_key = PrivateKey::<Testnet3>::new(&mut rand::thread_rng()).unwrap();
+        let address = Address::try_from(&private_key).unwrap();
+        ProverState::new(
+            "127.0.0.1:4040".parse().unwrap(),
+            address,
+            "default".to_string(),
+            None,
+            min_target,
+            SpeedometerConfig::default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn initial_target_respects_floor() {
+        assert_eq!(prover_state(1).current_target(), 512);
+        assert_eq!(prover_state(1000).current_target(), 1000);
+    }
+
+    #[tokio::test]
+    async fn target_increases_after_shares() {
+        let mut state = prover_state(1);
+        for _ in 0..60 {
+            state.add_share(1000).await;
+        }
+        // 60 shares of 1000 over the default 2 minute target window, times 20
+        assert_eq!(state.next_target().await, 10000);
+    }
+
+    #[tokio::test]
+    async fn target_is_clamped_to_min_target() {
+        let mut state = prover_state(1000);
+        state.add_share(1).await;
+        assert_eq!(state.next_target, 1000);
+        assert_eq!(state.next_target().await, 1000);
+    }
+
+    #[tokio::test]
+    async fn small_changes_are_damped() {
+        let mut state = prover_state(1);
+        state.current_target = 10000;
+        state.next_target = 10500;
+        assert_eq!(state.next_target().await, 10000);
+        state.next_target = 9500;
+        assert_eq!(state.next_target().await, 10000);
+        state.next_target = 11500;
+        assert_eq!(state.next_target().await, 11500);
+    }
+
+    #[tokio::test]
+    async fn add_share_does_not_change_current_target() {
+        let mut state = prover_state(1);
+        for _ in 0..60 {
+            state.add_share(1000).await;
+        }
+        assert_eq!(state.current_target(), 512);
+        state.next_target().await;
+        assert_eq!(state.current_target(), 10000);
+    }
+
+    #[tokio::test]
+    async fn speed_is_weighted_by_share_value() {
+        let state = prover_state(1);
+        state.target_speed.event(100).await;
+        state.target_speed.event(500).await;
+        assert_eq!(state.target_speed.speed().await, 600.0 / 120.0);
+    }
+
+    #[tokio::test]
+    async fn speed_is_cached_on_share() {
+        let mut state = prover_state(1);
+        assert_eq!(state.speed(), vec![0.0; 4]);
+        state.add_share(1200).await;
+        assert_eq!(state.speed()[0], 1200.0 / 300.0);
+    }
+
+    #[tokio::test]
+    async fn time_to_first_share_is_reported_once() {
+        let mut state = prover_state(1);
+        assert!(state.add_share(1000).await.is_some());
+        assert!(state.add_share(1000).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn display_includes_label() {
+        let mut state = prover_state(1);
+        assert!(!state.to_string().contains('['));
+        state.label = Some("Farm-A Rack 3".to_string());
+        assert!(state.to_string().starts_with("127.0.0.1:4040 (Farm-A Rack 3) [aleo1"));
+    }
+
+    #[tokio::test]
+    async fn fallback_lowers_target_until_limit() {
+        let mut state = prover_state(100);
+        state.current_target = 1000;
+        state.last_share = Instant::now() - Duration::from_secs(120);
+        let idle = Duration::from_secs(60);
+        assert_eq!(state.fallback_target(idle, 0.5, 2), Some(500));
+        // not again until another idle period has passed
+        assert_eq!(state.fallback_target(idle, 0.5, 2), None);
+        state.last_fallback = Some(Instant::now() - Duration::from_secs(120));
+        assert_eq!(state.fallback_target(idle, 0.5, 2), Some(250));
+        state.last_fallback = Some(Instant::now() - Duration::from_secs(120));
+        assert_eq!(state.fallback_target(idle, 0.5, 2), None);
+    }
+
+    #[tokio::test]
+    async fn global_target_modifier_is_only_raised_early() {
+        let mut pool_state = PoolState::new(vec![]);
+        pool_state.next_global_target_modifier = 2.0;
+        assert_eq!(pool_state.raise_global_target_modifier(), Some((1.0, 2.0)));
+        pool_state.next_global_target_modifier = 1.5;
+        assert_eq!(pool_state.raise_global_target_modifier(), None);
+        assert_eq!(pool_state.current_global_target_modifier(), 2.0);
+    }
+
+    #[test]
+    fn speed_window_switches_past_the_boundary() {
+        let mut pool_state = PoolState::new(vec![]);
+        let mut switch = |prover_count| {
+            pool_state
+                .auto_select_speed_config(prover_count)
+                .map(|config| config.target_window_secs)
+        };
+        assert_eq!(switch(10), None);
+        assert_eq!(switch(11), None);
+        assert_eq!(switch(12), Some(180));
+        assert_eq!(switch(9), None);
+        assert_eq!(switch(8), Some(300));
+    }
+
+    #[test]
+    fn share_difficulty_is_capped_at_min_valid_target() {
+        assert_eq!(cap_share_difficulty(1000, 1024), 1000);
+        assert_eq!(cap_share_difficulty(u64::MAX, 1024), u64::MAX / 1024);
+        assert!(u64::MAX / cap_share_difficulty(u64::MAX, 1024) >= 1024);
+    }
+
+    #[test]
+    fn job_fields_reject_wrong_degree() {
+        let challenge = EpochChallenge::<Testnet3>::new(1, Default::default(), COINBASE_PUZZLE_DEGREE).unwrap();
+        assert!(ComputedJobFields::new(&challenge).is_ok());
+        let challenge = EpochChallenge::<Testnet3>::new(1, Default::default(), 15).unwrap();
+        assert!(ComputedJobFields::new(&challenge).is_err());
+    }
+}
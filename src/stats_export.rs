@@ -0,0 +1,84 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+static HEADER: &str =
+    "timestamp,address,peer_addr,speed_5m,speed_1h,accepted_shares,rejected_shares,current_difficulty\n";
+
+/// One active prover at the time of an export.
+pub struct ProverStatsRow {
+    pub address: String,
+    pub peer_addr: SocketAddr,
+    pub speed_5m: f64,
+    pub speed_1h: f64,
+    pub accepted_shares: u64,
+    pub rejected_shares: u64,
+    pub current_difficulty: u64,
+}
+
+/// CSV export of prover stats for deployments without a database.
+/// A new file is started every day, named after the configured path with the date added before the extension.
+/// Each export rewrites the day's file through a temporary file in the same directory that is synced to disk and
+/// renamed over it, so a reader always sees the file as it was either before or after a whole export.
+pub struct ProverStatsExport {
+    path: PathBuf,
+}
+
+impl ProverStatsExport {
+    pub fn init(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+        }
+    }
+
+    fn file_for(&self, now: DateTime<Utc>) -> PathBuf {
+        let stem = self
+            .path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "prover_stats".to_string());
+        let date = now.format("%Y-%m-%d");
+        let file_name = match self.path.extension() {
+            Some(extension) => format!("{}-{}.{}", stem, date, extension.to_string_lossy()),
+            None => format!("{}-{}", stem, date),
+        };
+        self.path.with_file_name(file_name)
+    }
+
+    /// Appends one row per prover to the file of the day.
+    pub fn append(&self, rows: &[ProverStatsRow]) -> io::Result<()> {
+        let now = Utc::now();
+        let path = self.file_for(now);
+        let mut contents = match fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HEADER.as_bytes().to_vec(),
+            Err(e) => return Err(e),
+        };
+        let timestamp = now.timestamp();
+        for row in rows {
+            writeln!(
+                contents,
+                "{},{},{},{},{},{},{},{}",
+                timestamp,
+                row.address,
+                row.peer_addr,
+                row.speed_5m,
+                row.speed_1h,
+                row.accepted_shares,
+                row.rejected_shares,
+                row.current_difficulty
+            )?;
+        }
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        fs::rename(&temporary, &path)
+    }
+}
@@ -1,9 +1,10 @@
 use std::{
+    collections::VecDeque,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use futures_util::sink::SinkExt;
@@ -40,8 +41,19 @@ use tracing::{debug, error, info, trace, warn};
 
 use crate::ServerMessage;
 
+/// How often the validator is pinged, which it answers with a pong
+static PING_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time to wait before reconnecting to the validator
+static RECONNECT_DELAY: Duration = Duration::from_secs(25);
+
+/// Pings kept waiting for their pong, beyond which the oldest are forgotten if the validator stops answering
+static MAX_OUTSTANDING_PINGS: usize = 64;
+
 pub struct Node {
     operator: String,
+    /// Seconds without a message from the validator before the connection is considered dead and reconnected
+    timeout_secs: u64,
     sender: Arc<Sender<SnarkOSMessage>>,
     receiver: Arc<Mutex<Receiver<SnarkOSMessage>>>,
 }
@@ -49,10 +61,11 @@ pub struct Node {
 pub(crate) type SnarkOSMessage = snarkos_node_messages::Message<Testnet3>;
 
 impl Node {
-    pub fn init(operator: String) -> Self {
+    pub fn init(operator: String, timeout_secs: u64) -> Self {
         let (sender, receiver) = mpsc::channel(1024);
         Self {
             operator,
+            timeout_secs,
             sender: Arc::new(sender),
             receiver: Arc::new(Mutex::new(receiver)),
         }
@@ -75,6 +88,8 @@ pub fn start(node: Node, server_sender: Sender<ServerMessage>) {
             .unwrap()
             .header();
         let connected = Arc::new(AtomicBool::new(false));
+        // Leave room for a few pings to go unanswered, so a short timeout doesn't drop a healthy connection.
+        let heartbeat_timeout = Duration::from_secs(node.timeout_secs).max(PING_INTERVAL * 3);
         let peer_sender = sender.clone();
         let peer_sender_ping = sender.clone();
 
@@ -92,7 +107,7 @@ pub fn start(node: Node, server_sender: Sender<ServerMessage>) {
         });
         task::spawn(async move {
             loop {
-                sleep(Duration::from_secs(5)).await;
+                sleep(PING_INTERVAL).await;
                 if connected_ping.load(Ordering::SeqCst) {
                     if let Err(e) = peer_sender_ping
                         .send(SnarkOSMessage::Ping(Ping {
@@ -116,151 +131,205 @@ pub fn start(node: Node, server_sender: Sender<ServerMessage>) {
                 Ok(socket) => match socket {
                     Ok(socket) => {
                         info!("Connected to {}", node.operator);
-                        let mut framed: Framed<TcpStream, MessageCodec<Testnet3>> =
-                            Framed::new(socket, Default::default());
-                        let challenge = SnarkOSMessage::ChallengeRequest(ChallengeRequest {
-                            version: SnarkOSMessage::VERSION,
-                            listener_port: 4140,
-                            node_type: NodeType::Prover,
-                            address: random_account.address(),
-                            nonce: rng.gen(),
-                        });
-                        if let Err(e) = framed.send(challenge).await {
-                            error!("Error sending challenge request: {}", e);
-                        } else {
-                            trace!("Sent challenge request");
-                        }
-                        let receiver = &mut *receiver.lock().await;
-                        loop {
-                            tokio::select! {
-                                Some(message) = receiver.recv() => {
-                                    trace!("Sending {} to validator", message.name());
-                                    if let Err(e) = framed.send(message.clone()).await {
-                                        error!("Error sending {}: {:?}", message.name(), e);
+                        // The session ticks this for every message from the validator, so it stops when the
+                        // connection is half-open or a send to the validator blocks, and it has to be dropped
+                        let (heartbeat_sender, mut heartbeat_receiver) = mpsc::channel(1);
+                        let session = async {
+                            let mut framed: Framed<TcpStream, MessageCodec<Testnet3>> =
+                                Framed::new(socket, Default::default());
+                            let challenge = SnarkOSMessage::ChallengeRequest(ChallengeRequest {
+                                version: SnarkOSMessage::VERSION,
+                                listener_port: 4140,
+                                node_type: NodeType::Prover,
+                                address: random_account.address(),
+                                nonce: rng.gen(),
+                            });
+                            if let Err(e) = framed.send(challenge).await {
+                                error!("Error sending challenge request: {}", e);
+                            } else {
+                                trace!("Sent challenge request");
+                            }
+                            let receiver = &mut *receiver.lock().await;
+                            // Pings in the order they were sent, each with the time its solution was sent if it
+                            // follows one. Pongs come back in the same order.
+                            let mut outstanding_pings: VecDeque<Option<Instant>> = VecDeque::new();
+                            loop {
+                                tokio::select! {
+                                    Some(message) = receiver.recv() => {
+                                        trace!("Sending {} to validator", message.name());
+                                        if let Err(e) = framed.send(message.clone()).await {
+                                            error!("Error sending {}: {:?}", message.name(), e);
+                                        } else if matches!(message, SnarkOSMessage::Ping(..)) {
+                                            record_ping(&mut outstanding_pings, None);
+                                        } else if matches!(message, SnarkOSMessage::UnconfirmedSolution(..)) {
+                                            // Validators don't acknowledge solutions, so the pong to a ping sent right
+                                            // behind one stands in for it: it can only come after the solution was read
+                                            let solution_sent_at = Instant::now();
+                                            let ping = SnarkOSMessage::Ping(Ping {
+                                                version: SnarkOSMessage::VERSION,
+                                                node_type: NodeType::Prover,
+                                                block_locators: None,
+                                            });
+                                            if let Err(e) = framed.send(ping).await {
+                                                error!("Error sending ping: {:?}", e);
+                                            } else {
+                                                record_ping(&mut outstanding_pings, Some(solution_sent_at));
+                                            }
+                                        }
                                     }
-                                }
-                                result = framed.next() => match result {
-                                    Some(Ok(message)) => {
-                                        trace!("Received {} from validator", message.name());
-                                        match message {
-                                            SnarkOSMessage::ChallengeRequest(ChallengeRequest {
-                                                version,
-                                                listener_port: _,
-                                                node_type,
-                                                address: _,
-                                                nonce,
-                                            }) => {
-                                                if version < SnarkOSMessage::VERSION {
-                                                    error!("Peer is running an older version of the protocol");
-                                                    sleep(Duration::from_secs(25)).await;
-                                                    break;
-                                                }
-                                                if node_type != NodeType::Beacon && node_type != NodeType::Validator {
-                                                    error!("Peer is not a beacon or validator");
-                                                    sleep(Duration::from_secs(25)).await;
-                                                    break;
-                                                }
-                                                let response = SnarkOSMessage::ChallengeResponse(ChallengeResponse {
-                                                    genesis_header,
-                                                    signature: Data::Object(random_account.sign_bytes(&nonce.to_le_bytes(), rng).unwrap()),
-                                                });
-                                                if let Err(e) = framed.send(response).await {
-                                                    error!("Error sending challenge response: {:?}", e);
-                                                } else {
-                                                    debug!("Sent challenge response");
+                                    result = framed.next() => match result {
+                                        Some(Ok(message)) => {
+                                            trace!("Received {} from validator", message.name());
+                                            // A full channel already holds a heartbeat the watchdog has yet to read
+                                            let _ = heartbeat_sender.try_send(());
+                                            match message {
+                                                SnarkOSMessage::ChallengeRequest(ChallengeRequest {
+                                                    version,
+                                                    listener_port: _,
+                                                    node_type,
+                                                    address: _,
+                                                    nonce,
+                                                }) => {
+                                                    if version < SnarkOSMessage::VERSION {
+                                                        error!("Peer is running an older version of the protocol");
+                                                        break;
+                                                    }
+                                                    if node_type != NodeType::Beacon && node_type != NodeType::Validator {
+                                                        error!("Peer is not a beacon or validator");
+                                                        break;
+                                                    }
+                                                    let response = SnarkOSMessage::ChallengeResponse(ChallengeResponse {
+                                                        genesis_header,
+                                                        signature: Data::Object(random_account.sign_bytes(&nonce.to_le_bytes(), rng).unwrap()),
+                                                    });
+                                                    if let Err(e) = framed.send(response).await {
+                                                        error!("Error sending challenge response: {:?}", e);
+                                                    } else {
+                                                        debug!("Sent challenge response");
+                                                    }
                                                 }
-                                            }
-                                            SnarkOSMessage::ChallengeResponse(message) => {
-                                                match message.genesis_header == genesis_header {
-                                                    true => {
-                                                        let was_connected = connected.load(Ordering::SeqCst);
-                                                        connected.store(true, Ordering::SeqCst);
-                                                        if !was_connected {
-                                                            if let Err(e) = sender.send(SnarkOSMessage::PuzzleRequest(PuzzleRequest {})).await {
-                                                                error!("Failed to send puzzle request: {}", e);
+                                                SnarkOSMessage::ChallengeResponse(message) => {
+                                                    match message.genesis_header == genesis_header {
+                                                        true => {
+                                                            let was_connected = connected.load(Ordering::SeqCst);
+                                                            connected.store(true, Ordering::SeqCst);
+                                                            if !was_connected {
+                                                                if let Err(e) = server_sender.send(ServerMessage::ValidatorConnected).await {
+                                                                    error!("Error sending validator connected to pool server: {}", e);
+                                                                }
+                                                                if let Err(e) = sender.send(SnarkOSMessage::PuzzleRequest(PuzzleRequest {})).await {
+                                                                    error!("Failed to send puzzle request: {}", e);
+                                                                }
                                                             }
                                                         }
-                                                    }
-                                                    false => {
-                                                        error!("Peer has a different genesis block");
-                                                        sleep(Duration::from_secs(25)).await;
-                                                        break;
+                                                        false => {
+                                                            error!("Peer has a different genesis block");
+                                                            break;
+                                                        }
                                                     }
                                                 }
-                                            }
-                                            SnarkOSMessage::Ping(..) => {
-                                                let pong = SnarkOSMessage::Pong(Pong { is_fork: None });
-                                                if let Err(e) = framed.send(pong).await {
-                                                    error!("Error sending pong: {:?}", e);
-                                                } else {
-                                                    debug!("Sent pong");
+                                                SnarkOSMessage::Ping(..) => {
+                                                    let pong = SnarkOSMessage::Pong(Pong { is_fork: None });
+                                                    if let Err(e) = framed.send(pong).await {
+                                                        error!("Error sending pong: {:?}", e);
+                                                    } else {
+                                                        debug!("Sent pong");
+                                                    }
+                                                    let message = SnarkOSMessage::Ping(Ping {
+                                                        version: SnarkOSMessage::VERSION,
+                                                        node_type: NodeType::Prover,
+                                                        block_locators: None,
+                                                    });
+                                                    if let Err(e) = framed.send(message).await {
+                                                        error!("Error sending ping: {:?}", e);
+                                                    } else {
+                                                        record_ping(&mut outstanding_pings, None);
+                                                        debug!("Sent ping");
+                                                    }
                                                 }
-                                                let message = SnarkOSMessage::Ping(Ping {
-                                                    version: SnarkOSMessage::VERSION,
-                                                    node_type: NodeType::Prover,
-                                                    block_locators: None,
-                                                });
-                                                if let Err(e) = framed.send(message).await {
-                                                    error!("Error sending ping: {:?}", e);
-                                                } else {
-                                                    debug!("Sent ping");
+                                                SnarkOSMessage::Pong(..) => {
+                                                    if let Some(Some(sent_at)) = outstanding_pings.pop_front() {
+                                                        if let Err(e) = server_sender.send(ServerMessage::SolutionAcknowledged(sent_at.elapsed())).await {
+                                                            error!("Error sending solution latency to pool server: {}", e);
+                                                        }
+                                                    }
                                                 }
-                                            }
-                                            SnarkOSMessage::PuzzleResponse(PuzzleResponse {
-                                                epoch_challenge, block_header
-                                            }) => {
-                                                let block_header = match block_header.deserialize().await {
-                                                    Ok(block_header) => block_header,
-                                                    Err(error) => {
-                                                        error!("Error deserializing block header: {:?}", error);
-                                                        connected.store(false, Ordering::SeqCst);
-                                                        sleep(Duration::from_secs(25)).await;
-                                                        break;
+                                                SnarkOSMessage::PuzzleResponse(PuzzleResponse {
+                                                    epoch_challenge, block_header
+                                                }) => {
+                                                    let block_header = match block_header.deserialize().await {
+                                                        Ok(block_header) => block_header,
+                                                        Err(error) => {
+                                                            error!("Error deserializing block header: {:?}", error);
+                                                            connected.store(false, Ordering::SeqCst);
+                                                            break;
+                                                        }
+                                                    };
+                                                    let epoch_number = epoch_challenge.epoch_number();
+                                                    if let Err(e) = server_sender.send(ServerMessage::NewEpochChallenge(
+                                                        Arc::new(epoch_challenge),
+                                                        block_header.proof_target(),
+                                                        block_header.coinbase_target(),
+                                                        block_header.height(),
+                                                    )).await {
+                                                        error!("Error sending new block template to pool server: {}", e);
+                                                    } else {
+                                                        trace!("Sent new epoch challenge {} to pool server", epoch_number);
                                                     }
-                                                };
-                                                let epoch_number = epoch_challenge.epoch_number();
-                                                if let Err(e) = server_sender.send(ServerMessage::NewEpochChallenge(
-                                                    epoch_challenge, block_header.proof_target()
-                                                )).await {
-                                                    error!("Error sending new block template to pool server: {}", e);
-                                                } else {
-                                                    trace!("Sent new epoch challenge {} to pool server", epoch_number);
                                                 }
-                                            }
-                                            SnarkOSMessage::Disconnect(message) => {
-                                                error!("Peer disconnected: {:?}", message.reason);
-                                                connected.store(false, Ordering::SeqCst);
-                                                sleep(Duration::from_secs(25)).await;
-                                                break;
-                                            }
-                                            _ => {
-                                                debug!("Unhandled message: {}", message.name());
+                                                SnarkOSMessage::Disconnect(message) => {
+                                                    error!("Peer disconnected: {:?}", message.reason);
+                                                    connected.store(false, Ordering::SeqCst);
+                                                    break;
+                                                }
+                                                _ => {
+                                                    debug!("Unhandled message: {}", message.name());
+                                                }
                                             }
                                         }
-                                    }
-                                    Some(Err(e)) => {
-                                        warn!("Failed to read the message: {:?}", e);
-                                    }
-                                    None => {
-                                        error!("Disconnected from operator");
-                                        connected.store(false, Ordering::SeqCst);
-                                        sleep(Duration::from_secs(25)).await;
-                                        break;
+                                        Some(Err(e)) => {
+                                            warn!("Failed to read the message: {:?}", e);
+                                        }
+                                        None => {
+                                            error!("Disconnected from operator");
+                                            connected.store(false, Ordering::SeqCst);
+                                            break;
+                                        }
                                     }
                                 }
                             }
+                        };
+                        let watchdog = async {
+                            while let Ok(Some(())) = timeout(heartbeat_timeout, heartbeat_receiver.recv()).await {}
+                        };
+                        tokio::select! {
+                            _ = session => {}
+                            _ = watchdog => {
+                                error!("No message from the validator for {:?}, reconnecting", heartbeat_timeout);
+                                connected.store(false, Ordering::SeqCst);
+                            }
                         }
+                        // Outside the session, so the watchdog doesn't cut the wait short.
+                        sleep(RECONNECT_DELAY).await;
                     }
                     Err(e) => {
                         error!("Failed to connect to operator: {}", e);
-                        sleep(Duration::from_secs(25)).await;
+                        sleep(RECONNECT_DELAY).await;
                     }
                 },
                 Err(_) => {
                     error!("Failed to connect to operator: Timed out");
-                    sleep(Duration::from_secs(25)).await;
+                    sleep(RECONNECT_DELAY).await;
                 }
             }
         }
     });
 }
+
+/// Remembers a ping sent to the validator, with the time of the solution it follows if any.
+fn record_ping(outstanding_pings: &mut VecDeque<Option<Instant>>, solution_sent_at: Option<Instant>) {
+    if outstanding_pings.len() >= MAX_OUTSTANDING_PINGS {
+        outstanding_pings.pop_front();
+    }
+    outstanding_pings.push_back(solution_sent_at);
+}
@@ -5,7 +5,7 @@ use downcast_rs::{impl_downcast, DowncastSync};
 use erased_serde::Serialize as ErasedSerialize;
 use json_rpc_types::{Id, Request, Response, Version};
 use serde::{ser::SerializeSeq, Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 use tokio_util::codec::{AnyDelimiterCodec, Decoder, Encoder};
 
 use crate::message::StratumMessage;
@@ -14,22 +14,38 @@ pub struct StratumCodec {
     codec: AnyDelimiterCodec,
 }
 
-impl Default for StratumCodec {
-    fn default() -> Self {
+impl StratumCodec {
+    /// Codec accepting messages of up to `max_length` bytes, for peers sending batched submissions.
+    pub fn new(max_length: usize) -> Self {
         Self {
-            // Notify is ~400 bytes and submit is ~1750 bytes. 4096 should be enough for all messages
-            // TODO: verify again
-            codec: AnyDelimiterCodec::new_with_max_length(vec![b'\n'], vec![b'\n'], 4096),
+            codec: AnyDelimiterCodec::new_with_max_length(vec![b'\n'], vec![b'\n'], max_length),
         }
     }
 }
 
+impl Default for StratumCodec {
+    fn default() -> Self {
+        // Notify is ~400 bytes and submit is ~1750 bytes. 4096 should be enough for all messages
+        // TODO: verify again
+        Self::new(4096)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 struct NotifyParams(String, String, Option<String>, bool);
 
 #[derive(Serialize, Deserialize)]
 struct SubscribeParams(String, String, Option<String>);
 
+#[derive(Serialize, Deserialize)]
+struct ReconnectParams(String, u16, u32);
+
+#[derive(Serialize, Deserialize)]
+struct RoundResetParams(u32, u64, u64);
+
+#[derive(Serialize, Deserialize)]
+struct SubmitBatchParams(String, Vec<(String, String, String, String)>);
+
 pub trait BoxedType: ErasedSerialize + Send + DowncastSync {}
 erased_serde::serialize_trait_object!(BoxedType);
 impl_downcast!(sync BoxedType);
@@ -41,6 +57,7 @@ impl BoxedType for Option<String> {}
 pub enum ResponseParams {
     Bool(bool),
     Array(Vec<Box<dyn BoxedType>>),
+    Object(Map<String, Value>),
     Null,
 }
 
@@ -58,6 +75,7 @@ impl Serialize for ResponseParams {
                 }
                 seq.end()
             }
+            ResponseParams::Object(o) => o.serialize(serializer),
             ResponseParams::Null => serializer.serialize_none(),
         }
     }
@@ -81,6 +99,7 @@ impl<'de> Deserialize<'de> for ResponseParams {
                 });
                 Ok(ResponseParams::Array(vec))
             }
+            Value::Object(o) => Ok(ResponseParams::Object(o)),
             Value::Null => Ok(ResponseParams::Null),
             _ => Err(serde::de::Error::custom("invalid response params")),
         }
@@ -128,15 +147,59 @@ impl Encoder<StratumMessage> for StratumCodec {
                 };
                 serde_json::to_vec(&request).unwrap_or_default()
             }
-            StratumMessage::Submit(id, worker_name, job_id, nonce, commitment, proof) => {
+            StratumMessage::Submit(id, worker_name, job_id, nonce, commitment, proof, extra_data) => {
+                let mut params = vec![worker_name, job_id, nonce, commitment, proof];
+                params.extend(extra_data);
                 let request = Request {
                     jsonrpc: Version::V2,
                     method: "mining.submit",
-                    params: Some(vec![worker_name, job_id, nonce, commitment, proof]),
+                    params: Some(params),
                     id: Some(id),
                 };
                 serde_json::to_vec(&request).unwrap_or_default()
             }
+            StratumMessage::SubmitBatch(id, worker_name, submissions) => {
+                let request = Request {
+                    jsonrpc: Version::V2,
+                    method: "mining.submit_batch",
+                    params: Some(SubmitBatchParams(worker_name, submissions)),
+                    id: Some(id),
+                };
+                serde_json::to_vec(&request).unwrap_or_default()
+            }
+            StratumMessage::Disconnect(reason) => {
+                let request = Request {
+                    jsonrpc: Version::V2,
+                    method: "mining.disconnect",
+                    params: Some(vec![reason]),
+                    id: None,
+                };
+                serde_json::to_vec(&request).unwrap_or_default()
+            }
+            StratumMessage::ServerRestart(host, port, wait) => {
+                let request = Request {
+                    jsonrpc: Version::V2,
+                    method: "client.reconnect",
+                    params: Some(ReconnectParams(host, port, wait)),
+                    id: None,
+                };
+                serde_json::to_vec(&request).unwrap_or_default()
+            }
+            StratumMessage::RoundReset(block_height, round_shares, round_duration_secs) => {
+                let request = Request {
+                    jsonrpc: Version::V2,
+                    method: "mining.round_reset",
+                    params: Some(RoundResetParams(block_height, round_shares, round_duration_secs)),
+                    id: None,
+                };
+                serde_json::to_vec(&request).unwrap_or_default()
+            }
+            StratumMessage::Malformed(..) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Malformed messages can't be sent",
+                ));
+            }
             StratumMessage::Response(id, result, error) => match error {
                 Some(error) => {
                     let response = Response::<(), ()>::error(Version::V2, error, Some(id));
@@ -192,94 +255,147 @@ impl Decoder for StratumCodec {
         if string.is_none() {
             return Ok(None);
         }
+        // Only framing errors end the stream, a line that doesn't parse is skipped and reported as malformed.
         let bytes = string.unwrap();
-        let json = serde_json::from_slice::<serde_json::Value>(&bytes)
+        Ok(Some(
+            decode_message(&bytes).unwrap_or_else(|e| StratumMessage::Malformed(e.to_string())),
+        ))
+    }
+}
+
+fn decode_message(bytes: &[u8]) -> Result<StratumMessage, io::Error> {
+    let json = serde_json::from_slice::<serde_json::Value>(bytes)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if !json.is_object() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an object"));
+    }
+    let object = json.as_object().unwrap();
+    let result = if object.contains_key("method") {
+        let request = serde_json::from_value::<Request<Vec<Value>>>(json)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-        if !json.is_object() {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not an object"));
-        }
-        let object = json.as_object().unwrap();
-        let result = if object.contains_key("method") {
-            let request = serde_json::from_value::<Request<Vec<Value>>>(json)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-            let id = request.id;
-            let method = request.method.as_str();
-            let params = match request.params {
-                Some(params) => params,
-                None => return Err(io::Error::new(io::ErrorKind::InvalidData, "No params")),
-            };
-            match method {
-                "mining.subscribe" => {
-                    if params.len() != 3 {
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
-                    }
-                    let user_agent = unwrap_str_value(&params[0])?;
-                    let protocol_version = unwrap_str_value(&params[1])?;
-                    let session_id = match &params[2] {
-                        Value::String(s) => Some(s),
-                        Value::Null => None,
-                        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params")),
-                    };
-                    StratumMessage::Subscribe(
-                        id.unwrap_or(Id::Num(0)),
-                        user_agent,
-                        protocol_version,
-                        session_id.cloned(),
-                    )
+        let id = request.id;
+        let method = request.method.as_str();
+        let params = match request.params {
+            Some(params) => params,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidData, "No params")),
+        };
+        match method {
+            "mining.subscribe" => {
+                if params.len() != 3 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                 }
-                "mining.authorize" => {
-                    if params.len() != 2 {
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
-                    }
-                    let worker_name = unwrap_str_value(&params[0])?;
-                    let worker_password = unwrap_str_value(&params[1])?;
-                    StratumMessage::Authorize(id.unwrap_or(Id::Num(0)), worker_name, worker_password)
+                let user_agent = unwrap_str_value(&params[0])?;
+                let protocol_version = unwrap_str_value(&params[1])?;
+                let session_id = match &params[2] {
+                    Value::String(s) => Some(s),
+                    Value::Null => None,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params")),
+                };
+                StratumMessage::Subscribe(
+                    id.unwrap_or(Id::Num(0)),
+                    user_agent,
+                    protocol_version,
+                    session_id.cloned(),
+                )
+            }
+            "mining.authorize" => {
+                if params.len() != 2 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                 }
-                "mining.set_target" => {
-                    if params.len() != 1 {
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
-                    }
-                    let difficulty_target = unwrap_u64_value(&params[0])?;
-                    StratumMessage::SetTarget(difficulty_target)
+                let worker_name = unwrap_str_value(&params[0])?;
+                let worker_password = unwrap_str_value(&params[1])?;
+                StratumMessage::Authorize(id.unwrap_or(Id::Num(0)), worker_name, worker_password)
+            }
+            "mining.set_target" => {
+                if params.len() != 1 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                 }
-                "mining.notify" => {
-                    if params.len() != 4 {
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
-                    }
-                    let job_id = unwrap_str_value(&params[0])?;
-                    let epoch_challenge = unwrap_str_value(&params[1])?;
-                    let address = match &params[2] {
-                        Value::String(s) => Some(s),
-                        Value::Null => None,
-                        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params")),
-                    };
-                    let clean_jobs = unwrap_bool_value(&params[3])?;
-                    StratumMessage::Notify(job_id, epoch_challenge, address.cloned(), clean_jobs)
+                let difficulty_target = unwrap_u64_value(&params[0])?;
+                StratumMessage::SetTarget(difficulty_target)
+            }
+            "mining.notify" => {
+                if params.len() != 4 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                 }
-                "mining.submit" => {
-                    if params.len() != 5 {
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
-                    }
-                    let worker_name = unwrap_str_value(&params[0])?;
-                    let job_id = unwrap_str_value(&params[1])?;
-                    let nonce = unwrap_str_value(&params[2])?;
-                    let commitment = unwrap_str_value(&params[3])?;
-                    let proof = unwrap_str_value(&params[4])?;
-                    StratumMessage::Submit(id.unwrap_or(Id::Num(0)), worker_name, job_id, nonce, commitment, proof)
+                let job_id = unwrap_str_value(&params[0])?;
+                let epoch_challenge = unwrap_str_value(&params[1])?;
+                let address = match &params[2] {
+                    Value::String(s) => Some(s),
+                    Value::Null => None,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params")),
+                };
+                let clean_jobs = unwrap_bool_value(&params[3])?;
+                StratumMessage::Notify(job_id, epoch_challenge, address.cloned(), clean_jobs)
+            }
+            "mining.submit" => {
+                if params.len() != 5 && params.len() != 6 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                 }
-                _ => {
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown method"));
+                let worker_name = unwrap_str_value(&params[0])?;
+                let job_id = unwrap_str_value(&params[1])?;
+                let nonce = unwrap_str_value(&params[2])?;
+                let commitment = unwrap_str_value(&params[3])?;
+                let proof = unwrap_str_value(&params[4])?;
+                let extra_data = params.get(5).map(unwrap_str_value).transpose()?;
+                StratumMessage::Submit(
+                    id.unwrap_or(Id::Num(0)),
+                    worker_name,
+                    job_id,
+                    nonce,
+                    commitment,
+                    proof,
+                    extra_data,
+                )
+            }
+            "mining.submit_batch" => {
+                if params.len() != 2 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
                 }
+                let worker_name = unwrap_str_value(&params[0])?;
+                let submissions = serde_json::from_value::<Vec<(String, String, String, String)>>(params[1].clone())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                StratumMessage::SubmitBatch(id.unwrap_or(Id::Num(0)), worker_name, submissions)
             }
-        } else {
-            let response = serde_json::from_value::<Response<ResponseParams, ()>>(json)
-                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
-            let id = response.id;
-            match response.payload {
-                Ok(payload) => StratumMessage::Response(id.unwrap_or(Id::Num(0)), Some(payload), None),
-                Err(error) => StratumMessage::Response(id.unwrap_or(Id::Num(0)), None, Some(error)),
+            "mining.disconnect" => {
+                if params.len() != 1 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
+                }
+                let reason = unwrap_str_value(&params[0])?;
+                StratumMessage::Disconnect(reason)
             }
-        };
-        Ok(Some(result))
-    }
+            "client.reconnect" => {
+                if params.len() != 3 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
+                }
+                let host = unwrap_str_value(&params[0])?;
+                let port = u16::try_from(unwrap_u64_value(&params[1])?)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid port"))?;
+                let wait = u32::try_from(unwrap_u64_value(&params[2])?)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid wait time"))?;
+                StratumMessage::ServerRestart(host, port, wait)
+            }
+            "mining.round_reset" => {
+                if params.len() != 3 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid params"));
+                }
+                let block_height = u32::try_from(unwrap_u64_value(&params[0])?)
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid block height"))?;
+                let round_shares = unwrap_u64_value(&params[1])?;
+                let round_duration_secs = unwrap_u64_value(&params[2])?;
+                StratumMessage::RoundReset(block_height, round_shares, round_duration_secs)
+            }
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Unknown method"));
+            }
+        }
+    } else {
+        let response = serde_json::from_value::<Response<ResponseParams, ()>>(json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+        let id = response.id;
+        match response.payload {
+            Ok(payload) => StratumMessage::Response(id.unwrap_or(Id::Num(0)), Some(payload), None),
+            Err(error) => StratumMessage::Response(id.unwrap_or(Id::Num(0)), None, Some(error)),
+        }
+    };
+    Ok(result)
 }
@@ -21,11 +21,33 @@ pub enum StratumMessage {
 
     /// Submit shares to the pool.
     /// See protocol specification for details about the fields.
-    /// (id, worker_name, job_id, nonce, commitment, proof)
-    Submit(Id, String, String, String, String, String),
+    /// (id, worker_name, job_id, nonce, commitment, proof, extra_data)
+    Submit(Id, String, String, String, String, String, Option<String>),
+
+    /// Submit several shares to the pool in one message.
+    /// See protocol specification for details about the fields.
+    /// (id, worker_name, [(job_id, nonce, commitment, proof)])
+    SubmitBatch(Id, String, Vec<(String, String, String, String)>),
 
     /// (id, result, error)
     Response(Id, Option<ResponseParams>, Option<Error<()>>),
+
+    /// The pool is about to close the connection.
+    /// (reason)
+    Disconnect(String),
+
+    /// The pool is restarting and the miner should reconnect elsewhere in the meantime.
+    /// (reconnect_host, reconnect_port, wait_before_reconnect_seconds)
+    ServerRestart(String, u16, u32),
+
+    /// A new block started a new round; sent before the `Notify` of the new job.
+    /// (block_height, round_shares, round_duration_secs)
+    RoundReset(u32, u64, u64),
+
+    /// A line that is not a valid stratum message. The decoder yields it instead of an error so the stream
+    /// continues with the next line; it can't be encoded.
+    /// (error)
+    Malformed(String),
 }
 
 impl StratumMessage {
@@ -36,7 +58,12 @@ impl StratumMessage {
             StratumMessage::SetTarget(..) => "mining.set_target",
             StratumMessage::Notify(..) => "mining.notify",
             StratumMessage::Submit(..) => "mining.submit",
+            StratumMessage::SubmitBatch(..) => "mining.submit_batch",
             StratumMessage::Response(..) => "mining.response",
+            StratumMessage::Disconnect(..) => "mining.disconnect",
+            StratumMessage::ServerRestart(..) => "client.reconnect",
+            StratumMessage::RoundReset(..) => "mining.round_reset",
+            StratumMessage::Malformed(..) => "malformed",
         }
     }
 }
@@ -0,0 +1,115 @@
+use std::{net::SocketAddr, ops::AddAssign};
+
+use serde::{Deserialize, Serialize};
+
+/// Response of `GET /stats`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolStats {
+    pub online_addresses: u32,
+    pub online_provers: u32,
+    pub connected_provers: u32,
+    pub max_provers: Option<usize>,
+    pub speed: Vec<f64>,
+    /// `speed` scaled to a readable unit, such as `12.3 MH/s`
+    #[serde(default)]
+    pub speed_display: Vec<String>,
+    pub stale_rate_percent: f64,
+    pub submit_latency_us: SubmitLatency,
+    pub rejections: RejectionStats,
+    /// Weighted combination of luck, share efficiency and speed, updated every minute
+    #[serde(default)]
+    pub performance_score: f64,
+    /// Percentage change of the network coinbase target over the last 24 hours
+    #[serde(default)]
+    pub difficulty_trend_24h: Option<f64>,
+    /// Accepted connections per second over the last minute
+    #[serde(default)]
+    pub connection_rate_1m: f64,
+    /// Accepted connections per second over the last 5 minutes
+    #[serde(default)]
+    pub connection_rate_5m: f64,
+    /// Provers whose message channel has stayed over 80% full
+    #[serde(default)]
+    pub backpressured_provers: u32,
+    /// Days until the current submission rate would use up the nonce space of an epoch
+    #[serde(default)]
+    pub nonce_exhaustion_estimate_days: Option<f64>,
+}
+
+/// Response of `GET /stats/:address`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AddressStats {
+    /// Label configured for the address
+    #[serde(default)]
+    pub label: Option<String>,
+    pub online_provers: u32,
+    pub speed: Vec<f64>,
+    /// `speed` scaled to a readable unit, such as `12.3 MH/s`
+    #[serde(default)]
+    pub speed_display: Vec<String>,
+    pub stale_rate_percent: f64,
+    pub share_quality: Option<f64>,
+    pub rejections: RejectionStats,
+}
+
+/// Time from a submission being read off the connection to its response, in microseconds.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct SubmitLatency {
+    pub samples: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+}
+
+/// Rejected submissions by reason.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct RejectionStats {
+    pub stale: u64,
+    pub duplicate_nonce: u64,
+    pub low_difficulty: u64,
+    pub invalid_proof: u64,
+    pub unknown_prover: u64,
+    pub rate_limited: u64,
+}
+
+impl AddAssign for RejectionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.stale += other.stale;
+        self.duplicate_nonce += other.duplicate_nonce;
+        self.low_difficulty += other.low_difficulty;
+        self.invalid_proof += other.invalid_proof;
+        self.unknown_prover += other.unknown_prover;
+        self.rate_limited += other.rate_limited;
+    }
+}
+
+/// A prover connecting, authenticating or disconnecting, as published on `GET /events/connections`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolEvent {
+    pub event: String,
+    pub peer_addr: SocketAddr,
+    pub aleo_address: Option<String>,
+    pub timestamp: u64,
+}
+
+/// Messages pushed to clients of the `GET /ws/stats` WebSocket.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StatsUpdate {
+    /// A share accepted from one of the subscribed addresses
+    AddressStats {
+        address: String,
+        worker_name: String,
+        difficulty: u64,
+        prover_speed_5m: f64,
+        timestamp: u64,
+    },
+    /// The addresses the connection now receives updates for
+    Subscribed {
+        addresses: Vec<String>,
+    },
+    Error {
+        error: String,
+    },
+}